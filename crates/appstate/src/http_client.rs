@@ -0,0 +1,68 @@
+use anyhow::Context;
+use reqwest::{Certificate, Client, NoProxy};
+use settings::proxy::Proxy;
+use std::time::Duration;
+
+/// Builds the HTTP client for all requests to upstream registries. The client is shared,
+/// such that connections to the upstreams are reused.
+pub fn upstream_client(settings: &Proxy) -> anyhow::Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent(&settings.user_agent)
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs));
+    if settings.timeout_secs > 0 {
+        builder = builder.timeout(Duration::from_secs(settings.timeout_secs));
+    }
+
+    let http_proxy = &settings.http_proxy;
+    if !http_proxy.is_empty() {
+        let proxy = reqwest::Proxy::all(http_proxy)
+            .with_context(|| format!("Invalid HTTP proxy: {http_proxy}"))?
+            .no_proxy(NoProxy::from_string(&settings.no_proxy));
+        builder = builder.proxy(proxy);
+    }
+
+    let ca_cert = &settings.ca_cert;
+    if !ca_cert.is_empty() {
+        let pem = std::fs::read(ca_cert)
+            .with_context(|| format!("Failed to read CA certificates from {ca_cert}"))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA certificates in {ca_cert}"))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    builder
+        .build()
+        .context("Failed to build upstream HTTP client")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_settings_build_client() {
+        assert!(upstream_client(&Proxy::default()).is_ok());
+    }
+
+    #[test]
+    fn invalid_http_proxy_is_rejected() {
+        let settings = Proxy {
+            http_proxy: "http://[invalid".to_string(),
+            ..Proxy::default()
+        };
+
+        assert!(upstream_client(&settings).is_err());
+    }
+
+    #[test]
+    fn missing_ca_cert_is_rejected() {
+        let settings = Proxy {
+            ca_cert: "/does/not/exist.pem".to_string(),
+            ..Proxy::default()
+        };
+
+        assert!(upstream_client(&settings).is_err());
+    }
+}
@@ -1,14 +1,25 @@
+mod http_client;
+mod prefetch_queue;
+mod proxy_policies;
+
 use axum::extract::FromRef;
 use axum_extra::extract::cookie::Key;
-use common::cratesio_prefetch_msg::CratesioPrefetchMsg;
 use db::DbProvider;
-use flume::Sender;
+use reqwest::Client;
+use settings::proxy::Upstream;
 use settings::Settings;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use storage::{
     cratesio_crate_storage::CratesIoCrateStorage, kellnr_crate_storage::KellnrCrateStorage,
+    scrub::Scrubber,
 };
 
+pub use http_client::upstream_client;
+pub use prefetch_queue::{CrateQueueStatus, PrefetchOutcome, PrefetchQueue, PrefetchQueueStatus};
+pub use proxy_policies::ProxyPolicies;
+
 pub type AppState = axum::extract::State<AppStateData>;
 
 // Substates
@@ -17,7 +28,47 @@ pub type SettingsState = axum::extract::State<Arc<Settings>>;
 pub type CrateStorageState = axum::extract::State<Arc<KellnrCrateStorage>>;
 pub type CrateIoStorageState = axum::extract::State<Arc<CratesIoCrateStorage>>;
 pub type SigningKeyState = axum::extract::State<Key>;
-pub type CratesIoPrefetchSenderState = axum::extract::State<Arc<Sender<CratesioPrefetchMsg>>>;
+pub type PrefetchQueueState = axum::extract::State<Arc<PrefetchQueue>>;
+pub type ScrubberState = axum::extract::State<Arc<Scrubber>>;
+pub type UpstreamsState = axum::extract::State<Arc<Upstreams>>;
+pub type ProxyModeState = axum::extract::State<Arc<ProxyMode>>;
+pub type ProxyPoliciesState = axum::extract::State<Arc<ProxyPolicies>>;
+pub type HttpClientState = axum::extract::State<Client>;
+
+/// Proxied registries next to crates.io by name.
+pub type Upstreams = HashMap<String, UpstreamProxy>;
+
+/// A registry proxied next to crates.io. It uses the same machinery as the crates.io
+/// proxy, but with its own database scope, storage and prefetch threads.
+pub struct UpstreamProxy {
+    pub settings: Upstream,
+    pub db: Arc<dyn DbProvider>,
+    pub storage: Arc<CratesIoCrateStorage>,
+    pub prefetch_queue: Arc<PrefetchQueue>,
+}
+
+/// Runtime mode of the crates.io and upstream proxies, which admins can switch
+/// without a restart.
+pub struct ProxyMode {
+    offline: AtomicBool,
+}
+
+impl ProxyMode {
+    pub fn new(offline: bool) -> Self {
+        Self {
+            offline: AtomicBool::new(offline),
+        }
+    }
+
+    /// In offline mode, only cached crates are served and no upstream is contacted.
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::Relaxed);
+    }
+}
 
 #[derive(Clone, FromRef)]
 pub struct AppStateData {
@@ -27,7 +78,13 @@ pub struct AppStateData {
     pub settings: Arc<Settings>,
     pub crate_storage: Arc<KellnrCrateStorage>,
     pub cratesio_storage: Arc<CratesIoCrateStorage>,
-    pub cratesio_prefetch_sender: Arc<Sender<CratesioPrefetchMsg>>,
+    pub cratesio_prefetch_queue: Arc<PrefetchQueue>,
+    pub scrubber: Arc<Scrubber>,
+    pub upstreams: Arc<Upstreams>,
+    pub proxy_mode: Arc<ProxyMode>,
+    pub proxy_policies: Arc<ProxyPolicies>,
+    // shared client for all requests to upstream registries
+    pub http_client: Client,
 }
 
 pub async fn test_state() -> AppStateData {
@@ -36,13 +93,18 @@ pub async fn test_state() -> AppStateData {
     let settings = Arc::new(Settings::default());
     let crate_storage = Arc::new(KellnrCrateStorage::new(&settings).await.unwrap());
     let crateio_storage = Arc::new(CratesIoCrateStorage::new(&settings).await.unwrap());
-    let (cratesio_prefetch_sender, _) = flume::unbounded();
+    let cratesio_prefetch_queue = Arc::new(PrefetchQueue::new(settings.proxy.update_queue_size));
     AppStateData {
         db,
         signing_key,
         settings,
         crate_storage,
         cratesio_storage: crateio_storage,
-        cratesio_prefetch_sender: Arc::new(cratesio_prefetch_sender),
+        cratesio_prefetch_queue,
+        scrubber: Arc::new(Scrubber::new()),
+        upstreams: Arc::new(HashMap::new()),
+        proxy_mode: Arc::new(ProxyMode::new(false)),
+        proxy_policies: Arc::new(ProxyPolicies::new()),
+        http_client: Client::new(),
     }
 }
@@ -0,0 +1,409 @@
+use chrono::{DateTime, Utc};
+use common::cratesio_prefetch_msg::CratesioPrefetchMsg;
+use common::original_name::OriginalName;
+use flume::{Receiver, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Backoff of a crate after a failed update: 1min, 2min, 4min, ... up to 6h.
+const BACKOFF_BASE_SECS: u64 = 60;
+const BACKOFF_MAX_SECS: u64 = 60 * 60 * 6;
+// Consecutive failures after which all updates are paused, e.g. if the upstream is down.
+const ERROR_BUDGET: u32 = 50;
+const ERROR_BUDGET_PAUSE_SECS: u64 = 60 * 10;
+
+/// Result of processing a message of the prefetch queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefetchOutcome {
+    /// The index of the crate was fetched from the upstream or is unchanged.
+    Success,
+    /// The message was not processed, e.g. because the proxy is offline.
+    Skipped,
+    Failed(String),
+}
+
+/// Work queue of the prefetch threads of an upstream. The queue is bounded and each
+/// crate is queued at most once. Crates whose update failed are retried with an
+/// exponential backoff.
+///
+/// The queue also keeps track of when a crate was last checked against the upstream,
+/// such that fresh crates are not updated again and crates that do not exist upstream
+/// are not requested on every index request.
+pub struct PrefetchQueue {
+    sender: Sender<CratesioPrefetchMsg>,
+    receiver: Receiver<CratesioPrefetchMsg>,
+    capacity: usize,
+    index_ttl: Duration,
+    not_found_ttl: Duration,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    queued: HashSet<String>,
+    in_flight: HashSet<String>,
+    crates: HashMap<String, CrateState>,
+    dropped: u64,
+    consecutive_failures: u32,
+    paused_until: Option<Instant>,
+}
+
+#[derive(Default)]
+struct CrateState {
+    failures: u32,
+    retry_after: Option<Instant>,
+    last_success: Option<DateTime<Utc>>,
+    last_checked: Option<Instant>,
+    last_error: Option<String>,
+    not_found_until: Option<Instant>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PrefetchQueueStatus {
+    /// Number of queued messages.
+    pub depth: usize,
+    pub capacity: usize,
+    /// Number of messages that are processed by the prefetch threads.
+    pub in_flight: usize,
+    /// Number of messages that were dropped, as the queue was full.
+    pub dropped: u64,
+    /// Seconds until updates are queued again after the error budget was exhausted.
+    pub paused_secs: Option<u64>,
+    pub crates: Vec<CrateQueueStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrateQueueStatus {
+    pub name: String,
+    pub last_success: Option<String>,
+    /// Number of consecutive failed updates.
+    pub failures: u32,
+    /// Seconds until the next update is queued.
+    pub retry_secs: Option<u64>,
+    pub last_error: Option<String>,
+    /// Seconds until the crate is requested again after the upstream returned a 404.
+    pub not_found_secs: Option<u64>,
+}
+
+impl PrefetchQueue {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = flume::bounded(capacity);
+        Self {
+            sender,
+            receiver,
+            capacity,
+            index_ttl: Duration::ZERO,
+            not_found_ttl: Duration::ZERO,
+            state: Mutex::new(QueueState::default()),
+        }
+    }
+
+    /// Sets how long an index file is served without an update after it was checked
+    /// against the upstream, and how long a crate that was not found upstream is not
+    /// requested again. Both are zero by default.
+    pub fn with_ttl(self, index_ttl: Duration, not_found_ttl: Duration) -> Self {
+        Self {
+            index_ttl,
+            not_found_ttl,
+            ..self
+        }
+    }
+
+    /// Queues a message without waiting. Returns false if the message was dropped,
+    /// because the crate is already queued, fresh, in backoff or the queue is full.
+    pub fn push(&self, msg: CratesioPrefetchMsg) -> bool {
+        let key = Self::key(&msg);
+        let mut state = self.state.lock().unwrap();
+        if !state.accepts(&key, &msg, self.index_ttl) {
+            return false;
+        }
+
+        match self.sender.try_send(msg) {
+            Ok(()) => {
+                state.queued.insert(key);
+                true
+            }
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                state.dropped += 1;
+                false
+            }
+        }
+    }
+
+    /// Queues a message and waits until the queue has space for it. Returns false if
+    /// the message was dropped, because the crate is already queued, fresh or in backoff.
+    pub async fn push_wait(&self, msg: CratesioPrefetchMsg) -> bool {
+        let key = Self::key(&msg);
+        {
+            let mut state = self.state.lock().unwrap();
+            if !state.accepts(&key, &msg, self.index_ttl) {
+                return false;
+            }
+            // Marked before sending, such that the crate is not queued twice while waiting.
+            state.queued.insert(key.clone());
+        }
+
+        if self.sender.send_async(msg).await.is_err() {
+            let mut state = self.state.lock().unwrap();
+            state.queued.remove(&key);
+            state.dropped += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Waits for the next message and marks its crate as in flight.
+    /// [`PrefetchQueue::complete`] has to be called after the message was processed.
+    pub async fn recv(&self) -> Option<CratesioPrefetchMsg> {
+        let msg = self.receiver.recv_async().await.ok()?;
+        let key = Self::key(&msg);
+        let mut state = self.state.lock().unwrap();
+        state.queued.remove(&key);
+        state.in_flight.insert(key);
+        Some(msg)
+    }
+
+    pub fn complete(&self, msg: &CratesioPrefetchMsg, outcome: PrefetchOutcome) {
+        let key = Self::key(msg);
+        let mut state = self.state.lock().unwrap();
+        state.in_flight.remove(&key);
+
+        match outcome {
+            PrefetchOutcome::Skipped => {}
+            PrefetchOutcome::Success => {
+                state.consecutive_failures = 0;
+                let krate = state.crates.entry(key).or_default();
+                krate.failures = 0;
+                krate.retry_after = None;
+                krate.last_success = Some(Utc::now());
+                krate.last_checked = Some(Instant::now());
+                krate.last_error = None;
+                krate.not_found_until = None;
+            }
+            PrefetchOutcome::Failed(error) => {
+                state.consecutive_failures += 1;
+                if state.consecutive_failures >= ERROR_BUDGET {
+                    state.consecutive_failures = 0;
+                    state.paused_until =
+                        Some(Instant::now() + Duration::from_secs(ERROR_BUDGET_PAUSE_SECS));
+                }
+                let krate = state.crates.entry(key).or_default();
+                krate.failures += 1;
+                krate.retry_after = Some(Instant::now() + backoff(krate.failures));
+                krate.last_error = Some(error);
+            }
+        }
+    }
+
+    /// Remembers that the upstream returned a 404 for the crate.
+    pub fn set_not_found(&self, name: &OriginalName) {
+        if self.not_found_ttl.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        // Expired entries are removed, as requests of non-existent crates would grow the map.
+        state
+            .crates
+            .retain(|_, c| c.not_found_until.map_or(true, |u| u > now) || c.last_checked.is_some());
+        state
+            .crates
+            .entry(name.to_normalized().to_string())
+            .or_default()
+            .not_found_until = Some(now + self.not_found_ttl);
+    }
+
+    /// Returns true if the upstream returned a 404 for the crate within the not found TTL.
+    pub fn is_not_found(&self, name: &OriginalName) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .crates
+            .get(name.to_normalized().as_str())
+            .and_then(|c| c.not_found_until)
+            .is_some_and(|u| u > Instant::now())
+    }
+
+    pub fn status(&self) -> PrefetchQueueStatus {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let remaining_secs = |until: Option<Instant>| {
+            until
+                .filter(|u| *u > now)
+                .map(|u| u.duration_since(now).as_secs())
+        };
+
+        let mut crates: Vec<CrateQueueStatus> = state
+            .crates
+            .iter()
+            .map(|(name, krate)| CrateQueueStatus {
+                name: name.clone(),
+                last_success: krate.last_success.map(|d| d.to_rfc3339()),
+                failures: krate.failures,
+                retry_secs: remaining_secs(krate.retry_after),
+                last_error: krate.last_error.clone(),
+                not_found_secs: remaining_secs(krate.not_found_until),
+            })
+            .collect();
+        crates.sort_by(|a, b| a.name.cmp(&b.name));
+
+        PrefetchQueueStatus {
+            depth: self.sender.len(),
+            capacity: self.capacity,
+            in_flight: state.in_flight.len(),
+            dropped: state.dropped,
+            paused_secs: remaining_secs(state.paused_until),
+            crates,
+        }
+    }
+
+    fn key(msg: &CratesioPrefetchMsg) -> String {
+        msg.name().to_normalized().to_string()
+    }
+}
+
+impl QueueState {
+    fn accepts(&self, key: &str, msg: &CratesioPrefetchMsg, index_ttl: Duration) -> bool {
+        // Inserts carry index data that was already fetched, such that they are never skipped.
+        let CratesioPrefetchMsg::Update(_) = msg else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let paused = self.paused_until.is_some_and(|u| u > now);
+        let in_backoff = self
+            .crates
+            .get(key)
+            .and_then(|c| c.retry_after)
+            .is_some_and(|r| r > now);
+        let fresh = self
+            .crates
+            .get(key)
+            .and_then(|c| c.last_checked)
+            .is_some_and(|c| now.duration_since(c) < index_ttl);
+
+        !paused
+            && !in_backoff
+            && !fresh
+            && !self.queued.contains(key)
+            && !self.in_flight.contains(key)
+    }
+}
+
+fn backoff(failures: u32) -> Duration {
+    let secs = BACKOFF_BASE_SECS.saturating_mul(1 << failures.saturating_sub(1).min(16));
+    Duration::from_secs(secs.min(BACKOFF_MAX_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::cratesio_prefetch_msg::{InsertData, UpdateData};
+
+    fn update(name: &str) -> CratesioPrefetchMsg {
+        CratesioPrefetchMsg::Update(UpdateData {
+            name: OriginalName::from_unchecked_str(name.to_string()),
+            etag: None,
+            last_modified: None,
+        })
+    }
+
+    fn insert(name: &str) -> CratesioPrefetchMsg {
+        CratesioPrefetchMsg::Insert(InsertData {
+            name: OriginalName::from_unchecked_str(name.to_string()),
+            etag: None,
+            last_modified: None,
+            data: String::new(),
+        })
+    }
+
+    #[test]
+    fn push_deduplicates_updates() {
+        let queue = PrefetchQueue::new(10);
+
+        assert!(queue.push(update("serde")));
+        assert!(!queue.push(update("Serde")));
+        assert!(queue.push(insert("serde")));
+        assert_eq!(2, queue.status().depth);
+    }
+
+    #[test]
+    fn push_drops_messages_if_full() {
+        let queue = PrefetchQueue::new(1);
+
+        assert!(queue.push(update("serde")));
+        assert!(!queue.push(update("rand")));
+        assert_eq!(1, queue.status().dropped);
+    }
+
+    #[tokio::test]
+    async fn failed_update_is_backed_off() {
+        let queue = PrefetchQueue::new(10);
+        queue.push(update("serde"));
+
+        let msg = queue.recv().await.unwrap();
+        assert!(!queue.push(update("serde")));
+        assert_eq!(1, queue.status().in_flight);
+        queue.complete(&msg, PrefetchOutcome::Failed("timeout".to_string()));
+
+        let status = queue.status();
+        assert!(!queue.push(update("serde")));
+        assert_eq!(0, status.in_flight);
+        assert_eq!(1, status.crates[0].failures);
+        assert_eq!(Some("timeout".to_string()), status.crates[0].last_error);
+        assert!(status.crates[0].retry_secs.is_some());
+    }
+
+    #[tokio::test]
+    async fn successful_update_resets_failures() {
+        let queue = PrefetchQueue::new(10);
+        queue.push(insert("serde"));
+        let msg = queue.recv().await.unwrap();
+        queue.complete(&msg, PrefetchOutcome::Failed("timeout".to_string()));
+        queue.push(insert("serde"));
+        let msg = queue.recv().await.unwrap();
+
+        queue.complete(&msg, PrefetchOutcome::Success);
+
+        let status = queue.status();
+        assert!(queue.push(update("serde")));
+        assert_eq!(0, status.crates[0].failures);
+        assert!(status.crates[0].last_success.is_some());
+        assert_eq!(None, status.crates[0].retry_secs);
+    }
+
+    #[tokio::test]
+    async fn fresh_crates_are_not_updated() {
+        let queue = PrefetchQueue::new(10).with_ttl(Duration::from_secs(60), Duration::ZERO);
+        queue.push(insert("serde"));
+        let msg = queue.recv().await.unwrap();
+
+        queue.complete(&msg, PrefetchOutcome::Success);
+
+        assert!(!queue.push(update("serde")));
+        assert!(queue.push(update("rand")));
+    }
+
+    #[test]
+    fn not_found_crates_are_remembered() {
+        let name = OriginalName::from_unchecked_str("Serde".to_string());
+        let queue = PrefetchQueue::new(10).with_ttl(Duration::ZERO, Duration::from_secs(60));
+        let disabled = PrefetchQueue::new(10);
+
+        queue.set_not_found(&name);
+        disabled.set_not_found(&name);
+
+        assert!(queue.is_not_found(&OriginalName::from_unchecked_str("serde".to_string())));
+        assert!(queue.status().crates[0].not_found_secs.is_some());
+        assert!(!disabled.is_not_found(&name));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(Duration::from_secs(60), backoff(1));
+        assert_eq!(Duration::from_secs(240), backoff(3));
+        assert_eq!(Duration::from_secs(BACKOFF_MAX_SECS), backoff(30));
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::Utc;
+use common::normalized_name::NormalizedName;
+use common::proxy_policy::{PolicyAction, ProxyPolicy};
+use db::error::DbError;
+use db::provider::DbResult;
+use db::DbProvider;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Max. number of approval requests that index requests can add per window.
+const APPROVAL_REQUESTS_PER_WINDOW: u32 = 30;
+const APPROVAL_REQUEST_WINDOW: Duration = Duration::from_secs(60);
+
+/// Compiled proxy policy, shared by all requests until the proxy rules change. Handlers
+/// that change the rules have to call `invalidate`.
+pub struct ProxyPolicies {
+    policy: RwLock<Option<Arc<ProxyPolicy>>>,
+    // Incremented on each invalidation, such that a policy that was loaded while the rules
+    // changed is not cached.
+    generation: AtomicU64,
+    // Start of the current window and the number of approval requests added in it.
+    approval_requests: Mutex<(Instant, u32)>,
+}
+
+impl Default for ProxyPolicies {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProxyPolicies {
+    pub fn new() -> Self {
+        Self {
+            policy: RwLock::new(None),
+            generation: AtomicU64::new(0),
+            approval_requests: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Returns the cached policy, or compiles it from the rules in the database.
+    pub async fn get(
+        &self,
+        db: &Arc<dyn DbProvider>,
+        approval_required: bool,
+    ) -> DbResult<Arc<ProxyPolicy>> {
+        if let Some(policy) = self.policy.read().unwrap().as_ref() {
+            if policy.approval_required() == approval_required {
+                return Ok(policy.clone());
+            }
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let rules = db.get_proxy_rules().await?;
+        let policy = Arc::new(ProxyPolicy::new(&rules, approval_required));
+        let mut cached = self.policy.write().unwrap();
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *cached = Some(policy.clone());
+        }
+        Ok(policy)
+    }
+
+    /// Drops the cached policy, such that the next request loads the changed rules.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *self.policy.write().unwrap() = None;
+    }
+
+    /// Adds a pending rule for the crate, such that an admin can approve it. Returns `false`
+    /// if the crate has a rule already or too many requests were added recently, as any
+    /// index request for an unknown crate can trigger this.
+    pub async fn request_approval(
+        &self,
+        db: &Arc<dyn DbProvider>,
+        name: &NormalizedName,
+    ) -> DbResult<bool> {
+        // Held until the rule is added, such that concurrent requests for the same crate
+        // find the rule of the first one.
+        let mut window = self.approval_requests.lock().await;
+        if window.0.elapsed() >= APPROVAL_REQUEST_WINDOW {
+            *window = (Instant::now(), 0);
+        }
+        if window.1 >= APPROVAL_REQUESTS_PER_WINDOW {
+            return Ok(false);
+        }
+
+        match db
+            .add_proxy_rule(name, "*", PolicyAction::Pending, &Utc::now())
+            .await
+        {
+            Ok(_) => {
+                window.1 += 1;
+                self.invalidate();
+                Ok(true)
+            }
+            Err(DbError::ProxyRuleExists(_, _)) => {
+                self.invalidate();
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::proxy_policy::ProxyRule;
+    use db::mock::MockDb;
+
+    fn rule(pattern: &str) -> ProxyRule {
+        ProxyRule {
+            id: 1,
+            pattern: pattern.to_string(),
+            version_req: "*".to_string(),
+            action: PolicyAction::Deny,
+            created: String::new(),
+        }
+    }
+
+    fn name(name: &str) -> NormalizedName {
+        NormalizedName::from_unchecked_str(name)
+    }
+
+    #[tokio::test]
+    async fn policy_is_cached_until_invalidated() {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_get_proxy_rules()
+            .times(2)
+            .returning(|| Ok(vec![rule("rand")]));
+        let db: Arc<dyn DbProvider> = Arc::new(mock_db);
+        let policies = ProxyPolicies::new();
+
+        let policy = policies.get(&db, false).await.unwrap();
+        policies.get(&db, false).await.unwrap();
+        policies.invalidate();
+        policies.get(&db, false).await.unwrap();
+
+        assert_eq!(PolicyAction::Deny, policy.action(&name("rand"), "1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn approval_requests_are_limited() {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_add_proxy_rule()
+            .times(APPROVAL_REQUESTS_PER_WINDOW as usize)
+            .returning(|pattern, _, _, _| Ok(rule(pattern)));
+        let db: Arc<dyn DbProvider> = Arc::new(mock_db);
+        let policies = ProxyPolicies::new();
+
+        for i in 0..APPROVAL_REQUESTS_PER_WINDOW {
+            let added = policies
+                .request_approval(&db, &name(&format!("crate{i}")))
+                .await
+                .unwrap();
+            assert!(added);
+        }
+
+        assert!(!policies
+            .request_approval(&db, &name("other"))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn approval_request_for_existing_rule_is_skipped() {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_add_proxy_rule()
+            .returning(|pattern, version_req, _, _| {
+                Err(DbError::ProxyRuleExists(
+                    pattern.to_string(),
+                    version_req.to_string(),
+                ))
+            });
+        let db: Arc<dyn DbProvider> = Arc::new(mock_db);
+        let policies = ProxyPolicies::new();
+
+        assert!(!policies.request_approval(&db, &name("rand")).await.unwrap());
+    }
+}
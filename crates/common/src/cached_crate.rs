@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Crate in the cache of a proxied registry.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CachedCrate {
+    pub name: String,
+    /// Pinned crates are never evicted from the cache.
+    pub pinned: bool,
+    pub total_downloads: u64,
+    pub versions: Vec<CachedVersion>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CachedVersion {
+    pub version: String,
+    pub downloads: u64,
+    /// Last download through the proxy. `None` if the crate file is not cached.
+    pub last_access: Option<String>,
+    /// Size of the cached crate file in bytes.
+    pub size: Option<u64>,
+}
@@ -10,3 +10,24 @@ pub struct CrateOverview {
     pub description: Option<String>,
     pub documentation: Option<String>,
 }
+
+/// A page of search results with the total number of matching crates.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateOverviewPage {
+    pub crates: Vec<CrateOverview>,
+    pub total: u64,
+}
+
+/// A full-text search hit with an HTML snippet of the matching text.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateSearchHit {
+    #[serde(flatten)]
+    pub overview: CrateOverview,
+    pub snippet: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateSearchHitPage {
+    pub hits: Vec<CrateSearchHit>,
+    pub total: u64,
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+/// Metadata of a crate from the web API of the upstream registry.
+/// The fields match the `crate` object of the crates.io API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CratesIoMetadata {
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Total downloads on the upstream registry.
+    #[serde(default)]
+    pub downloads: u64,
+}
+
+/// Response of the crates.io API for a single crate, e.g. `/api/v1/crates/serde`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CratesIoCrate {
+    #[serde(rename = "crate")]
+    pub krate: CratesIoMetadata,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_cratesio_response() {
+        let json = r#"{
+            "crate": {
+                "id": "serde",
+                "description": "A serialization framework",
+                "homepage": "https://serde.rs",
+                "repository": "https://github.com/serde-rs/serde",
+                "documentation": null,
+                "keywords": ["serde", "serialization"],
+                "downloads": 42
+            },
+            "versions": []
+        }"#;
+
+        let krate = serde_json::from_str::<CratesIoCrate>(json).unwrap().krate;
+
+        assert_eq!(
+            CratesIoMetadata {
+                description: Some("A serialization framework".to_string()),
+                homepage: Some("https://serde.rs".to_string()),
+                repository: Some("https://github.com/serde-rs/serde".to_string()),
+                documentation: None,
+                keywords: vec!["serde".to_string(), "serialization".to_string()],
+                downloads: 42,
+            },
+            krate
+        );
+    }
+}
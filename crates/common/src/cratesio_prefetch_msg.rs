@@ -17,3 +17,12 @@ pub enum CratesioPrefetchMsg {
     Insert(InsertData),
     Update(UpdateData),
 }
+
+impl CratesioPrefetchMsg {
+    pub fn name(&self) -> &OriginalName {
+        match self {
+            CratesioPrefetchMsg::Insert(data) => &data.name,
+            CratesioPrefetchMsg::Update(data) => &data.name,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// Crate version as recorded in the index, together with the checksum cargo verifies.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedCrate {
+    pub name: String,
+    pub version: String,
+    pub cksum: String,
+}
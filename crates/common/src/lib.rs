@@ -1,11 +1,16 @@
+pub mod cached_crate;
 pub mod crate_data;
 pub mod crate_overview;
+pub mod cratesio_metadata;
 pub mod cratesio_prefetch_msg;
 pub mod index_metadata;
+pub mod indexed_crate;
 pub mod normalized_name;
 pub mod original_name;
 pub mod prefetch;
+pub mod proxy_policy;
 pub mod publish_metadata;
 pub mod search_result;
+pub mod single_flight;
 pub mod util;
 pub mod version;
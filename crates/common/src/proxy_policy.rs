@@ -0,0 +1,271 @@
+use crate::normalized_name::NormalizedName;
+use regex::Regex;
+use semver::VersionReq;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Decision of the proxy policy for a crate version.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    Allow,
+    Deny,
+    /// Blocked until an admin allows or denies the crate.
+    Pending,
+}
+
+impl fmt::Display for PolicyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyAction::Allow => write!(f, "allow"),
+            PolicyAction::Deny => write!(f, "deny"),
+            PolicyAction::Pending => write!(f, "pending"),
+        }
+    }
+}
+
+impl FromStr for PolicyAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(PolicyAction::Allow),
+            "deny" => Ok(PolicyAction::Deny),
+            "pending" => Ok(PolicyAction::Pending),
+            _ => Err(format!("Invalid policy action: {s}")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProxyRule {
+    pub id: i64,
+    /// Crate name, where `*` matches any characters, e.g. `tokio-*`.
+    pub pattern: String,
+    /// Semver requirement of the crate versions, e.g. `>=1.2, <2`.
+    /// `*` matches all versions, including pre-releases.
+    pub version_req: String,
+    pub action: PolicyAction,
+    pub created: String,
+}
+
+impl ProxyRule {
+    /// Checks the pattern and version requirement of a new rule.
+    pub fn validate(pattern: &str, version_req: &str) -> Result<(), String> {
+        if pattern.is_empty()
+            || !pattern
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '*')
+        {
+            return Err(format!("Invalid crate name pattern: {pattern}"));
+        }
+        if version_req != "*" && VersionReq::parse(version_req).is_err() {
+            return Err(format!("Invalid version requirement: {version_req}"));
+        }
+        Ok(())
+    }
+}
+
+/// Rule with the pattern and version requirement parsed for matching.
+struct CompiledRule {
+    name: Regex,
+    // `None` matches all versions
+    version: Option<VersionReq>,
+    action: PolicyAction,
+}
+
+impl CompiledRule {
+    fn new(rule: &ProxyRule) -> Option<Self> {
+        let pattern = regex::escape(&rule.pattern.to_lowercase()).replace(r"\*", ".*");
+        let version = match rule.version_req.as_str() {
+            "*" => None,
+            req => Some(VersionReq::parse(req).ok()?),
+        };
+
+        Some(Self {
+            name: Regex::new(&format!("^{pattern}$")).ok()?,
+            version,
+            action: rule.action,
+        })
+    }
+
+    fn matches(&self, name: &str, version: &str) -> bool {
+        self.name.is_match(name)
+            && match (&self.version, semver::Version::parse(version)) {
+                (None, _) => true,
+                (Some(req), Ok(version)) => req.matches(&version),
+                (Some(_), Err(_)) => false,
+            }
+    }
+}
+
+/// Policy that decides which crates can be downloaded through the proxy.
+pub struct ProxyPolicy {
+    rules: Vec<CompiledRule>,
+    approval_required: bool,
+}
+
+impl ProxyPolicy {
+    /// If `approval_required` is set, crates without a matching rule are pending approval.
+    /// Else, they are allowed.
+    pub fn new(rules: &[ProxyRule], approval_required: bool) -> Self {
+        Self {
+            rules: rules.iter().filter_map(CompiledRule::new).collect(),
+            approval_required,
+        }
+    }
+
+    pub fn approval_required(&self) -> bool {
+        self.approval_required
+    }
+
+    /// Decides if the crate version can be downloaded. If multiple rules match,
+    /// a denying rule wins over a pending one, which wins over an allowing one.
+    pub fn action(&self, name: &NormalizedName, version: &str) -> PolicyAction {
+        let actions: Vec<PolicyAction> = self
+            .rules
+            .iter()
+            .filter(|r| r.matches(name, version))
+            .map(|r| r.action)
+            .collect();
+
+        if actions.contains(&PolicyAction::Deny) {
+            PolicyAction::Deny
+        } else if actions.contains(&PolicyAction::Pending) {
+            PolicyAction::Pending
+        } else if actions.contains(&PolicyAction::Allow) {
+            PolicyAction::Allow
+        } else if self.approval_required {
+            PolicyAction::Pending
+        } else {
+            PolicyAction::Allow
+        }
+    }
+
+    /// Returns true if an approval is required, but no rule exists for any version of the crate.
+    pub fn needs_approval_request(&self, name: &NormalizedName) -> bool {
+        self.approval_required && !self.rules.iter().any(|r| r.name.is_match(name))
+    }
+
+    /// Returns true if no version of the crate can be downloaded, independent of the versions
+    /// that exist upstream.
+    pub fn blocks_all_versions(&self, name: &NormalizedName) -> bool {
+        self.needs_approval_request(name)
+            || self.rules.iter().any(|r| {
+                r.version.is_none() && r.action != PolicyAction::Allow && r.name.is_match(name)
+            })
+    }
+
+    /// Removes the entries of blocked versions from an index file.
+    /// Returns the filtered index file and the blocked versions.
+    pub fn filter_index(&self, name: &NormalizedName, data: &[u8]) -> (Vec<u8>, Vec<String>) {
+        #[derive(Deserialize)]
+        struct Entry {
+            vers: String,
+        }
+
+        let mut filtered = Vec::with_capacity(data.len());
+        let mut blocked = Vec::new();
+        for line in data.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            // Lines that cannot be parsed are passed on, such that cargo reports them.
+            if let Ok(entry) = serde_json::from_slice::<Entry>(line) {
+                if self.action(name, &entry.vers) != PolicyAction::Allow {
+                    blocked.push(entry.vers);
+                    continue;
+                }
+            }
+            filtered.extend_from_slice(line);
+            filtered.push(b'\n');
+        }
+
+        (filtered, blocked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, version_req: &str, action: PolicyAction) -> ProxyRule {
+        ProxyRule {
+            id: 0,
+            pattern: pattern.to_string(),
+            version_req: version_req.to_string(),
+            action,
+            created: String::new(),
+        }
+    }
+
+    fn name(name: &str) -> NormalizedName {
+        NormalizedName::from_unchecked_str(name)
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let policy = ProxyPolicy::new(
+            &[
+                rule("tokio*", "*", PolicyAction::Allow),
+                rule("tokio-evil", "<2", PolicyAction::Deny),
+            ],
+            false,
+        );
+
+        assert_eq!(PolicyAction::Allow, policy.action(&name("tokio"), "1.0.0"));
+        assert_eq!(
+            PolicyAction::Deny,
+            policy.action(&name("tokio-evil"), "1.0.0")
+        );
+        assert_eq!(
+            PolicyAction::Allow,
+            policy.action(&name("tokio-evil"), "2.0.0")
+        );
+    }
+
+    #[test]
+    fn unmatched_crates_depend_on_approval_requirement() {
+        let rules = [rule("serde", "*", PolicyAction::Allow)];
+
+        let open = ProxyPolicy::new(&rules, false);
+        let approval = ProxyPolicy::new(&rules, true);
+
+        assert_eq!(PolicyAction::Allow, open.action(&name("rand"), "1.0.0"));
+        assert_eq!(
+            PolicyAction::Pending,
+            approval.action(&name("rand"), "1.0.0")
+        );
+        assert_eq!(
+            PolicyAction::Allow,
+            approval.action(&name("serde"), "1.0.0")
+        );
+        assert!(approval.needs_approval_request(&name("rand")));
+        assert!(!approval.needs_approval_request(&name("serde")));
+        assert!(!open.needs_approval_request(&name("rand")));
+        assert!(approval.blocks_all_versions(&name("rand")));
+        assert!(!approval.blocks_all_versions(&name("serde")));
+    }
+
+    #[test]
+    fn filter_index_removes_blocked_versions() {
+        let policy = ProxyPolicy::new(&[rule("rand", ">=0.8", PolicyAction::Deny)], false);
+        let data =
+            b"{\"name\":\"rand\",\"vers\":\"0.7.0\"}\n{\"name\":\"rand\",\"vers\":\"0.8.0\"}\n";
+
+        let (filtered, blocked) = policy.filter_index(&name("rand"), data);
+
+        assert_eq!(
+            b"{\"name\":\"rand\",\"vers\":\"0.7.0\"}\n".to_vec(),
+            filtered
+        );
+        assert_eq!(vec!["0.8.0".to_string()], blocked);
+    }
+
+    #[test]
+    fn validate_rule() {
+        assert!(ProxyRule::validate("tokio-*", ">=1, <2").is_ok());
+        assert!(ProxyRule::validate("serde", "*").is_ok());
+        assert!(ProxyRule::validate("", "*").is_err());
+        assert!(ProxyRule::validate("a/b", "*").is_err());
+        assert!(ProxyRule::validate("serde", "latest").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Coalesces concurrent calls with the same key, such that only the first call runs
+/// and all others wait for its result. A call that starts after the first one completed
+/// runs again, i.e. results are not cached.
+pub struct SingleFlight<K, V> {
+    calls: Mutex<BTreeMap<K, Arc<OnceCell<V>>>>,
+}
+
+impl<K: Ord + Clone, V: Clone> SingleFlight<K, V> {
+    pub const fn new() -> Self {
+        Self {
+            calls: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Runs `f` or waits for the running call with the same key. If the running call
+    /// is cancelled, one of the waiting calls runs its `f` instead.
+    pub async fn run<F, Fut>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let call = self
+            .calls
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let value = call.get_or_init(f).await.clone();
+
+        let mut calls = self.calls.lock().unwrap();
+        if calls.get(&key).is_some_and(|c| Arc::ptr_eq(c, &call)) {
+            calls.remove(&key);
+        }
+        value
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn concurrent_calls_run_once() {
+        let flight = SingleFlight::<String, usize>::new();
+        let runs = AtomicUsize::new(0);
+        let call = || async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            runs.fetch_add(1, Ordering::SeqCst) + 1
+        };
+
+        let (a, b, c) = tokio::join!(
+            flight.run("serde".to_string(), call),
+            flight.run("serde".to_string(), call),
+            flight.run("serde".to_string(), call)
+        );
+
+        assert_eq!((1, 1, 1), (a, b, c));
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn later_calls_run_again() {
+        let flight = SingleFlight::<String, usize>::new();
+        let runs = AtomicUsize::new(0);
+        let call = || async { runs.fetch_add(1, Ordering::SeqCst) + 1 };
+
+        let first = flight.run("serde".to_string(), call).await;
+        let second = flight.run("serde".to_string(), call).await;
+        let other = flight.run("rand".to_string(), call).await;
+
+        assert_eq!((1, 2, 3), (first, second, other));
+    }
+}
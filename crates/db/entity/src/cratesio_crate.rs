@@ -7,9 +7,9 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
-    #[sea_orm(column_type = "Text", unique)]
+    #[sea_orm(column_type = "Text")]
     pub name: String,
-    #[sea_orm(column_type = "Text", unique)]
+    #[sea_orm(column_type = "Text")]
     pub original_name: String,
     pub e_tag: String,
     #[sea_orm(column_type = "Text")]
@@ -17,6 +17,19 @@ pub struct Model {
     #[sea_orm(column_type = "Text", nullable)]
     pub description: Option<String>,
     pub total_downloads: i64,
+    #[sea_orm(column_type = "Text")]
+    pub upstream: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub homepage: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub repository: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub documentation: Option<String>,
+    pub keywords: Option<Json>,
+    pub upstream_downloads: i64,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub metadata_updated: Option<String>,
+    pub pinned: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
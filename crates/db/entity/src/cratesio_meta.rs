@@ -11,6 +11,8 @@ pub struct Model {
     pub version: String,
     pub downloads: i64,
     pub crates_io_fk: i64,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub last_access: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
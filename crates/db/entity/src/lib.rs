@@ -17,5 +17,7 @@ pub mod cratesio_meta;
 pub mod doc_queue;
 pub mod krate;
 pub mod owner;
+pub mod proxy_rule;
+pub mod security_event;
 pub mod session;
 pub mod user;
@@ -15,5 +15,7 @@ pub use super::cratesio_meta::Entity as CratesioMeta;
 pub use super::doc_queue::Entity as DocQueue;
 pub use super::krate::Entity as Krate;
 pub use super::owner::Entity as Owner;
+pub use super::proxy_rule::Entity as ProxyRule;
+pub use super::security_event::Entity as SecurityEvent;
 pub use super::session::Entity as Session;
 pub use super::user::Entity as User;
@@ -157,9 +157,19 @@ pub enum CratesIoIden {
     Table,
     Id,
     Name,
+    OriginalName,
     Description,
     ETag,
     LastModified,
+    TotalDownloads,
+    Upstream,
+    Homepage,
+    Repository,
+    Documentation,
+    Keywords,
+    UpstreamDownloads,
+    MetadataUpdated,
+    Pinned,
 }
 
 #[derive(Iden)]
@@ -167,10 +177,12 @@ pub enum CratesIoIndexIden {
     #[iden = "cratesio_index"]
     Table,
     Id,
+    Name,
     Vers,
     Deps,
     Cksum,
     Features,
+    Features2,
     Yanked,
     Links,
     V,
@@ -185,4 +197,29 @@ pub enum CratesIoMetaIden {
     Version,
     Downloads,
     CratesIoFk,
+    LastAccess,
+}
+
+#[derive(Iden)]
+pub enum ProxyRuleIden {
+    #[iden = "proxy_rule"]
+    Table,
+    Id,
+    Pattern,
+    VersionReq,
+    Action,
+    Created,
+}
+
+#[derive(Iden)]
+pub enum SecurityEventIden {
+    #[iden = "security_event"]
+    Table,
+    Id,
+    Kind,
+    Upstream,
+    CrateName,
+    Version,
+    Detail,
+    Created,
 }
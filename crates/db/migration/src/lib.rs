@@ -18,6 +18,12 @@ mod m20220101_000008_create_table;
 mod m20220101_000008_create_table_entities;
 mod m20220101_000009_create_table;
 mod m20220101_000009_create_table_entities;
+mod m20220101_000010_create_table;
+mod m20220101_000011_create_table;
+mod m20220101_000012_create_table;
+mod m20220101_000013_create_table;
+mod m20220101_000014_create_table;
+mod m20220101_000015_create_table;
 mod old_index_metadata;
 
 pub struct Migrator;
@@ -35,6 +41,12 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000007_create_table::Migration),
             Box::new(m20220101_000008_create_table::Migration),
             Box::new(m20220101_000009_create_table::Migration),
+            Box::new(m20220101_000010_create_table::Migration),
+            Box::new(m20220101_000011_create_table::Migration),
+            Box::new(m20220101_000012_create_table::Migration),
+            Box::new(m20220101_000013_create_table::Migration),
+            Box::new(m20220101_000014_create_table::Migration),
+            Box::new(m20220101_000015_create_table::Migration),
         ]
     }
 }
@@ -0,0 +1,50 @@
+use crate::iden::CratesIoMetaIden;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Manual check if the column exists is needed, as Sqlite does not support
+        // ALTER TABLE IF COLUMN EXISTS. Without the check, the migration would fail
+        // on Sqlite with an "duplicate column" error.
+        if !manager.has_column("cratesio_meta", "last_access").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(CratesIoMetaIden::Table)
+                        .add_column_if_not_exists(
+                            ColumnDef::new(CratesIoMetaIden::LastAccess).text().null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // Crate versions that were downloaded through the proxy before the last access
+        // was tracked are marked as least recently used, such that they are evicted first.
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(CratesIoMetaIden::Table)
+                    .value(CratesIoMetaIden::LastAccess, "1970-01-01 00:00:00")
+                    .and_where(Expr::col(CratesIoMetaIden::Downloads).gt(0))
+                    .and_where(Expr::col(CratesIoMetaIden::LastAccess).is_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CratesIoMetaIden::Table)
+                    .drop_column(CratesIoMetaIden::LastAccess)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,303 @@
+use crate::iden::{CratesIoIden, CratesIoIndexIden, CratesIoMetaIden};
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DbBackend;
+
+/// Upstream of all crates that were cached before multiple upstreams were supported.
+const CRATESIO_UPSTREAM: &str = "crates-io";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.has_column("cratesio_crate", "upstream").await? {
+            return Ok(());
+        }
+
+        match manager.get_database_backend() {
+            DbBackend::Sqlite => rebuild_sqlite_tables(manager).await?,
+            _ => alter_table(manager).await?,
+        }
+
+        // A crate name is only unique per upstream, as the same name can exist
+        // in multiple proxied registries.
+        manager
+            .create_index(
+                Index::create()
+                    .name("cratesio_crate_upstream_name_idx")
+                    .table(CratesIoIden::Table)
+                    .col(CratesIoIden::Upstream)
+                    .col(CratesIoIden::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("cratesio_crate_upstream_original_name_idx")
+                    .table(CratesIoIden::Table)
+                    .col(CratesIoIden::Upstream)
+                    .col(CratesIoIden::OriginalName)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // The unique crate names cannot be restored if the same crate name
+        // was cached from multiple upstreams.
+        Err(DbErr::Migration(
+            "Removing the upstream of cached crates is not supported".to_string(),
+        ))
+    }
+}
+
+async fn alter_table(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(CratesIoIden::Table)
+                .add_column(&mut upstream_column())
+                .to_owned(),
+        )
+        .await?;
+
+    // Default names of the unique constraints created by Postgres.
+    let db = manager.get_connection();
+    db.execute_unprepared(
+        "ALTER TABLE cratesio_crate DROP CONSTRAINT IF EXISTS cratesio_crate_name_key",
+    )
+    .await?;
+    db.execute_unprepared(
+        "ALTER TABLE cratesio_crate DROP CONSTRAINT IF EXISTS cratesio_crate_original_name_key",
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sqlite cannot drop the unique constraint of a column. Instead, the crates.io tables
+/// are copied into new tables without the constraint, which replace the old ones.
+async fn rebuild_sqlite_tables(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let crate_table = Alias::new("cratesio_crate_new");
+    let index_table = Alias::new("cratesio_index_new");
+    let meta_table = Alias::new("cratesio_meta_new");
+
+    manager
+        .create_table(
+            Table::create()
+                .table(crate_table.clone())
+                .col(&mut id_column(CratesIoIden::Id))
+                .col(ColumnDef::new(CratesIoIden::Name).text().not_null())
+                .col(ColumnDef::new(CratesIoIden::OriginalName).text().not_null())
+                .col(ColumnDef::new(CratesIoIden::ETag).string_len(64).not_null())
+                .col(ColumnDef::new(CratesIoIden::LastModified).text().not_null())
+                .col(ColumnDef::new(CratesIoIden::Description).text())
+                .col(
+                    ColumnDef::new(CratesIoIden::TotalDownloads)
+                        .big_integer()
+                        .not_null()
+                        .default(0),
+                )
+                .col(&mut upstream_column())
+                .to_owned(),
+        )
+        .await?;
+    copy_rows(
+        manager,
+        CratesIoIden::Table,
+        crate_table.clone(),
+        [
+            CratesIoIden::Id,
+            CratesIoIden::Name,
+            CratesIoIden::OriginalName,
+            CratesIoIden::ETag,
+            CratesIoIden::LastModified,
+            CratesIoIden::Description,
+            CratesIoIden::TotalDownloads,
+        ],
+    )
+    .await?;
+
+    manager
+        .create_table(
+            Table::create()
+                .table(index_table.clone())
+                .col(&mut id_column(CratesIoIndexIden::Id))
+                .col(ColumnDef::new(CratesIoIndexIden::Name).text().not_null())
+                .col(ColumnDef::new(CratesIoIndexIden::Vers).text().not_null())
+                .col(ColumnDef::new(CratesIoIndexIden::Deps).json_binary())
+                .col(ColumnDef::new(CratesIoIndexIden::Cksum).text().not_null())
+                .col(ColumnDef::new(CratesIoIndexIden::Features).json_binary())
+                .col(ColumnDef::new(CratesIoIndexIden::Features2).json_binary())
+                .col(
+                    ColumnDef::new(CratesIoIndexIden::Yanked)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(ColumnDef::new(CratesIoIndexIden::Links).text())
+                .col(
+                    ColumnDef::new(CratesIoIndexIden::V)
+                        .integer()
+                        .not_null()
+                        .default(1),
+                )
+                .col(
+                    ColumnDef::new(CratesIoIndexIden::CratesIoFk)
+                        .big_integer()
+                        .not_null(),
+                )
+                .foreign_key(&mut crate_foreign_key(
+                    index_table.clone(),
+                    CratesIoIndexIden::CratesIoFk,
+                    crate_table.clone(),
+                ))
+                .to_owned(),
+        )
+        .await?;
+    copy_rows(
+        manager,
+        CratesIoIndexIden::Table,
+        index_table.clone(),
+        [
+            CratesIoIndexIden::Id,
+            CratesIoIndexIden::Name,
+            CratesIoIndexIden::Vers,
+            CratesIoIndexIden::Deps,
+            CratesIoIndexIden::Cksum,
+            CratesIoIndexIden::Features,
+            CratesIoIndexIden::Features2,
+            CratesIoIndexIden::Yanked,
+            CratesIoIndexIden::Links,
+            CratesIoIndexIden::V,
+            CratesIoIndexIden::CratesIoFk,
+        ],
+    )
+    .await?;
+
+    manager
+        .create_table(
+            Table::create()
+                .table(meta_table.clone())
+                .col(&mut id_column(CratesIoMetaIden::Id))
+                .col(ColumnDef::new(CratesIoMetaIden::Version).text().not_null())
+                .col(
+                    ColumnDef::new(CratesIoMetaIden::Downloads)
+                        .big_integer()
+                        .not_null()
+                        .default(0),
+                )
+                .col(
+                    ColumnDef::new(CratesIoMetaIden::CratesIoFk)
+                        .big_integer()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(CratesIoMetaIden::LastAccess).text().null())
+                .foreign_key(&mut crate_foreign_key(
+                    meta_table.clone(),
+                    CratesIoMetaIden::CratesIoFk,
+                    crate_table.clone(),
+                ))
+                .to_owned(),
+        )
+        .await?;
+    copy_rows(
+        manager,
+        CratesIoMetaIden::Table,
+        meta_table.clone(),
+        [
+            CratesIoMetaIden::Id,
+            CratesIoMetaIden::Version,
+            CratesIoMetaIden::Downloads,
+            CratesIoMetaIden::CratesIoFk,
+            CratesIoMetaIden::LastAccess,
+        ],
+    )
+    .await?;
+
+    // Drop the referencing tables first, else the foreign keys would delete
+    // the copied rows on cascade.
+    for table in [
+        CratesIoIndexIden::Table.into_iden(),
+        CratesIoMetaIden::Table.into_iden(),
+        CratesIoIden::Table.into_iden(),
+    ] {
+        manager
+            .drop_table(Table::drop().table(table).to_owned())
+            .await?;
+    }
+
+    // Renaming the crate table updates the foreign keys of the new index and meta tables.
+    for (from, to) in [
+        (crate_table.into_iden(), CratesIoIden::Table.into_iden()),
+        (
+            index_table.into_iden(),
+            CratesIoIndexIden::Table.into_iden(),
+        ),
+        (meta_table.into_iden(), CratesIoMetaIden::Table.into_iden()),
+    ] {
+        manager
+            .rename_table(Table::rename().table(from, to).to_owned())
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn copy_rows<const N: usize, C>(
+    manager: &SchemaManager<'_>,
+    from: impl IntoIden,
+    to: impl IntoIden,
+    columns: [C; N],
+) -> Result<(), DbErr>
+where
+    C: IntoIden + Clone,
+{
+    let select = Query::select()
+        .columns(columns.clone())
+        .from(from)
+        .to_owned();
+    let insert = Query::insert()
+        .into_table(to)
+        .columns(columns)
+        .select_from(select)
+        .map_err(|e| DbErr::Migration(e.to_string()))?
+        .to_owned();
+
+    manager.exec_stmt(insert).await
+}
+
+fn id_column(id: impl IntoIden) -> ColumnDef {
+    ColumnDef::new(id)
+        .big_integer()
+        .not_null()
+        .primary_key()
+        .auto_increment()
+        .to_owned()
+}
+
+fn upstream_column() -> ColumnDef {
+    ColumnDef::new(CratesIoIden::Upstream)
+        .text()
+        .not_null()
+        .default(CRATESIO_UPSTREAM)
+        .to_owned()
+}
+
+fn crate_foreign_key(
+    table: Alias,
+    column: impl IntoIden,
+    crate_table: Alias,
+) -> ForeignKeyCreateStatement {
+    ForeignKey::create()
+        .name("cratesio_fk")
+        .from(table, column)
+        .to(crate_table, CratesIoIden::Id)
+        .on_delete(ForeignKeyAction::Cascade)
+        .on_update(ForeignKeyAction::NoAction)
+        .to_owned()
+}
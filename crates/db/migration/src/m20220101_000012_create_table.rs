@@ -0,0 +1,49 @@
+use crate::iden::ProxyRuleIden;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProxyRuleIden::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ProxyRuleIden::Id)
+                            .big_integer()
+                            .not_null()
+                            .primary_key()
+                            .auto_increment(),
+                    )
+                    .col(ColumnDef::new(ProxyRuleIden::Pattern).text().not_null())
+                    .col(ColumnDef::new(ProxyRuleIden::VersionReq).text().not_null())
+                    .col(ColumnDef::new(ProxyRuleIden::Action).text().not_null())
+                    .col(ColumnDef::new(ProxyRuleIden::Created).text().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("proxy_rule_pattern_version_req_idx")
+                    .table(ProxyRuleIden::Table)
+                    .col(ProxyRuleIden::Pattern)
+                    .col(ProxyRuleIden::VersionReq)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ProxyRuleIden::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,46 @@
+use crate::iden::SecurityEventIden;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SecurityEventIden::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SecurityEventIden::Id)
+                            .big_integer()
+                            .not_null()
+                            .primary_key()
+                            .auto_increment(),
+                    )
+                    .col(ColumnDef::new(SecurityEventIden::Kind).text().not_null())
+                    .col(
+                        ColumnDef::new(SecurityEventIden::Upstream)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SecurityEventIden::CrateName)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SecurityEventIden::Version).text().not_null())
+                    .col(ColumnDef::new(SecurityEventIden::Detail).text().not_null())
+                    .col(ColumnDef::new(SecurityEventIden::Created).text().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SecurityEventIden::Table).to_owned())
+            .await
+    }
+}
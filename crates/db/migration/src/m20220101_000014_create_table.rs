@@ -0,0 +1,93 @@
+use crate::iden::CratesIoIden;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sqlite supports only one column per ALTER TABLE statement and no
+        // ADD COLUMN IF NOT EXISTS, such that each column is checked and added on its own.
+        for (name, mut column) in metadata_columns() {
+            if !manager.has_column("cratesio_crate", name).await? {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(CratesIoIden::Table)
+                            .add_column(&mut column)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (name, _) in metadata_columns() {
+            if manager.has_column("cratesio_crate", name).await? {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(CratesIoIden::Table)
+                            .drop_column(Alias::new(name))
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Crate metadata from the web API of the upstream registry.
+fn metadata_columns() -> [(&'static str, ColumnDef); 6] {
+    [
+        (
+            "homepage",
+            ColumnDef::new(CratesIoIden::Homepage)
+                .text()
+                .null()
+                .to_owned(),
+        ),
+        (
+            "repository",
+            ColumnDef::new(CratesIoIden::Repository)
+                .text()
+                .null()
+                .to_owned(),
+        ),
+        (
+            "documentation",
+            ColumnDef::new(CratesIoIden::Documentation)
+                .text()
+                .null()
+                .to_owned(),
+        ),
+        (
+            "keywords",
+            ColumnDef::new(CratesIoIden::Keywords)
+                .json_binary()
+                .null()
+                .to_owned(),
+        ),
+        (
+            "upstream_downloads",
+            ColumnDef::new(CratesIoIden::UpstreamDownloads)
+                .big_integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+        ),
+        (
+            "metadata_updated",
+            ColumnDef::new(CratesIoIden::MetadataUpdated)
+                .text()
+                .null()
+                .to_owned(),
+        ),
+    ]
+}
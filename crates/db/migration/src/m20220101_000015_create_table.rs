@@ -0,0 +1,44 @@
+use crate::iden::CratesIoIden;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Pinned crates are never evicted from the proxy cache.
+        if !manager.has_column("cratesio_crate", "pinned").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(CratesIoIden::Table)
+                        .add_column(
+                            ColumnDef::new(CratesIoIden::Pinned)
+                                .boolean()
+                                .not_null()
+                                .default(false),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.has_column("cratesio_crate", "pinned").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(CratesIoIden::Table)
+                        .drop_column(CratesIoIden::Pinned)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -3,44 +3,63 @@ use crate::provider::{DbResult, PrefetchState};
 use crate::tables::init_database;
 use crate::{error::DbError, AuthToken, CrateMeta, CrateSummary, DbProvider, User};
 use crate::{ConString, DocQueueEntry};
-use chrono::{DateTime, Utc};
+use crate::{SecurityEvent, SecurityEventKind};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use common::cached_crate::{CachedCrate, CachedVersion};
 use common::crate_data::{CrateData, CrateRegistryDep, CrateVersionData};
-use common::crate_overview::CrateOverview;
+use common::crate_overview::{
+    CrateOverview, CrateOverviewPage, CrateSearchHit, CrateSearchHitPage,
+};
+use common::cratesio_metadata::CratesIoMetadata;
 use common::cratesio_prefetch_msg::{CratesioPrefetchMsg, UpdateData};
 use common::index_metadata::{IndexDep, IndexMetadata};
+use common::indexed_crate::IndexedCrate;
 use common::normalized_name::NormalizedName;
 use common::original_name::OriginalName;
 use common::prefetch::Prefetch;
+use common::proxy_policy::{PolicyAction, ProxyRule};
 use common::publish_metadata::PublishMetadata;
+use common::search_result::{self, SearchResult};
 use common::version::Version;
 use entity::{
     auth_token, crate_author, crate_author_to_crate, crate_category, crate_category_to_crate,
     crate_index, crate_keyword, crate_keyword_to_crate, crate_meta, cratesio_crate, cratesio_index,
-    cratesio_meta, doc_queue, krate, owner, prelude::*, session, user,
+    cratesio_meta, doc_queue, krate, owner, prelude::*, proxy_rule, security_event, session, user,
 };
 use hex::ToHex;
 use migration::iden::{AuthTokenIden, CrateIden, CrateMetaIden, CratesIoIden, CratesIoMetaIden};
 use sea_orm::sea_query::{Alias, Expr, Query, *};
 use sea_orm::{
     prelude::async_trait::async_trait, query::*, ActiveModelTrait, ColumnTrait, ConnectionTrait,
-    DatabaseConnection, EntityTrait, FromQueryResult, InsertResult, ModelTrait, QueryFilter,
-    RelationTrait, Set,
+    DatabaseConnection, EntityTrait, FromQueryResult, InsertResult, ModelTrait, PaginatorTrait,
+    QueryFilter, RelationTrait, Set,
 };
+use search::search_index::{SearchDocument, SearchIndex};
+use settings::proxy::CRATESIO_UPSTREAM;
 use sha2::{Digest, Sha256};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Add;
 use std::path::Path;
+use std::sync::Arc;
 use std::vec;
+use tracing::error;
 
 const DB_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
 pub struct Database {
     db_con: DatabaseConnection,
+    // Proxied registry the cached crates belong to.
+    upstream: String,
+    search_index: Option<Arc<SearchIndex>>,
 }
 
 impl Database {
     pub fn existing(db_con: DatabaseConnection) -> Self {
-        Self { db_con }
+        Self {
+            db_con,
+            upstream: CRATESIO_UPSTREAM.to_string(),
+            search_index: None,
+        }
     }
 
     pub async fn new(con: &ConString) -> Result<Self, DbError> {
@@ -52,7 +71,32 @@ impl Database {
             Self::insert_admin_credentials(&db_con, con).await?;
         }
 
-        Ok(Self { db_con })
+        Ok(Self::existing(db_con))
+    }
+
+    /// Database that shares the connection, but reads and writes the cached crates of the
+    /// given proxied registry instead of crates.io.
+    pub fn with_upstream(&self, upstream: &str) -> Self {
+        Self {
+            db_con: self.db_con.clone(),
+            upstream: upstream.to_string(),
+            search_index: self.search_index.clone(),
+        }
+    }
+
+    /// Database that updates the full-text search index when crates are published,
+    /// yanked or deleted.
+    pub fn with_search_index(self, search_index: Arc<SearchIndex>) -> Self {
+        Self {
+            search_index: Some(search_index),
+            ..self
+        }
+    }
+
+    /// Query of the cached crates of the proxied registry.
+    fn find_cratesio_crates(&self) -> Select<cratesio_crate::Entity> {
+        cratesio_crate::Entity::find()
+            .filter(cratesio_crate::Column::Upstream.eq(self.upstream.as_str()))
     }
 
     async fn get_desc_for_crate_dep(
@@ -63,6 +107,7 @@ impl Database {
         let desc = if registry == &Some("https://github.com/rust-lang/crates.io-index".to_string())
         {
             let krate = cratesio_crate::Entity::find()
+                .filter(cratesio_crate::Column::Upstream.eq(CRATESIO_UPSTREAM))
                 .filter(cratesio_crate::Column::Name.eq(name))
                 .one(&self.db_con)
                 .await?;
@@ -153,10 +198,14 @@ impl Database {
         Ok(())
     }
 
-    async fn add_owner_if_not_exists(&self, owner: &str, crate_id: i64) -> DbResult<()> {
+    async fn add_owner_if_not_exists(
+        db: &impl ConnectionTrait,
+        owner: &str,
+        crate_id: i64,
+    ) -> DbResult<()> {
         let user_fk = user::Entity::find()
             .filter(user::Column::Name.eq(owner))
-            .one(&self.db_con)
+            .one(db)
             .await?
             .map(|model| model.id)
             .ok_or_else(|| DbError::UserNotFound(owner.to_string()))?;
@@ -164,7 +213,7 @@ impl Database {
         let owner = owner::Entity::find()
             .filter(owner::Column::CrateFk.eq(crate_id))
             .filter(owner::Column::UserFk.eq(user_fk))
-            .one(&self.db_con)
+            .one(db)
             .await?;
 
         if owner.is_none() {
@@ -174,7 +223,7 @@ impl Database {
                 ..Default::default()
             };
 
-            o.insert(&self.db_con).await?;
+            o.insert(db).await?;
         }
         Ok(())
     }
@@ -187,7 +236,8 @@ impl Database {
     ) -> DbResult<()> {
         let _ = self.test_add_cached_crate(name, version).await?;
 
-        let krate = cratesio_crate::Entity::find()
+        let krate = self
+            .find_cratesio_crates()
             .filter(cratesio_crate::Column::Name.eq(name))
             .one(&self.db_con)
             .await?
@@ -315,7 +365,7 @@ impl Database {
     }
 
     async fn add_crate_index(
-        &self,
+        db: &impl ConnectionTrait,
         pub_metadata: &PublishMetadata,
         cksum: &str,
         crate_id: i64,
@@ -346,12 +396,12 @@ impl Database {
             crate_fk: Set(crate_id),
         };
 
-        ci.insert(&self.db_con).await?;
+        ci.insert(db).await?;
         Ok(())
     }
 
     async fn update_crate_categories(
-        &self,
+        db: &impl ConnectionTrait,
         pub_metadata: &PublishMetadata,
         crate_id: i64,
     ) -> DbResult<()> {
@@ -360,14 +410,14 @@ impl Database {
         // Delete all existing categories relationships as only the latest list of categories is relevant
         crate_category_to_crate::Entity::delete_many()
             .filter(crate_category_to_crate::Column::CrateFk.eq(crate_id))
-            .exec(&self.db_con)
+            .exec(db)
             .await?;
 
         // Set the latest list of categories for the crate
         for category in categories {
             let category_fk = crate_category::Entity::find()
                 .filter(crate_category::Column::Category.eq(category.clone()))
-                .one(&self.db_con)
+                .one(db)
                 .await?
                 .map(|model| model.id);
 
@@ -380,7 +430,7 @@ impl Database {
                         category: Set(category.clone()),
                     };
 
-                    cc.insert(&self.db_con).await?.id
+                    cc.insert(db).await?.id
                 }
             };
 
@@ -390,14 +440,14 @@ impl Database {
                 crate_fk: Set(crate_id),
                 category_fk: Set(category_fk),
             };
-            cctc.insert(&self.db_con).await?;
+            cctc.insert(db).await?;
         }
 
         Ok(())
     }
 
     async fn update_crate_keywords(
-        &self,
+        db: &impl ConnectionTrait,
         pub_metadata: &PublishMetadata,
         crate_id: i64,
     ) -> DbResult<()> {
@@ -406,14 +456,14 @@ impl Database {
         // Delete all existing keywords relationships as only the latest list of keywords is relevant
         crate_keyword_to_crate::Entity::delete_many()
             .filter(crate_keyword_to_crate::Column::CrateFk.eq(crate_id))
-            .exec(&self.db_con)
+            .exec(db)
             .await?;
 
         // Set the latest list of keywords for the crate
         for keyword in keywords {
             let keyword_fk = crate_keyword::Entity::find()
                 .filter(crate_keyword::Column::Keyword.eq(keyword.clone()))
-                .one(&self.db_con)
+                .one(db)
                 .await?
                 .map(|model| model.id);
 
@@ -426,7 +476,7 @@ impl Database {
                         keyword: Set(keyword.clone()),
                     };
 
-                    ck.insert(&self.db_con).await?.id
+                    ck.insert(db).await?.id
                 }
             };
 
@@ -436,14 +486,14 @@ impl Database {
                 crate_fk: Set(crate_id),
                 keyword_fk: Set(keyword_fk),
             };
-            cktc.insert(&self.db_con).await?;
+            cktc.insert(db).await?;
         }
 
         Ok(())
     }
 
     async fn update_crate_authors(
-        &self,
+        db: &impl ConnectionTrait,
         pub_metadata: &PublishMetadata,
         crate_id: i64,
     ) -> DbResult<()> {
@@ -452,14 +502,14 @@ impl Database {
         // Delete all existing authors relationships as only the latest list of authors is relevant
         crate_author_to_crate::Entity::delete_many()
             .filter(crate_author_to_crate::Column::CrateFk.eq(crate_id))
-            .exec(&self.db_con)
+            .exec(db)
             .await?;
 
         // Set the latest list of authors for the crate
         for author in authors {
             let author_fk = crate_author::Entity::find()
                 .filter(crate_author::Column::Author.eq(author.clone()))
-                .one(&self.db_con)
+                .one(db)
                 .await?
                 .map(|model| model.id);
 
@@ -472,7 +522,7 @@ impl Database {
                         author: Set(author.clone()),
                     };
 
-                    ca.insert(&self.db_con).await?.id
+                    ca.insert(db).await?.id
                 }
             };
 
@@ -482,16 +532,20 @@ impl Database {
                 crate_fk: Set(crate_id),
                 author_fk: Set(author_fk),
             };
-            catc.insert(&self.db_con).await?;
+            catc.insert(db).await?;
         }
 
         Ok(())
     }
 
-    async fn compute_etag(&self, crate_name: &str, crate_id: i64) -> DbResult<String> {
+    async fn compute_etag(
+        db: &impl ConnectionTrait,
+        crate_name: &str,
+        crate_id: i64,
+    ) -> DbResult<String> {
         let crate_indices = crate_index::Entity::find()
             .filter(crate_index::Column::CrateFk.eq(crate_id))
-            .all(&self.db_con)
+            .all(db)
             .await?;
 
         let index_metadata = Self::crate_index_model_to_index_metadata(crate_name, crate_indices)?;
@@ -587,16 +641,408 @@ impl Database {
         Ok(index_metadata)
     }
 
-    async fn update_etag(&self, crate_name: &str, crate_id: i64) -> DbResult<()> {
-        let etag = self.compute_etag(crate_name, crate_id).await?;
+    async fn update_etag(
+        db: &impl ConnectionTrait,
+        crate_name: &str,
+        crate_id: i64,
+    ) -> DbResult<()> {
+        let etag = Self::compute_etag(db, crate_name, crate_id).await?;
         let krate = krate::Entity::find()
             .filter(krate::Column::Id.eq(crate_id))
-            .one(&self.db_con)
+            .one(db)
             .await?
             .ok_or(DbError::CrateNotFound(crate_name.to_string()))?;
         let mut krate: krate::ActiveModel = krate.into();
         krate.e_tag = Set(etag);
-        krate.update(&self.db_con).await?;
+        krate.update(db).await?;
+        Ok(())
+    }
+
+    async fn insert_crate(
+        db: &impl ConnectionTrait,
+        pub_metadata: &PublishMetadata,
+        cksum: &str,
+        created: &DateTime<Utc>,
+        owner: &str,
+    ) -> DbResult<i64> {
+        let created = created.format(DB_DATE_FORMAT).to_string();
+        let normalized_name = NormalizedName::from(
+            OriginalName::try_from(&pub_metadata.name)
+                .map_err(|_| DbError::InvalidCrateName(pub_metadata.name.clone()))?,
+        );
+
+        let crate_id = match krate::Entity::find()
+            .filter(krate::Column::Name.eq(pub_metadata.name.clone()))
+            .one(db)
+            .await?
+        {
+            Some(krate) => {
+                let krate_id = krate.id;
+                let current_max_version = Version::try_from(&krate.max_version)
+                    .map_err(|_| DbError::InvalidVersion(krate.max_version.clone()))?;
+                let max_version = current_max_version.max(
+                    Version::try_from(&pub_metadata.vers)
+                        .map_err(|_| DbError::InvalidVersion(pub_metadata.vers.clone()))?,
+                );
+
+                let mut krate: krate::ActiveModel = krate.into();
+                krate.last_updated = Set(created.clone());
+                krate.max_version = Set(max_version.to_string());
+                krate.homepage = Set(pub_metadata.homepage.clone());
+                krate.description = Set(pub_metadata.description.clone());
+                krate.repository = Set(pub_metadata.repository.clone());
+                krate.e_tag = Set("".to_string()); // Set to empty string, as it can be computed, when the crate index is inserted
+                krate.update(db).await?;
+                krate_id
+            }
+            None => {
+                let krate = krate::ActiveModel {
+                    id: Default::default(),
+                    name: Set(normalized_name.to_string()),
+                    original_name: Set(pub_metadata.name.clone()),
+                    max_version: Set(pub_metadata.vers.clone()),
+                    last_updated: Set(created.clone()),
+                    total_downloads: Set(0),
+                    homepage: Set(pub_metadata.homepage.clone()),
+                    description: Set(pub_metadata.description.clone()),
+                    repository: Set(pub_metadata.repository.clone()),
+                    e_tag: Set("".to_string()), // Set to empty string, as it can be computed, when the crate index is inserted
+                };
+                let krate = krate.insert(db).await?;
+                krate.id
+            }
+        };
+
+        Self::add_owner_if_not_exists(db, owner, crate_id).await?;
+        Self::insert_crate_metadata(db, pub_metadata, &created, crate_id).await?;
+        Self::add_crate_index(db, pub_metadata, cksum, crate_id).await?;
+        Self::update_etag(db, &pub_metadata.name, crate_id).await?;
+        Self::update_crate_categories(db, pub_metadata, crate_id).await?;
+        Self::update_crate_keywords(db, pub_metadata, crate_id).await?;
+        Self::update_crate_authors(db, pub_metadata, crate_id).await?;
+        Ok(crate_id)
+    }
+
+    async fn insert_crate_metadata(
+        db: &impl ConnectionTrait,
+        pub_metadata: &PublishMetadata,
+        created: &str,
+        crate_id: i64,
+    ) -> DbResult<()> {
+        let cm = crate_meta::ActiveModel {
+            id: Default::default(),
+            version: Set(pub_metadata.vers.to_string()),
+            created: Set(created.to_string()),
+            downloads: Set(0),
+            crate_fk: Set(crate_id),
+            readme: Set(pub_metadata.readme.clone()),
+            license: Set(pub_metadata.license.clone()),
+            license_file: Set(pub_metadata.license_file.clone()),
+            documentation: Set(pub_metadata.documentation.clone()),
+        };
+
+        cm.insert(db).await?;
+
+        Ok(())
+    }
+
+    async fn insert_doc_queue(
+        db: &impl ConnectionTrait,
+        krate: &impl ToString,
+        version: &impl ToString,
+        path: &Path,
+    ) -> DbResult<()> {
+        let s = doc_queue::ActiveModel {
+            krate: Set(krate.to_string()),
+            version: Set(version.to_string()),
+            path: Set(path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        s.insert(db).await?;
+        Ok(())
+    }
+
+    fn proxy_rule_from_model(model: proxy_rule::Model) -> ProxyRule {
+        ProxyRule {
+            id: model.id,
+            pattern: model.pattern,
+            version_req: model.version_req,
+            // Only valid actions are written to the database. Block the crate if the action
+            // is unknown anyway.
+            action: model.action.parse().unwrap_or(PolicyAction::Pending),
+            created: model.created,
+        }
+    }
+
+    fn cached_crate_from_model(
+        krate: cratesio_crate::Model,
+        metas: Vec<cratesio_meta::Model>,
+    ) -> CachedCrate {
+        let mut versions: Vec<CachedVersion> = metas
+            .into_iter()
+            .map(|m| CachedVersion {
+                version: m.version,
+                downloads: m.downloads as u64,
+                last_access: m.last_access,
+                size: None,
+            })
+            .collect();
+        versions.sort_by_cached_key(|v| std::cmp::Reverse(Version::from_unchecked_str(&v.version)));
+
+        CachedCrate {
+            name: krate.original_name,
+            pinned: krate.pinned,
+            total_downloads: krate.total_downloads as u64,
+            versions,
+        }
+    }
+
+    /// Condition matching crates which contain `term` in their name, description, keywords
+    /// or categories. `term` has to be lowercase.
+    fn search_term_condition(term: &str) -> Condition {
+        let pattern = Self::contains_pattern(term);
+        let keyword_crates = Query::select()
+            .column(crate_keyword_to_crate::Column::CrateFk)
+            .from(crate_keyword_to_crate::Entity)
+            .inner_join(
+                crate_keyword::Entity,
+                Expr::col((crate_keyword::Entity, crate_keyword::Column::Id)).equals((
+                    crate_keyword_to_crate::Entity,
+                    crate_keyword_to_crate::Column::KeywordFk,
+                )),
+            )
+            .and_where(
+                Expr::expr(Func::lower(Expr::col((
+                    crate_keyword::Entity,
+                    crate_keyword::Column::Keyword,
+                ))))
+                .like(pattern.clone()),
+            )
+            .to_owned();
+        let category_crates = Query::select()
+            .column(crate_category_to_crate::Column::CrateFk)
+            .from(crate_category_to_crate::Entity)
+            .inner_join(
+                crate_category::Entity,
+                Expr::col((crate_category::Entity, crate_category::Column::Id)).equals((
+                    crate_category_to_crate::Entity,
+                    crate_category_to_crate::Column::CategoryFk,
+                )),
+            )
+            .and_where(
+                Expr::expr(Func::lower(Expr::col((
+                    crate_category::Entity,
+                    crate_category::Column::Category,
+                ))))
+                .like(pattern.clone()),
+            )
+            .to_owned();
+
+        Condition::any()
+            .add(
+                Expr::expr(Func::lower(Expr::col(krate::Column::OriginalName)))
+                    .like(pattern.clone()),
+            )
+            .add(Expr::expr(Func::lower(Expr::col(krate::Column::Description))).like(pattern))
+            .add(krate::Column::Id.in_subquery(keyword_crates))
+            .add(krate::Column::Id.in_subquery(category_crates))
+    }
+
+    /// LIKE pattern matching values that contain `term`. The LIKE wildcards `%` and `_`
+    /// in `term` are escaped, such that they match literally.
+    fn contains_pattern(term: &str) -> LikeExpr {
+        let mut escaped = String::with_capacity(term.len());
+        for c in term.chars() {
+            if matches!(c, '\\' | '%' | '_') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        LikeExpr::new(format!("%{}%", escaped)).escape('\\')
+    }
+
+    /// Relevance of a crate for the search terms. Matches in the name weigh more than
+    /// matches in the keywords and categories, which weigh more than matches in the description.
+    fn search_score(
+        terms: &[String],
+        name: &str,
+        description: Option<&str>,
+        keywords: &[String],
+        categories: &[String],
+    ) -> u64 {
+        let name = name.to_lowercase();
+        let description = description.map(str::to_lowercase).unwrap_or_default();
+
+        terms
+            .iter()
+            .map(|term| {
+                let term = term.as_str();
+                let name_score = if name == term {
+                    100
+                } else if name.starts_with(term) {
+                    50
+                } else if name.contains(term) {
+                    25
+                } else {
+                    0
+                };
+                let keyword_score = keywords
+                    .iter()
+                    .map(|k| match k.to_lowercase() {
+                        k if k == term => 20,
+                        k if k.contains(term) => 10,
+                        _ => 0,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                let category_score =
+                    match categories.iter().any(|c| c.to_lowercase().contains(term)) {
+                        true => 10,
+                        false => 0,
+                    };
+                let description_score = match description.contains(term) {
+                    true => 5,
+                    false => 0,
+                };
+                name_score + keyword_score + category_score + description_score
+            })
+            .sum()
+    }
+
+    /// Overviews of the crates in the given order.
+    async fn crate_overviews(&self, krates: Vec<krate::Model>) -> DbResult<Vec<CrateOverview>> {
+        let documentation: HashMap<(i64, String), Option<String>> = crate_meta::Entity::find()
+            .filter(crate_meta::Column::CrateFk.is_in(krates.iter().map(|k| k.id)))
+            .all(&self.db_con)
+            .await?
+            .into_iter()
+            .map(|m| ((m.crate_fk, m.version), m.documentation))
+            .collect();
+
+        Ok(krates
+            .into_iter()
+            .map(|k| CrateOverview {
+                documentation: documentation
+                    .get(&(k.id, k.max_version.clone()))
+                    .cloned()
+                    .flatten(),
+                original_name: k.original_name,
+                max_version: k.max_version,
+                last_updated: k.last_updated,
+                total_downloads: k.total_downloads,
+                description: k.description,
+            })
+            .collect())
+    }
+
+    /// Document of the crate for the full-text search index. Crates whose versions are all
+    /// yanked are not indexed.
+    async fn search_document(&self, krate: &krate::Model) -> DbResult<Option<SearchDocument>> {
+        let version = crate_index::Entity::find()
+            .filter(crate_index::Column::CrateFk.eq(krate.id))
+            .filter(crate_index::Column::Yanked.eq(false))
+            .all(&self.db_con)
+            .await?
+            .into_iter()
+            .map(|ci| ci.vers)
+            .max_by_key(|v| Version::from_unchecked_str(v));
+        let Some(version) = version else {
+            return Ok(None);
+        };
+
+        let readme = crate_meta::Entity::find()
+            .filter(crate_meta::Column::CrateFk.eq(krate.id))
+            .filter(crate_meta::Column::Version.eq(version))
+            .one(&self.db_con)
+            .await?
+            .and_then(|m| m.readme);
+        let keywords = krate
+            .find_related(crate_keyword_to_crate::Entity)
+            .find_also_related(crate_keyword::Entity)
+            .all(&self.db_con)
+            .await?
+            .into_iter()
+            .filter_map(|(_, k)| k.map(|k| k.keyword))
+            .collect();
+        let categories = krate
+            .find_related(crate_category_to_crate::Entity)
+            .find_also_related(crate_category::Entity)
+            .all(&self.db_con)
+            .await?
+            .into_iter()
+            .filter_map(|(_, c)| c.map(|c| c.category))
+            .collect();
+
+        Ok(Some(SearchDocument {
+            name: krate.name.clone(),
+            original_name: krate.original_name.clone(),
+            description: krate.description.clone(),
+            keywords,
+            categories,
+            readme,
+        }))
+    }
+
+    /// Updates the crate in the full-text search index, if there is one. The index can be
+    /// rebuilt, so failures are logged instead of failing the write to the database.
+    async fn update_search_index(&self, crate_name: &NormalizedName) {
+        let Some(index) = self.search_index.clone() else {
+            return;
+        };
+
+        let krate = krate::Entity::find()
+            .filter(krate::Column::Name.eq(crate_name.to_string()))
+            .one(&self.db_con)
+            .await;
+        let document = match krate {
+            Ok(Some(krate)) => self.search_document(&krate).await,
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.into()),
+        };
+        let name = crate_name.to_string();
+        // Writing to the index commits to disk, which must not block the async runtime.
+        let result = match document {
+            Ok(document) => tokio::task::spawn_blocking(move || match document {
+                Some(document) => index.upsert(&document),
+                None => index.remove(&name),
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r.map_err(|e| e.to_string())),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            error!(
+                "Failed to update search index of crate {}: {}",
+                crate_name, e
+            );
+        }
+    }
+
+    async fn set_cratesio_last_access(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+        last_access: Option<String>,
+    ) -> DbResult<()> {
+        let krate = self
+            .find_cratesio_crates()
+            .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
+            .one(&self.db_con)
+            .await?
+            .ok_or_else(|| DbError::CrateNotFound(crate_name.to_string()))?;
+
+        cratesio_meta::Entity::update_many()
+            .col_expr(cratesio_meta::Column::LastAccess, Expr::value(last_access))
+            .filter(
+                Cond::all()
+                    .add(cratesio_meta::Column::Version.eq(version.to_string()))
+                    .add(cratesio_meta::Column::CratesIoFk.eq(krate.id)),
+            )
+            .exec(&self.db_con)
+            .await?;
+
         Ok(())
     }
 }
@@ -722,7 +1168,8 @@ impl DbProvider for Database {
         crate_name: &NormalizedName,
         crate_version: &Version,
     ) -> DbResult<()> {
-        let krate: cratesio_crate::Model = cratesio_crate::Entity::find()
+        let krate: cratesio_crate::Model = self
+            .find_cratesio_crates()
             .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
             .one(&self.db_con)
             .await?
@@ -1197,15 +1644,7 @@ impl DbProvider for Database {
         version: &Version,
         path: &Path,
     ) -> DbResult<()> {
-        let s = doc_queue::ActiveModel {
-            krate: Set(krate.to_string()),
-            version: Set(version.to_string()),
-            path: Set(path.to_string_lossy().to_string()),
-            ..Default::default()
-        };
-
-        s.insert(&self.db_con).await?;
-        Ok(())
+        Self::insert_doc_queue(&self.db_con, krate, version, path).await
     }
 
     async fn delete_doc_queue(&self, id: i64) -> DbResult<()> {
@@ -1272,11 +1711,12 @@ impl DbProvider for Database {
                 c.max_version = Set(new_max_version.to_string());
             }
             // Update the ETag value of the crate index.
-            let etag = self.compute_etag(krate, crate_id).await?;
+            let etag = Self::compute_etag(&self.db_con, krate, crate_id).await?;
             c.e_tag = Set(etag);
             c.update(&self.db_con).await?;
         }
 
+        self.update_search_index(krate).await;
         Ok(())
     }
 
@@ -1351,28 +1791,174 @@ impl DbProvider for Database {
         Ok(result)
     }
 
-    async fn get_crate_overview_list(&self) -> DbResult<Vec<CrateOverview>> {
-        let stmt = Query::select()
-            .columns(vec![
-                CrateIden::OriginalName,
-                CrateIden::MaxVersion,
-                CrateIden::LastUpdated,
-                CrateIden::TotalDownloads,
-                CrateIden::Description,
-            ])
-            .column(CrateMetaIden::Documentation)
-            .from(CrateMetaIden::Table)
-            .inner_join(
-                CrateIden::Table,
-                Expr::col((CrateMetaIden::Table, CrateMetaIden::CrateFk))
-                    .equals((CrateIden::Table, CrateIden::Id)),
-            )
-            .and_where(
-                Expr::col((CrateMetaIden::Table, CrateMetaIden::Version))
-                    .equals((CrateIden::Table, CrateIden::MaxVersion)),
-            )
-            .order_by(CrateIden::OriginalName, Order::Asc)
-            .to_owned();
+    async fn search_crates(
+        &self,
+        query: &str,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<CrateOverviewPage> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Ok(CrateOverviewPage::default());
+        }
+
+        let condition = terms.iter().fold(Condition::any(), |c, t| {
+            c.add(Self::search_term_condition(t))
+        });
+        let krates = krate::Entity::find()
+            .filter(condition)
+            .all(&self.db_con)
+            .await?;
+        let ids: Vec<i64> = krates.iter().map(|k| k.id).collect();
+
+        let mut keywords: HashMap<i64, Vec<String>> = HashMap::new();
+        for (ktc, keyword) in crate_keyword_to_crate::Entity::find()
+            .filter(crate_keyword_to_crate::Column::CrateFk.is_in(ids.clone()))
+            .find_also_related(crate_keyword::Entity)
+            .all(&self.db_con)
+            .await?
+        {
+            if let Some(keyword) = keyword {
+                keywords
+                    .entry(ktc.crate_fk)
+                    .or_default()
+                    .push(keyword.keyword);
+            }
+        }
+        let mut categories: HashMap<i64, Vec<String>> = HashMap::new();
+        for (ctc, category) in crate_category_to_crate::Entity::find()
+            .filter(crate_category_to_crate::Column::CrateFk.is_in(ids))
+            .find_also_related(crate_category::Entity)
+            .all(&self.db_con)
+            .await?
+        {
+            if let Some(category) = category {
+                categories
+                    .entry(ctc.crate_fk)
+                    .or_default()
+                    .push(category.category);
+            }
+        }
+
+        let mut scored: Vec<(u64, krate::Model)> = krates
+            .into_iter()
+            .map(|k| {
+                let score = Self::search_score(
+                    &terms,
+                    &k.original_name,
+                    k.description.as_deref(),
+                    keywords.get(&k.id).map(Vec::as_slice).unwrap_or_default(),
+                    categories.get(&k.id).map(Vec::as_slice).unwrap_or_default(),
+                );
+                (score, k)
+            })
+            .collect();
+        scored.sort_by(|(s1, k1), (s2, k2)| {
+            s2.cmp(s1)
+                .then(k2.total_downloads.cmp(&k1.total_downloads))
+                .then(k1.original_name.cmp(&k2.original_name))
+        });
+
+        let total = scored.len() as u64;
+        let page: Vec<krate::Model> = scored
+            .into_iter()
+            .skip(offset.try_into().unwrap_or(usize::MAX))
+            .take(limit.try_into().unwrap_or(usize::MAX))
+            .map(|(_, k)| k)
+            .collect();
+        let crates = self.crate_overviews(page).await?;
+
+        Ok(CrateOverviewPage { crates, total })
+    }
+
+    async fn search_full_text(
+        &self,
+        query: &str,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<CrateSearchHitPage> {
+        let index = self
+            .search_index
+            .as_ref()
+            .ok_or_else(|| DbError::SearchIndexError("Full-text search is disabled".into()))?;
+        let result = index
+            .search(
+                query,
+                offset.try_into().unwrap_or(usize::MAX),
+                limit.try_into().unwrap_or(usize::MAX),
+            )
+            .map_err(|e| DbError::SearchIndexError(e.to_string()))?;
+
+        let mut krates: HashMap<String, krate::Model> = krate::Entity::find()
+            .filter(krate::Column::Name.is_in(result.hits.iter().map(|h| h.name.clone())))
+            .all(&self.db_con)
+            .await?
+            .into_iter()
+            .map(|k| (k.name.clone(), k))
+            .collect();
+        // Crates deleted since the last update of the index are skipped.
+        let (found, snippets): (Vec<krate::Model>, Vec<String>) = result
+            .hits
+            .into_iter()
+            .filter_map(|h| krates.remove(&h.name).map(|k| (k, h.snippet)))
+            .unzip();
+
+        let hits = self
+            .crate_overviews(found)
+            .await?
+            .into_iter()
+            .zip(snippets)
+            .map(|(overview, snippet)| CrateSearchHit { overview, snippet })
+            .collect();
+        Ok(CrateSearchHitPage {
+            hits,
+            total: result.total as u64,
+        })
+    }
+
+    async fn rebuild_search_index(&self) -> DbResult<u64> {
+        let index = self
+            .search_index
+            .clone()
+            .ok_or_else(|| DbError::SearchIndexError("Full-text search is disabled".into()))?;
+
+        let mut documents = Vec::new();
+        for krate in krate::Entity::find().all(&self.db_con).await? {
+            if let Some(document) = self.search_document(&krate).await? {
+                documents.push(document);
+            }
+        }
+        let count = documents.len() as u64;
+        tokio::task::spawn_blocking(move || index.rebuild(&documents))
+            .await
+            .map_err(|e| DbError::SearchIndexError(e.to_string()))?
+            .map_err(|e| DbError::SearchIndexError(e.to_string()))?;
+
+        Ok(count)
+    }
+
+    async fn get_crate_overview_list(&self) -> DbResult<Vec<CrateOverview>> {
+        let stmt = Query::select()
+            .columns(vec![
+                CrateIden::OriginalName,
+                CrateIden::MaxVersion,
+                CrateIden::LastUpdated,
+                CrateIden::TotalDownloads,
+                CrateIden::Description,
+            ])
+            .column(CrateMetaIden::Documentation)
+            .from(CrateMetaIden::Table)
+            .inner_join(
+                CrateIden::Table,
+                Expr::col((CrateMetaIden::Table, CrateMetaIden::CrateFk))
+                    .equals((CrateIden::Table, CrateIden::Id)),
+            )
+            .and_where(
+                Expr::col((CrateMetaIden::Table, CrateMetaIden::Version))
+                    .equals((CrateIden::Table, CrateIden::MaxVersion)),
+            )
+            .order_by(CrateIden::OriginalName, Order::Asc)
+            .to_owned();
 
         let builder = self.db_con.get_database_backend();
         let result = CrateOverview::find_by_statement(builder.build(&stmt))
@@ -1516,62 +2102,50 @@ impl DbProvider for Database {
         created: &DateTime<Utc>,
         owner: &str,
     ) -> DbResult<i64> {
-        let created = created.format(DB_DATE_FORMAT).to_string();
-        let normalized_name = NormalizedName::from(
-            OriginalName::try_from(&pub_metadata.name)
-                .map_err(|_| DbError::InvalidCrateName(pub_metadata.name.clone()))?,
-        );
+        let txn = self.db_con.begin().await?;
+        let crate_id = Self::insert_crate(&txn, pub_metadata, cksum, created, owner).await?;
+        txn.commit().await?;
 
-        let crate_id = match krate::Entity::find()
-            .filter(krate::Column::Name.eq(pub_metadata.name.clone()))
-            .one(&self.db_con)
-            .await?
-        {
-            Some(krate) => {
-                let krate_id = krate.id;
-                let current_max_version = Version::try_from(&krate.max_version)
-                    .map_err(|_| DbError::InvalidVersion(krate.max_version.clone()))?;
-                let max_version = current_max_version.max(
-                    Version::try_from(&pub_metadata.vers)
-                        .map_err(|_| DbError::InvalidVersion(pub_metadata.vers.clone()))?,
-                );
-
-                let mut krate: krate::ActiveModel = krate.into();
-                krate.last_updated = Set(created.clone());
-                krate.max_version = Set(max_version.to_string());
-                krate.homepage = Set(pub_metadata.homepage.clone());
-                krate.description = Set(pub_metadata.description.clone());
-                krate.repository = Set(pub_metadata.repository.clone());
-                krate.e_tag = Set("".to_string()); // Set to empty string, as it can be computed, when the crate index is inserted
-                krate.update(&self.db_con).await?;
-                krate_id
-            }
-            None => {
-                let krate = krate::ActiveModel {
-                    id: Default::default(),
-                    name: Set(normalized_name.to_string()),
-                    original_name: Set(pub_metadata.name.clone()),
-                    max_version: Set(pub_metadata.vers.clone()),
-                    last_updated: Set(created.clone()),
-                    total_downloads: Set(0),
-                    homepage: Set(pub_metadata.homepage.clone()),
-                    description: Set(pub_metadata.description.clone()),
-                    repository: Set(pub_metadata.repository.clone()),
-                    e_tag: Set("".to_string()), // Set to empty string, as it can be computed, when the crate index is inserted
-                };
-                let krate = krate.insert(&self.db_con).await?;
-                krate.id
-            }
-        };
+        let name = OriginalName::from_unchecked_str(pub_metadata.name.clone());
+        self.update_search_index(&name.to_normalized()).await;
+        Ok(crate_id)
+    }
 
-        self.add_owner_if_not_exists(owner, crate_id).await?;
-        self.add_crate_metadata(pub_metadata, &created, crate_id)
+    async fn publish_crate(
+        &self,
+        pub_metadata: &PublishMetadata,
+        cksum: &str,
+        created: &DateTime<Utc>,
+        owner: &str,
+        doc_queue_path: Option<&Path>,
+    ) -> DbResult<i64> {
+        let txn = self.db_con.begin().await?;
+
+        // Checked again inside the transaction, as the same version may have been
+        // published concurrently since the caller checked it.
+        let normalized_name = OriginalName::try_from(&pub_metadata.name)
+            .map_err(|_| DbError::InvalidCrateName(pub_metadata.name.clone()))?
+            .to_normalized();
+        let existing = krate::Entity::find()
+            .filter(krate::Column::Name.eq(normalized_name.to_string()))
+            .find_also_related(crate_meta::Entity)
+            .filter(crate_meta::Column::Version.eq(pub_metadata.vers.clone()))
+            .one(&txn)
             .await?;
-        self.add_crate_index(pub_metadata, cksum, crate_id).await?;
-        self.update_etag(&pub_metadata.name, crate_id).await?;
-        self.update_crate_categories(pub_metadata, crate_id).await?;
-        self.update_crate_keywords(pub_metadata, crate_id).await?;
-        self.update_crate_authors(pub_metadata, crate_id).await?;
+        if existing.is_some() {
+            return Err(DbError::CrateVersionExists(
+                pub_metadata.name.clone(),
+                pub_metadata.vers.clone(),
+            ));
+        }
+
+        let crate_id = Self::insert_crate(&txn, pub_metadata, cksum, created, owner).await?;
+        if let Some(path) = doc_queue_path {
+            Self::insert_doc_queue(&txn, &pub_metadata.name, &pub_metadata.vers, path).await?;
+        }
+
+        txn.commit().await?;
+        self.update_search_index(&normalized_name).await;
         Ok(crate_id)
     }
 
@@ -1604,21 +2178,7 @@ impl DbProvider for Database {
         created: &str,
         crate_id: i64,
     ) -> DbResult<()> {
-        let cm = crate_meta::ActiveModel {
-            id: Default::default(),
-            version: Set(pub_metadata.vers.to_string()),
-            created: Set(created.to_string()),
-            downloads: Set(0),
-            crate_fk: Set(crate_id),
-            readme: Set(pub_metadata.readme.clone()),
-            license: Set(pub_metadata.license.clone()),
-            license_file: Set(pub_metadata.license_file.clone()),
-            documentation: Set(pub_metadata.documentation.clone()),
-        };
-
-        cm.insert(&self.db_con).await?;
-
-        Ok(())
+        Self::insert_crate_metadata(&self.db_con, pub_metadata, created, crate_id).await
     }
 
     async fn get_prefetch_data(&self, crate_name: &str) -> DbResult<Prefetch> {
@@ -1650,7 +2210,8 @@ impl DbProvider for Database {
         etag: Option<String>,
         last_modified: Option<String>,
     ) -> DbResult<PrefetchState> {
-        let krate = match cratesio_crate::Entity::find()
+        let krate = match self
+            .find_cratesio_crates()
             .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
             .one(&self.db_con)
             .await?
@@ -1696,7 +2257,8 @@ impl DbProvider for Database {
         indices: &[IndexMetadata],
     ) -> DbResult<Prefetch> {
         let normalized_name = crate_name.to_normalized();
-        let krate = match cratesio_crate::Entity::find()
+        let krate = match self
+            .find_cratesio_crates()
             .filter(cratesio_crate::Column::Name.eq(normalized_name.to_string()))
             .one(&self.db_con)
             .await?
@@ -1716,6 +2278,8 @@ impl DbProvider for Database {
                     e_tag: Set(etag.to_string()),
                     last_modified: Set(last_modified.to_string()),
                     total_downloads: Set(0),
+                    upstream: Set(self.upstream.clone()),
+                    ..Default::default()
                 };
                 krate.insert(&self.db_con).await?
             }
@@ -1771,6 +2335,7 @@ impl DbProvider for Database {
                     version: Set(index.vers.clone()),
                     downloads: Set(0),
                     crates_io_fk: Set(krate.id),
+                    last_access: Set(None),
                 };
 
                 meta.insert(&self.db_con).await?;
@@ -1785,7 +2350,7 @@ impl DbProvider for Database {
     }
 
     async fn get_cratesio_index_update_list(&self) -> DbResult<Vec<CratesioPrefetchMsg>> {
-        let crates = cratesio_crate::Entity::find().all(&self.db_con).await?;
+        let crates = self.find_cratesio_crates().all(&self.db_con).await?;
         let msgs = crates
             .into_iter()
             .map(|krate| {
@@ -1816,6 +2381,7 @@ impl DbProvider for Database {
         ci.yanked = Set(false);
         ci.save(&self.db_con).await?;
 
+        self.update_search_index(crate_name).await;
         Ok(())
     }
 
@@ -1836,6 +2402,469 @@ impl DbProvider for Database {
         ci.yanked = Set(true);
         ci.save(&self.db_con).await?;
 
+        self.update_search_index(crate_name).await;
+        Ok(())
+    }
+
+    async fn get_crate_cksum(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+    ) -> DbResult<Option<String>> {
+        let cksum = crate_index::Entity::find()
+            .filter(crate_index::Column::Name.eq(crate_name.to_string()))
+            .filter(crate_index::Column::Vers.eq(version.to_string()))
+            .one(&self.db_con)
+            .await?
+            .map(|ci| ci.cksum);
+
+        Ok(cksum)
+    }
+
+    async fn get_cratesio_crate_cksum(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+    ) -> DbResult<Option<String>> {
+        let cksum = cratesio_index::Entity::find()
+            .join(
+                JoinType::InnerJoin,
+                cratesio_index::Relation::CratesioCrate.def(),
+            )
+            .filter(cratesio_crate::Column::Upstream.eq(self.upstream.as_str()))
+            .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
+            .filter(cratesio_index::Column::Vers.eq(version.to_string()))
+            .one(&self.db_con)
+            .await?
+            .map(|ci| ci.cksum);
+
+        Ok(cksum)
+    }
+
+    async fn get_indexed_crates(&self) -> DbResult<Vec<IndexedCrate>> {
+        let indices = crate_index::Entity::find()
+            .order_by_asc(crate_index::Column::Id)
+            .all(&self.db_con)
+            .await?;
+
+        Ok(indices
+            .into_iter()
+            .map(|ci| IndexedCrate {
+                name: ci.name,
+                version: ci.vers,
+                cksum: ci.cksum,
+            })
+            .collect())
+    }
+
+    async fn get_cratesio_stored_crates(&self) -> DbResult<Vec<IndexedCrate>> {
+        let indices = cratesio_index::Entity::find()
+            .join(
+                JoinType::InnerJoin,
+                cratesio_index::Relation::CratesioCrate.def(),
+            )
+            .filter(cratesio_crate::Column::Upstream.eq(self.upstream.as_str()))
+            .order_by_asc(cratesio_index::Column::Id)
+            .all(&self.db_con)
+            .await?;
+
+        // The last access is only set while the crate file is cached. All other versions
+        // are only known from the index of the upstream.
+        let stored: HashSet<(i64, String)> = cratesio_meta::Entity::find()
+            .join(
+                JoinType::InnerJoin,
+                cratesio_meta::Relation::CratesioCrate.def(),
+            )
+            .filter(cratesio_crate::Column::Upstream.eq(self.upstream.as_str()))
+            .filter(cratesio_meta::Column::LastAccess.is_not_null())
+            .all(&self.db_con)
+            .await?
+            .into_iter()
+            .map(|m| (m.crates_io_fk, m.version))
+            .collect();
+
+        Ok(indices
+            .into_iter()
+            .filter(|ci| stored.contains(&(ci.crates_io_fk, ci.vers.clone())))
+            .map(|ci| IndexedCrate {
+                name: ci.name,
+                version: ci.vers,
+                cksum: ci.cksum,
+            })
+            .collect())
+    }
+
+    async fn update_cratesio_last_access(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+        last_access: &DateTime<Utc>,
+    ) -> DbResult<()> {
+        self.set_cratesio_last_access(
+            crate_name,
+            version,
+            Some(last_access.format(DB_DATE_FORMAT).to_string()),
+        )
+        .await
+    }
+
+    async fn clear_cratesio_last_access(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+    ) -> DbResult<()> {
+        self.set_cratesio_last_access(crate_name, version, None)
+            .await
+    }
+
+    async fn get_least_recently_used_cratesio_crates(
+        &self,
+        limit: u64,
+    ) -> DbResult<Vec<(OriginalName, Version)>> {
+        let metas = cratesio_meta::Entity::find()
+            .find_also_related(cratesio_crate::Entity)
+            .filter(cratesio_crate::Column::Upstream.eq(self.upstream.as_str()))
+            .filter(cratesio_crate::Column::Pinned.eq(false))
+            .filter(cratesio_meta::Column::LastAccess.is_not_null())
+            .order_by_asc(cratesio_meta::Column::LastAccess)
+            .limit(limit)
+            .all(&self.db_con)
+            .await?;
+
+        Ok(metas
+            .into_iter()
+            .filter_map(|(meta, krate)| {
+                // SAFETY: Unchecked is ok, as only valid crate names and versions are inserted into the database
+                krate.map(|k| {
+                    (
+                        OriginalName::from_unchecked_str(k.original_name),
+                        Version::from_unchecked_str(&meta.version),
+                    )
+                })
+            })
+            .collect())
+    }
+
+    async fn update_cratesio_metadata(
+        &self,
+        crate_name: &NormalizedName,
+        metadata: &CratesIoMetadata,
+        updated: &DateTime<Utc>,
+    ) -> DbResult<()> {
+        let krate = self
+            .find_cratesio_crates()
+            .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
+            .one(&self.db_con)
+            .await?
+            .ok_or_else(|| DbError::CrateNotFound(crate_name.to_string()))?;
+        let keywords = serde_json::to_value(&metadata.keywords)
+            .map_err(|e| DbError::FailedToConvertToJson(e.to_string()))?;
+
+        let mut krate: cratesio_crate::ActiveModel = krate.into();
+        krate.description = Set(metadata.description.clone());
+        krate.homepage = Set(metadata.homepage.clone());
+        krate.repository = Set(metadata.repository.clone());
+        krate.documentation = Set(metadata.documentation.clone());
+        krate.keywords = Set(Some(keywords));
+        krate.upstream_downloads = Set(metadata.downloads as i64);
+        krate.metadata_updated = Set(Some(updated.format(DB_DATE_FORMAT).to_string()));
+        krate.update(&self.db_con).await?;
+
         Ok(())
     }
+
+    async fn get_cratesio_metadata(
+        &self,
+        crate_name: &NormalizedName,
+    ) -> DbResult<Option<(CratesIoMetadata, DateTime<Utc>)>> {
+        let krate = self
+            .find_cratesio_crates()
+            .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
+            .one(&self.db_con)
+            .await?;
+
+        // Crates that were cached before the metadata was stored have no update time.
+        let Some((krate, updated)) = krate.and_then(|k| {
+            let updated = k
+                .metadata_updated
+                .as_deref()
+                .and_then(|u| NaiveDateTime::parse_from_str(u, DB_DATE_FORMAT).ok())?;
+            Some((k, updated.and_utc()))
+        }) else {
+            return Ok(None);
+        };
+
+        let keywords = match krate.keywords {
+            Some(keywords) => serde_json::from_value(keywords)
+                .map_err(|e| DbError::FailedToConvertFromJson(e.to_string()))?,
+            None => Vec::new(),
+        };
+
+        Ok(Some((
+            CratesIoMetadata {
+                description: krate.description,
+                homepage: krate.homepage,
+                repository: krate.repository,
+                documentation: krate.documentation,
+                keywords,
+                downloads: krate.upstream_downloads as u64,
+            },
+            updated,
+        )))
+    }
+
+    async fn search_cratesio_crates(
+        &self,
+        contains: &str,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<SearchResult> {
+        let query = self.find_cratesio_crates().filter(
+            Expr::col((cratesio_crate::Entity, cratesio_crate::Column::Name))
+                .like(Self::contains_pattern(&contains.to_lowercase())),
+        );
+        let total = query.clone().count(&self.db_con).await?;
+        let crates = query
+            .order_by_asc(cratesio_crate::Column::Name)
+            .offset(offset)
+            .limit(limit)
+            .all(&self.db_con)
+            .await?;
+
+        // Max. versions of all crates on the page, loaded at once.
+        let mut max_versions: HashMap<i64, Version> = HashMap::new();
+        for ci in cratesio_index::Entity::find()
+            .filter(cratesio_index::Column::CratesIoFk.is_in(crates.iter().map(|c| c.id)))
+            .filter(cratesio_index::Column::Yanked.eq(false))
+            .all(&self.db_con)
+            .await?
+        {
+            let Ok(version) = Version::try_from(&ci.vers) else {
+                continue;
+            };
+            match max_versions.get(&ci.crates_io_fk) {
+                Some(max_version) if *max_version >= version => {}
+                _ => {
+                    max_versions.insert(ci.crates_io_fk, version);
+                }
+            }
+        }
+
+        let crates = crates
+            .into_iter()
+            .map(|krate| search_result::Crate {
+                max_version: max_versions
+                    .get(&krate.id)
+                    .cloned()
+                    .unwrap_or_default()
+                    .to_string(),
+                name: krate.original_name,
+                description: krate
+                    .description
+                    .unwrap_or_else(|| "No description set".to_string()),
+            })
+            .collect();
+
+        Ok(SearchResult {
+            crates,
+            meta: search_result::Meta {
+                total: total as i32,
+            },
+        })
+    }
+
+    async fn get_cached_cratesio_crates(
+        &self,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<Vec<CachedCrate>> {
+        let crates = self
+            .find_cratesio_crates()
+            .order_by_asc(cratesio_crate::Column::Name)
+            .offset(offset)
+            .limit(limit)
+            .all(&self.db_con)
+            .await?;
+
+        let mut result = Vec::with_capacity(crates.len());
+        for krate in crates {
+            // The last access is only set while the crate file is cached.
+            let metas = krate
+                .find_related(cratesio_meta::Entity)
+                .filter(cratesio_meta::Column::LastAccess.is_not_null())
+                .all(&self.db_con)
+                .await?;
+            result.push(Self::cached_crate_from_model(krate, metas));
+        }
+
+        Ok(result)
+    }
+
+    async fn get_cached_cratesio_crate(
+        &self,
+        crate_name: &NormalizedName,
+    ) -> DbResult<Option<CachedCrate>> {
+        let Some(krate) = self
+            .find_cratesio_crates()
+            .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
+            .one(&self.db_con)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let metas = krate
+            .find_related(cratesio_meta::Entity)
+            .all(&self.db_con)
+            .await?;
+        Ok(Some(Self::cached_crate_from_model(krate, metas)))
+    }
+
+    async fn delete_cratesio_crate(&self, crate_name: &NormalizedName) -> DbResult<Vec<Version>> {
+        let krate = self
+            .find_cratesio_crates()
+            .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
+            .one(&self.db_con)
+            .await?
+            .ok_or_else(|| DbError::CrateNotFound(crate_name.to_string()))?;
+
+        let versions = krate
+            .find_related(cratesio_meta::Entity)
+            .all(&self.db_con)
+            .await?
+            .into_iter()
+            // SAFETY: Unchecked is ok, as only valid versions are inserted into the database
+            .map(|m| Version::from_unchecked_str(&m.version))
+            .collect();
+
+        cratesio_index::Entity::delete_many()
+            .filter(cratesio_index::Column::CratesIoFk.eq(krate.id))
+            .exec(&self.db_con)
+            .await?;
+        cratesio_meta::Entity::delete_many()
+            .filter(cratesio_meta::Column::CratesIoFk.eq(krate.id))
+            .exec(&self.db_con)
+            .await?;
+        krate.delete(&self.db_con).await?;
+
+        Ok(versions)
+    }
+
+    async fn set_cratesio_crate_pinned(
+        &self,
+        crate_name: &NormalizedName,
+        pinned: bool,
+    ) -> DbResult<()> {
+        let krate = self
+            .find_cratesio_crates()
+            .filter(cratesio_crate::Column::Name.eq(crate_name.to_string()))
+            .one(&self.db_con)
+            .await?
+            .ok_or_else(|| DbError::CrateNotFound(crate_name.to_string()))?;
+
+        let mut krate: cratesio_crate::ActiveModel = krate.into();
+        krate.pinned = Set(pinned);
+        krate.update(&self.db_con).await?;
+
+        Ok(())
+    }
+
+    async fn get_proxy_rules(&self) -> DbResult<Vec<ProxyRule>> {
+        let rules = proxy_rule::Entity::find()
+            .order_by_asc(proxy_rule::Column::Id)
+            .all(&self.db_con)
+            .await?;
+
+        Ok(rules.into_iter().map(Self::proxy_rule_from_model).collect())
+    }
+
+    async fn add_proxy_rule(
+        &self,
+        pattern: &str,
+        version_req: &str,
+        action: PolicyAction,
+        created: &DateTime<Utc>,
+    ) -> DbResult<ProxyRule> {
+        let exists = proxy_rule::Entity::find()
+            .filter(proxy_rule::Column::Pattern.eq(pattern))
+            .filter(proxy_rule::Column::VersionReq.eq(version_req))
+            .one(&self.db_con)
+            .await?
+            .is_some();
+        if exists {
+            return Err(DbError::ProxyRuleExists(
+                pattern.to_string(),
+                version_req.to_string(),
+            ));
+        }
+
+        let rule = proxy_rule::ActiveModel {
+            pattern: Set(pattern.to_string()),
+            version_req: Set(version_req.to_string()),
+            action: Set(action.to_string()),
+            created: Set(created.format(DB_DATE_FORMAT).to_string()),
+            ..Default::default()
+        };
+
+        let rule = rule.insert(&self.db_con).await?;
+        Ok(Self::proxy_rule_from_model(rule))
+    }
+
+    async fn update_proxy_rule_action(&self, id: i64, action: PolicyAction) -> DbResult<()> {
+        let mut rule: proxy_rule::ActiveModel = proxy_rule::Entity::find_by_id(id)
+            .one(&self.db_con)
+            .await?
+            .ok_or(DbError::ProxyRuleNotFound(id))?
+            .into();
+
+        rule.action = Set(action.to_string());
+        rule.update(&self.db_con).await?;
+        Ok(())
+    }
+
+    async fn delete_proxy_rule(&self, id: i64) -> DbResult<()> {
+        let result = proxy_rule::Entity::delete_by_id(id)
+            .exec(&self.db_con)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DbError::ProxyRuleNotFound(id));
+        }
+        Ok(())
+    }
+
+    async fn add_security_event(
+        &self,
+        kind: SecurityEventKind,
+        crate_name: &NormalizedName,
+        version: &Version,
+        detail: &str,
+        created: &DateTime<Utc>,
+    ) -> DbResult<()> {
+        let event = security_event::ActiveModel {
+            kind: Set(kind.to_string()),
+            upstream: Set(self.upstream.clone()),
+            crate_name: Set(crate_name.to_string()),
+            version: Set(version.to_string()),
+            detail: Set(detail.to_string()),
+            created: Set(created.format(DB_DATE_FORMAT).to_string()),
+            ..Default::default()
+        };
+
+        event.insert(&self.db_con).await?;
+        Ok(())
+    }
+
+    async fn get_security_events(&self, limit: u64) -> DbResult<Vec<SecurityEvent>> {
+        let events = security_event::Entity::find()
+            .order_by_desc(security_event::Column::Id)
+            .limit(limit)
+            .all(&self.db_con)
+            .await?;
+
+        // Events of an unknown kind, e.g. written by a newer version, are skipped.
+        Ok(events
+            .into_iter()
+            .filter_map(|e| SecurityEvent::try_from(e).ok())
+            .collect())
+    }
 }
@@ -48,6 +48,14 @@ pub enum DbError {
     CrateIndexNotFound(String, String),
     #[error("Invalid crate name {0}")]
     InvalidCrateName(String),
+    #[error("Crate with version already exists: {0}-{1}")]
+    CrateVersionExists(String, String),
     #[error("Crates.io index data is missing for crate {0}")]
     MissingCratesIoIndexData(String),
+    #[error("Proxy rule not found with id: {0}")]
+    ProxyRuleNotFound(i64),
+    #[error("Proxy rule already exists: {0} {1}")]
+    ProxyRuleExists(String, String),
+    #[error("Full-text search index error: {0}")]
+    SearchIndexError(String),
 }
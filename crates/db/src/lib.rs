@@ -8,6 +8,7 @@ pub mod error;
 mod krate;
 pub mod password;
 pub mod provider;
+mod security_event;
 mod tables;
 mod user;
 
@@ -24,4 +25,5 @@ pub use doc_queue_entry::DocQueueEntry;
 pub use krate::Crate;
 pub use provider::mock;
 pub use provider::DbProvider;
+pub use security_event::{SecurityEvent, SecurityEventKind};
 pub use user::User;
@@ -1,13 +1,21 @@
-use crate::{crate_meta, error::DbError, AuthToken, CrateSummary, DocQueueEntry, User};
+use crate::{
+    crate_meta, error::DbError, AuthToken, CrateSummary, DocQueueEntry, SecurityEvent,
+    SecurityEventKind, User,
+};
 use chrono::{DateTime, Utc};
+use common::cached_crate::CachedCrate;
 use common::crate_data::CrateData;
-use common::crate_overview::CrateOverview;
+use common::crate_overview::{CrateOverview, CrateOverviewPage, CrateSearchHitPage};
+use common::cratesio_metadata::CratesIoMetadata;
 use common::cratesio_prefetch_msg::CratesioPrefetchMsg;
 use common::index_metadata::IndexMetadata;
+use common::indexed_crate::IndexedCrate;
 use common::normalized_name::NormalizedName;
 use common::original_name::OriginalName;
 use common::prefetch::Prefetch;
+use common::proxy_policy::{PolicyAction, ProxyRule};
 use common::publish_metadata::PublishMetadata;
+use common::search_result::SearchResult;
 use common::version::Version;
 use crate_meta::CrateMeta;
 use sea_orm::prelude::async_trait::async_trait;
@@ -76,6 +84,24 @@ pub trait DbProvider: Send + Sync {
     async fn get_crate_meta_list(&self, crate_name: &NormalizedName) -> DbResult<Vec<CrateMeta>>;
     async fn update_last_updated(&self, id: i64, last_updated: &DateTime<Utc>) -> DbResult<()>;
     async fn search_in_crate_name(&self, contains: &str) -> DbResult<Vec<CrateOverview>>;
+    /// Searches the names, descriptions, keywords and categories of the crates and returns
+    /// a page of the matching crates, ordered by relevance.
+    async fn search_crates(
+        &self,
+        query: &str,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<CrateOverviewPage>;
+    /// Searches the full-text index of the crates with a query in the tantivy query language.
+    async fn search_full_text(
+        &self,
+        query: &str,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<CrateSearchHitPage>;
+    /// Recreates the full-text search index from the database. Returns the number of
+    /// indexed crates.
+    async fn rebuild_search_index(&self) -> DbResult<u64>;
     async fn get_crate_overview_list(&self) -> DbResult<Vec<CrateOverview>>;
     async fn get_crate_data(&self, crate_name: &NormalizedName) -> DbResult<CrateData>;
     async fn add_crate(
@@ -85,6 +111,14 @@ pub trait DbProvider: Send + Sync {
         created: &DateTime<Utc>,
         owner: &str,
     ) -> DbResult<i64>;
+    async fn publish_crate(
+        &self,
+        pub_metadata: &PublishMetadata,
+        cksum: &str,
+        created: &DateTime<Utc>,
+        owner: &str,
+        doc_queue_path: Option<&Path>,
+    ) -> DbResult<i64>;
     async fn update_docs_link(
         &self,
         crate_name: &NormalizedName,
@@ -115,6 +149,85 @@ pub trait DbProvider: Send + Sync {
     async fn get_cratesio_index_update_list(&self) -> DbResult<Vec<CratesioPrefetchMsg>>;
     async fn unyank_crate(&self, crate_name: &NormalizedName, version: &Version) -> DbResult<()>;
     async fn yank_crate(&self, crate_name: &NormalizedName, version: &Version) -> DbResult<()>;
+    async fn get_crate_cksum(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+    ) -> DbResult<Option<String>>;
+    async fn get_cratesio_crate_cksum(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+    ) -> DbResult<Option<String>>;
+    async fn get_indexed_crates(&self) -> DbResult<Vec<IndexedCrate>>;
+    /// Versions of the cached crates whose crate file is stored, i.e. was downloaded
+    /// and not evicted since.
+    async fn get_cratesio_stored_crates(&self) -> DbResult<Vec<IndexedCrate>>;
+    async fn update_cratesio_last_access(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+        last_access: &DateTime<Utc>,
+    ) -> DbResult<()>;
+    async fn clear_cratesio_last_access(
+        &self,
+        crate_name: &NormalizedName,
+        version: &Version,
+    ) -> DbResult<()>;
+    async fn get_least_recently_used_cratesio_crates(
+        &self,
+        limit: u64,
+    ) -> DbResult<Vec<(OriginalName, Version)>>;
+    async fn update_cratesio_metadata(
+        &self,
+        crate_name: &NormalizedName,
+        metadata: &CratesIoMetadata,
+        updated: &DateTime<Utc>,
+    ) -> DbResult<()>;
+    async fn get_cratesio_metadata(
+        &self,
+        crate_name: &NormalizedName,
+    ) -> DbResult<Option<(CratesIoMetadata, DateTime<Utc>)>>;
+    async fn search_cratesio_crates(
+        &self,
+        contains: &str,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<SearchResult>;
+    async fn get_cached_cratesio_crates(
+        &self,
+        offset: u64,
+        limit: u64,
+    ) -> DbResult<Vec<CachedCrate>>;
+    async fn get_cached_cratesio_crate(
+        &self,
+        crate_name: &NormalizedName,
+    ) -> DbResult<Option<CachedCrate>>;
+    async fn delete_cratesio_crate(&self, crate_name: &NormalizedName) -> DbResult<Vec<Version>>;
+    async fn set_cratesio_crate_pinned(
+        &self,
+        crate_name: &NormalizedName,
+        pinned: bool,
+    ) -> DbResult<()>;
+    async fn get_proxy_rules(&self) -> DbResult<Vec<ProxyRule>>;
+    async fn add_proxy_rule(
+        &self,
+        pattern: &str,
+        version_req: &str,
+        action: PolicyAction,
+        created: &DateTime<Utc>,
+    ) -> DbResult<ProxyRule>;
+    async fn update_proxy_rule_action(&self, id: i64, action: PolicyAction) -> DbResult<()>;
+    async fn delete_proxy_rule(&self, id: i64) -> DbResult<()>;
+    async fn add_security_event(
+        &self,
+        kind: SecurityEventKind,
+        crate_name: &NormalizedName,
+        version: &Version,
+        detail: &str,
+        created: &DateTime<Utc>,
+    ) -> DbResult<()>;
+    async fn get_security_events(&self, limit: u64) -> DbResult<Vec<SecurityEvent>>;
 }
 
 pub mod mock {
@@ -297,6 +410,18 @@ pub mod mock {
                 unimplemented!()
             }
 
+            async fn search_crates(&self, query: &str, offset: u64, limit: u64) -> DbResult<CrateOverviewPage> {
+                unimplemented!()
+            }
+
+            async fn search_full_text(&self, query: &str, offset: u64, limit: u64) -> DbResult<CrateSearchHitPage> {
+                unimplemented!()
+            }
+
+            async fn rebuild_search_index(&self) -> DbResult<u64> {
+                unimplemented!()
+            }
+
             async fn get_crate_overview_list(&self) -> DbResult<Vec<CrateOverview >> {
                 unimplemented!()
             }
@@ -309,6 +434,10 @@ pub mod mock {
                 unimplemented!()
             }
 
+            async fn publish_crate(&self, pub_metadata: &PublishMetadata, sha256: &str, created: &DateTime<Utc>, owner: &str, doc_queue_path: Option<&Path>) -> DbResult<i64> {
+                unimplemented!()
+            }
+
             async fn update_docs_link(&self, crate_name: &NormalizedName, version: &Version, docs_link: &str) -> DbResult<()> {
                 unimplemented!()
             }
@@ -347,6 +476,86 @@ pub mod mock {
             async fn yank_crate(&self, crate_name: &NormalizedName, version: &Version) -> DbResult<()> {
                 unimplemented!()
             }
+
+            async fn get_crate_cksum(&self, crate_name: &NormalizedName, version: &Version) -> DbResult<Option<String>> {
+                unimplemented!()
+            }
+
+            async fn get_cratesio_crate_cksum(&self, crate_name: &NormalizedName, version: &Version) -> DbResult<Option<String>> {
+                unimplemented!()
+            }
+
+            async fn get_indexed_crates(&self) -> DbResult<Vec<IndexedCrate>> {
+                unimplemented!()
+            }
+
+            async fn get_cratesio_stored_crates(&self) -> DbResult<Vec<IndexedCrate>> {
+                unimplemented!()
+            }
+
+            async fn update_cratesio_last_access(&self, crate_name: &NormalizedName, version: &Version, last_access: &DateTime<Utc>) -> DbResult<()> {
+                unimplemented!()
+            }
+
+            async fn clear_cratesio_last_access(&self, crate_name: &NormalizedName, version: &Version) -> DbResult<()> {
+                unimplemented!()
+            }
+
+            async fn get_least_recently_used_cratesio_crates(&self, limit: u64) -> DbResult<Vec<(OriginalName, Version)>> {
+                unimplemented!()
+            }
+
+            async fn update_cratesio_metadata(&self, crate_name: &NormalizedName, metadata: &CratesIoMetadata, updated: &DateTime<Utc>) -> DbResult<()> {
+                unimplemented!()
+            }
+
+            async fn get_cratesio_metadata(&self, crate_name: &NormalizedName) -> DbResult<Option<(CratesIoMetadata, DateTime<Utc>)>> {
+                unimplemented!()
+            }
+
+            async fn search_cratesio_crates(&self, contains: &str, offset: u64, limit: u64) -> DbResult<SearchResult> {
+                unimplemented!()
+            }
+
+            async fn get_cached_cratesio_crates(&self, offset: u64, limit: u64) -> DbResult<Vec<CachedCrate>> {
+                unimplemented!()
+            }
+
+            async fn get_cached_cratesio_crate(&self, crate_name: &NormalizedName) -> DbResult<Option<CachedCrate>> {
+                unimplemented!()
+            }
+
+            async fn delete_cratesio_crate(&self, crate_name: &NormalizedName) -> DbResult<Vec<Version>> {
+                unimplemented!()
+            }
+
+            async fn set_cratesio_crate_pinned(&self, crate_name: &NormalizedName, pinned: bool) -> DbResult<()> {
+                unimplemented!()
+            }
+
+            async fn get_proxy_rules(&self) -> DbResult<Vec<ProxyRule>> {
+                unimplemented!()
+            }
+
+            async fn add_proxy_rule(&self, pattern: &str, version_req: &str, action: PolicyAction, created: &DateTime<Utc>) -> DbResult<ProxyRule> {
+                unimplemented!()
+            }
+
+            async fn update_proxy_rule_action(&self, id: i64, action: PolicyAction) -> DbResult<()> {
+                unimplemented!()
+            }
+
+            async fn delete_proxy_rule(&self, id: i64) -> DbResult<()> {
+                unimplemented!()
+            }
+
+            async fn add_security_event(&self, kind: SecurityEventKind, crate_name: &NormalizedName, version: &Version, detail: &str, created: &DateTime<Utc>) -> DbResult<()> {
+                unimplemented!()
+            }
+
+            async fn get_security_events(&self, limit: u64) -> DbResult<Vec<SecurityEvent>> {
+                unimplemented!()
+            }
         }
     }
 }
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityEventKind {
+    /// A crate downloaded from an upstream did not match the checksum of the upstream index.
+    ChecksumMismatch,
+}
+
+impl fmt::Display for SecurityEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecurityEventKind::ChecksumMismatch => write!(f, "checksum_mismatch"),
+        }
+    }
+}
+
+impl FromStr for SecurityEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checksum_mismatch" => Ok(SecurityEventKind::ChecksumMismatch),
+            _ => Err(format!("Invalid security event kind: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityEvent {
+    pub id: i64,
+    pub kind: SecurityEventKind,
+    /// Proxied registry the event occurred for, e.g. `crates-io`.
+    pub upstream: String,
+    pub crate_name: String,
+    pub version: String,
+    pub detail: String,
+    pub created: String,
+}
+
+impl TryFrom<entity::security_event::Model> for SecurityEvent {
+    type Error = String;
+
+    fn try_from(model: entity::security_event::Model) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: model.id,
+            kind: model.kind.parse()?,
+            upstream: model.upstream,
+            crate_name: model.crate_name,
+            version: model.version,
+            detail: model.detail,
+            created: model.created,
+        })
+    }
+}
@@ -1,6 +1,6 @@
 use chrono::{TimeZone, Utc, DateTime};
 use common::crate_data::{CrateData, CrateRegistryDep, CrateVersionData};
-use common::crate_overview::CrateOverview;
+use common::crate_overview::{CrateOverview, CrateOverviewPage};
 use common::index_metadata::IndexMetadata;
 use common::normalized_name::NormalizedName;
 use common::original_name::OriginalName;
@@ -1379,6 +1379,57 @@ async fn search_in_crate_name_found_match() {
     assert_eq!(expected, search_results);
 }
 
+#[pg_testcontainer]
+#[tokio::test]
+async fn search_crates_ranks_and_pages_matches() {
+    let created = Utc.with_ymd_and_hms(2020, 10, 7, 13, 18, 00).unwrap();
+    let crates = [
+        ("json", "Parse JSON", vec![], vec![]),
+        (
+            "serde_json",
+            "A JSON serialization file format",
+            vec![],
+            vec![],
+        ),
+        ("fast_parser", "Parses data", vec!["json"], vec![]),
+        ("formats", "Data formats", vec![], vec!["encoding::json"]),
+        ("unrelated", "Something else", vec!["http"], vec![]),
+    ];
+    for (name, description, keywords, categories) in crates {
+        let pm = PublishMetadata {
+            description: Some(description.to_string()),
+            keywords: keywords.into_iter().map(String::from).collect(),
+            categories: categories.into_iter().map(String::from).collect(),
+            ..PublishMetadata::minimal(name, "1.0.0")
+        };
+        test_db
+            .add_crate(&pm, "cksum", &created, "admin")
+            .await
+            .unwrap();
+    }
+
+    let all = test_db.search_crates("JSON", 0, 10).await.unwrap();
+    let page = test_db.search_crates("json", 1, 2).await.unwrap();
+    let underscore = test_db.search_crates("_", 0, 10).await.unwrap();
+    let percent = test_db.search_crates("%", 0, 10).await.unwrap();
+
+    let names = |p: &CrateOverviewPage| {
+        p.crates
+            .iter()
+            .map(|c| c.original_name.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(4, all.total);
+    assert_eq!(
+        vec!["json", "serde_json", "fast_parser", "formats"],
+        names(&all)
+    );
+    assert_eq!(4, page.total);
+    assert_eq!(vec!["serde_json", "fast_parser"], names(&page));
+    assert_eq!(2, underscore.total);
+    assert_eq!(0, percent.total);
+}
+
 #[pg_testcontainer]
 #[tokio::test]
 async fn get_crate_overview_list() {
@@ -2531,3 +2582,27 @@ async fn test_get_total_cached_downloads_works() {
 
     assert_eq!(60, total_downloads);
 }
+
+#[pg_testcontainer]
+#[tokio::test]
+async fn get_crate_cksum_works() {
+    test_db
+        .test_add_crate(
+            "crate",
+            "admin",
+            &Version::from_unchecked_str("1.0.0"),
+            &Utc::now(),
+        )
+        .await
+        .unwrap();
+
+    let cksum = test_db
+        .get_crate_cksum(
+            &NormalizedName::from_unchecked_str("crate"),
+            &Version::from_unchecked_str("1.0.0"),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(Some("cksum".to_string()), cksum);
+}
@@ -1,20 +1,25 @@
 use chrono::prelude::*;
 use common::crate_data::{CrateData, CrateRegistryDep, CrateVersionData};
-use common::crate_overview::CrateOverview;
+use common::crate_overview::{CrateOverview, CrateOverviewPage};
+use common::cratesio_metadata::CratesIoMetadata;
 use common::index_metadata::IndexMetadata;
 use common::normalized_name::NormalizedName;
 use common::original_name::OriginalName;
 use common::prefetch::Prefetch;
+use common::proxy_policy::PolicyAction;
 use common::publish_metadata::{PublishMetadata, RegistryDep};
 use common::util::generate_rand_string;
 use common::version::Version;
+use db::error::DbError;
 use db::password::hash_pwd;
 use db::provider::PrefetchState;
-use db::{ConString, Database, DocQueueEntry, SqliteConString};
+use db::{ConString, Database, DocQueueEntry, SecurityEventKind, SqliteConString};
 use db::{DbProvider, User};
+use search::search_index::SearchIndex;
 use std::collections::BTreeMap;
 use std::ops::Add;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::{path, thread, time};
 
 struct TestDB {
@@ -24,6 +29,14 @@ struct TestDB {
 
 impl TestDB {
     async fn new() -> Self {
+        Self::open(None).await
+    }
+
+    async fn with_search_index() -> Self {
+        Self::open(Some(Arc::new(SearchIndex::in_memory().unwrap()))).await
+    }
+
+    async fn open(search_index: Option<Arc<SearchIndex>>) -> Self {
         let path = path::PathBuf::from("/tmp").join(generate_rand_string(8).add(".db"));
 
         let con_string = SqliteConString {
@@ -36,7 +49,10 @@ impl TestDB {
 
         let con_string = ConString::Sqlite(con_string);
 
-        let db: Database = Database::new(&con_string).await.unwrap();
+        let mut db: Database = Database::new(&con_string).await.unwrap();
+        if let Some(search_index) = search_index {
+            db = db.with_search_index(search_index);
+        }
 
         Self { path, db }
     }
@@ -1404,6 +1420,135 @@ async fn search_in_crate_name_found_match() {
     assert_eq!(expected, search_results);
 }
 
+#[tokio::test]
+async fn search_crates_ranks_and_pages_matches() {
+    let test_db = TestDB::new().await;
+    let created = Utc.with_ymd_and_hms(2020, 10, 7, 13, 18, 00).unwrap();
+    let crates = [
+        ("json", "Parse JSON", vec![], vec![]),
+        (
+            "serde_json",
+            "A JSON serialization file format",
+            vec![],
+            vec![],
+        ),
+        ("fast_parser", "Parses data", vec!["json"], vec![]),
+        ("formats", "Data formats", vec![], vec!["encoding::json"]),
+        ("unrelated", "Something else", vec!["http"], vec![]),
+    ];
+    for (name, description, keywords, categories) in crates {
+        let pm = PublishMetadata {
+            description: Some(description.to_string()),
+            documentation: Some(format!("https://docs/{}", name)),
+            keywords: keywords.into_iter().map(String::from).collect(),
+            categories: categories.into_iter().map(String::from).collect(),
+            ..PublishMetadata::minimal(name, "1.0.0")
+        };
+        test_db
+            .db
+            .add_crate(&pm, "cksum", &created, "admin")
+            .await
+            .unwrap();
+    }
+
+    let all = test_db.db.search_crates("JSON", 0, 10).await.unwrap();
+    let page = test_db.db.search_crates("json", 1, 2).await.unwrap();
+    let underscore = test_db.db.search_crates("_", 0, 10).await.unwrap();
+    let percent = test_db.db.search_crates("%", 0, 10).await.unwrap();
+    let none = test_db.db.search_crates("  ", 0, 10).await.unwrap();
+
+    let names = |p: &CrateOverviewPage| {
+        p.crates
+            .iter()
+            .map(|c| c.original_name.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(4, all.total);
+    assert_eq!(
+        vec!["json", "serde_json", "fast_parser", "formats"],
+        names(&all)
+    );
+    assert_eq!(
+        Some("https://docs/json".to_string()),
+        all.crates[0].documentation
+    );
+    assert_eq!(4, page.total);
+    assert_eq!(vec!["serde_json", "fast_parser"], names(&page));
+    assert_eq!(2, underscore.total);
+    assert_eq!(0, percent.total);
+    assert_eq!(CrateOverviewPage::default(), none);
+}
+
+#[tokio::test]
+async fn search_full_text_follows_publish_yank_and_delete() {
+    let test_db = TestDB::with_search_index().await;
+    let created = Utc.with_ymd_and_hms(2020, 10, 7, 13, 18, 00).unwrap();
+    for (name, version, readme) in [
+        (
+            "http_client",
+            "1.0.0",
+            "Sends requests with connection pooling.",
+        ),
+        (
+            "http_client",
+            "2.0.0",
+            "Sends requests over a pool of connections.",
+        ),
+        ("logger", "1.0.0", "Writes structured logs."),
+    ] {
+        let pm = PublishMetadata {
+            readme: Some(readme.to_string()),
+            ..PublishMetadata::minimal(name, version)
+        };
+        test_db
+            .db
+            .add_crate(&pm, "cksum", &created, "admin")
+            .await
+            .unwrap();
+    }
+    let version = |v| Version::try_from(v).unwrap();
+    let http_client = NormalizedName::from_unchecked("http_client".to_string());
+    let logger = NormalizedName::from_unchecked("logger".to_string());
+
+    let latest = test_db.db.search_full_text("pool", 0, 10).await.unwrap();
+    test_db
+        .db
+        .yank_crate(&http_client, &version("2.0.0"))
+        .await
+        .unwrap();
+    let yanked = test_db.db.search_full_text("pooling", 0, 10).await.unwrap();
+    test_db
+        .db
+        .delete_crate(&logger, &version("1.0.0"))
+        .await
+        .unwrap();
+    let deleted = test_db.db.search_full_text("logs", 0, 10).await.unwrap();
+
+    assert_eq!(1, latest.total);
+    assert_eq!("http_client", latest.hits[0].overview.original_name);
+    assert_eq!("2.0.0", latest.hits[0].overview.max_version);
+    assert_eq!(
+        "Sends requests over a <b>pool</b> of connections",
+        latest.hits[0].snippet
+    );
+    // The readme of the latest version which is not yanked is indexed.
+    assert_eq!(
+        "Sends requests with connection <b>pooling</b>",
+        yanked.hits[0].snippet
+    );
+    assert_eq!(0, deleted.total);
+    assert_eq!(1, test_db.db.rebuild_search_index().await.unwrap());
+}
+
+#[tokio::test]
+async fn search_full_text_without_index_fails() {
+    let test_db = TestDB::new().await;
+
+    let result = test_db.db.search_full_text("pool", 0, 10).await;
+
+    assert!(matches!(result, Err(DbError::SearchIndexError(_))));
+}
+
 #[tokio::test]
 async fn get_crate_overview_list() {
     let test_db = TestDB::new().await;
@@ -2604,3 +2749,567 @@ async fn test_get_total_cached_downloads_works() {
 
     assert_eq!(60, total_downloads);
 }
+
+#[tokio::test]
+async fn get_crate_cksum_works() {
+    let test_db = TestDB::new().await;
+    test_db
+        .db
+        .test_add_crate(
+            "crate",
+            "admin",
+            &Version::from_unchecked_str("1.0.0"),
+            &Utc::now(),
+        )
+        .await
+        .unwrap();
+
+    let cksum = test_db
+        .db
+        .get_crate_cksum(
+            &NormalizedName::from_unchecked_str("crate"),
+            &Version::from_unchecked_str("1.0.0"),
+        )
+        .await
+        .unwrap();
+    let missing = test_db
+        .db
+        .get_crate_cksum(
+            &NormalizedName::from_unchecked_str("crate"),
+            &Version::from_unchecked_str("2.0.0"),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(Some("cksum".to_string()), cksum);
+    assert_eq!(None, missing);
+}
+
+#[tokio::test]
+async fn get_indexed_crates_works() {
+    let test_db = TestDB::new().await;
+    test_db
+        .db
+        .test_add_crate(
+            "crate",
+            "admin",
+            &Version::from_unchecked_str("1.0.0"),
+            &Utc::now(),
+        )
+        .await
+        .unwrap();
+
+    let indexed = test_db.db.get_indexed_crates().await.unwrap();
+
+    assert_eq!(1, indexed.len());
+    assert_eq!("crate", indexed[0].name);
+    assert_eq!("1.0.0", indexed[0].version);
+    assert_eq!("cksum", indexed[0].cksum);
+}
+
+#[tokio::test]
+async fn get_least_recently_used_cratesio_crates_works() {
+    let test_db = TestDB::new().await;
+    test_db
+        .db
+        .test_add_cached_crate("old", "1.0.0")
+        .await
+        .unwrap();
+    test_db
+        .db
+        .test_add_cached_crate("new", "1.0.0")
+        .await
+        .unwrap();
+    test_db
+        .db
+        .test_add_cached_crate("never_downloaded", "1.0.0")
+        .await
+        .unwrap();
+    let version = Version::from_unchecked_str("1.0.0");
+    test_db
+        .db
+        .update_cratesio_last_access(
+            &NormalizedName::from_unchecked_str("new"),
+            &version,
+            &Utc.with_ymd_and_hms(2023, 1, 2, 0, 0, 0).unwrap(),
+        )
+        .await
+        .unwrap();
+    test_db
+        .db
+        .update_cratesio_last_access(
+            &NormalizedName::from_unchecked_str("old"),
+            &version,
+            &Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let lru = test_db
+        .db
+        .get_least_recently_used_cratesio_crates(10)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        vec![
+            (
+                OriginalName::from_unchecked_str("old".to_string()),
+                version.clone()
+            ),
+            (
+                OriginalName::from_unchecked_str("new".to_string()),
+                version.clone()
+            ),
+        ],
+        lru
+    );
+
+    test_db
+        .db
+        .clear_cratesio_last_access(&NormalizedName::from_unchecked_str("old"), &version)
+        .await
+        .unwrap();
+    let lru = test_db
+        .db
+        .get_least_recently_used_cratesio_crates(10)
+        .await
+        .unwrap();
+
+    assert_eq!(1, lru.len());
+}
+
+#[tokio::test]
+async fn publish_crate_adds_doc_queue_and_rejects_existing_version() {
+    let test_db = TestDB::new().await;
+    let pm = PublishMetadata {
+        name: "mycrate".to_string(),
+        vers: "1.0.0".to_string(),
+        ..PublishMetadata::default()
+    };
+
+    test_db
+        .db
+        .publish_crate(
+            &pm,
+            "cksum",
+            &Utc::now(),
+            "admin",
+            Some(&PathBuf::from("/tmp/foo")),
+        )
+        .await
+        .unwrap();
+    let result = test_db
+        .db
+        .publish_crate(&pm, "cksum", &Utc::now(), "admin", None)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(1, test_db.db.get_doc_queue().await.unwrap().len());
+    assert_eq!(1, test_db.db.get_crate_meta_list(1).await.unwrap().len());
+}
+
+#[tokio::test]
+async fn cached_crates_are_separated_by_upstream() {
+    let test_db = TestDB::new().await;
+    let upstream_db = test_db.db.with_upstream("my-registry");
+    test_db
+        .db
+        .test_add_cached_crate("mycrate", "1.0.0")
+        .await
+        .unwrap();
+    upstream_db
+        .test_add_cached_crate("mycrate", "2.0.0")
+        .await
+        .unwrap();
+    let name = NormalizedName::from_unchecked_str("mycrate");
+    for (db, version) in [(&test_db.db, "1.0.0"), (&upstream_db, "2.0.0")] {
+        db.update_cratesio_last_access(&name, &Version::from_unchecked_str(version), &Utc::now())
+            .await
+            .unwrap();
+    }
+
+    let cratesio_indexed = test_db.db.get_cratesio_stored_crates().await.unwrap();
+    let upstream_indexed = upstream_db.get_cratesio_stored_crates().await.unwrap();
+
+    assert_eq!(1, cratesio_indexed.len());
+    assert_eq!("1.0.0", cratesio_indexed[0].version);
+    assert_eq!(1, upstream_indexed.len());
+    assert_eq!("2.0.0", upstream_indexed[0].version);
+    assert!(test_db
+        .db
+        .get_cratesio_crate_cksum(&name, &Version::from_unchecked_str("2.0.0"))
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        1,
+        upstream_db
+            .get_cratesio_index_update_list()
+            .await
+            .unwrap()
+            .len()
+    );
+}
+
+#[tokio::test]
+async fn cratesio_stored_crates_skip_index_only_versions() {
+    let test_db = TestDB::new().await;
+    test_db
+        .db
+        .test_add_cached_crate("mycrate", "1.0.0")
+        .await
+        .unwrap();
+    test_db
+        .db
+        .test_add_cached_crate("mycrate", "2.0.0")
+        .await
+        .unwrap();
+    let name = NormalizedName::from_unchecked_str("mycrate");
+    let evicted = Version::from_unchecked_str("1.0.0");
+    test_db
+        .db
+        .update_cratesio_last_access(&name, &evicted, &Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(
+        1,
+        test_db.db.get_cratesio_stored_crates().await.unwrap().len()
+    );
+
+    test_db
+        .db
+        .clear_cratesio_last_access(&name, &evicted)
+        .await
+        .unwrap();
+
+    assert!(test_db
+        .db
+        .get_cratesio_stored_crates()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn add_update_and_delete_proxy_rules() {
+    let test_db = TestDB::new().await;
+
+    let rule = test_db
+        .db
+        .add_proxy_rule("tokio-*", ">=1, <2", PolicyAction::Pending, &Utc::now())
+        .await
+        .unwrap();
+    let duplicate = test_db
+        .db
+        .add_proxy_rule("tokio-*", ">=1, <2", PolicyAction::Deny, &Utc::now())
+        .await;
+    test_db
+        .db
+        .update_proxy_rule_action(rule.id, PolicyAction::Allow)
+        .await
+        .unwrap();
+    let rules = test_db.db.get_proxy_rules().await.unwrap();
+
+    assert!(duplicate.is_err());
+    assert_eq!(1, rules.len());
+    assert_eq!("tokio-*", rules[0].pattern);
+    assert_eq!(">=1, <2", rules[0].version_req);
+    assert_eq!(PolicyAction::Allow, rules[0].action);
+
+    test_db.db.delete_proxy_rule(rule.id).await.unwrap();
+
+    assert!(test_db.db.get_proxy_rules().await.unwrap().is_empty());
+    assert!(test_db.db.delete_proxy_rule(rule.id).await.is_err());
+}
+
+#[tokio::test]
+async fn add_and_get_security_events() {
+    let test_db = TestDB::new().await;
+    let upstream_db = test_db.db.with_upstream("my-registry");
+    let name = NormalizedName::from_unchecked_str("mycrate");
+    let version = Version::from_unchecked_str("1.0.0");
+
+    test_db
+        .db
+        .add_security_event(
+            SecurityEventKind::ChecksumMismatch,
+            &name,
+            &version,
+            "first",
+            &Utc::now(),
+        )
+        .await
+        .unwrap();
+    upstream_db
+        .add_security_event(
+            SecurityEventKind::ChecksumMismatch,
+            &name,
+            &version,
+            "second",
+            &Utc::now(),
+        )
+        .await
+        .unwrap();
+
+    let events = test_db.db.get_security_events(10).await.unwrap();
+    let latest = test_db.db.get_security_events(1).await.unwrap();
+
+    assert_eq!(2, events.len());
+    assert_eq!("second", events[0].detail);
+    assert_eq!("my-registry", events[0].upstream);
+    assert_eq!("crates-io", events[1].upstream);
+    assert_eq!("mycrate", events[1].crate_name);
+    assert_eq!("1.0.0", events[1].version);
+    assert_eq!(SecurityEventKind::ChecksumMismatch, events[1].kind);
+    assert_eq!(1, latest.len());
+}
+
+#[tokio::test]
+async fn update_and_get_cratesio_metadata() {
+    let test_db = TestDB::new().await;
+    let name = NormalizedName::from_unchecked_str("mycrate");
+    test_db
+        .db
+        .add_cratesio_prefetch_data(
+            &OriginalName::from_unchecked_str("mycrate".to_string()),
+            "etag",
+            "last_modified",
+            None,
+            &[],
+        )
+        .await
+        .unwrap();
+    let metadata = CratesIoMetadata {
+        description: Some("My crate".to_string()),
+        homepage: None,
+        repository: Some("https://example.com/mycrate".to_string()),
+        documentation: None,
+        keywords: vec!["test".to_string()],
+        downloads: 42,
+    };
+    let updated = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+
+    let before = test_db.db.get_cratesio_metadata(&name).await.unwrap();
+    test_db
+        .db
+        .update_cratesio_metadata(&name, &metadata, &updated)
+        .await
+        .unwrap();
+    let after = test_db.db.get_cratesio_metadata(&name).await.unwrap();
+    let other_upstream = test_db
+        .db
+        .with_upstream("my-registry")
+        .get_cratesio_metadata(&name)
+        .await
+        .unwrap();
+
+    assert_eq!(None, before);
+    assert_eq!(Some((metadata, updated)), after);
+    assert_eq!(None, other_upstream);
+}
+
+#[tokio::test]
+async fn update_cratesio_metadata_of_uncached_crate() {
+    let test_db = TestDB::new().await;
+
+    let result = test_db
+        .db
+        .update_cratesio_metadata(
+            &NormalizedName::from_unchecked_str("mycrate"),
+            &CratesIoMetadata::default(),
+            &Utc::now(),
+        )
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn pinned_cratesio_crates_are_not_evicted() {
+    let test_db = TestDB::new().await;
+    let name = NormalizedName::from_unchecked_str("pinned");
+    test_db
+        .db
+        .test_add_cached_crate("pinned", "1.0.0")
+        .await
+        .unwrap();
+    test_db
+        .db
+        .update_cratesio_last_access(&name, &Version::from_unchecked_str("1.0.0"), &Utc::now())
+        .await
+        .unwrap();
+
+    test_db
+        .db
+        .set_cratesio_crate_pinned(&name, true)
+        .await
+        .unwrap();
+    let pinned = test_db
+        .db
+        .get_least_recently_used_cratesio_crates(10)
+        .await
+        .unwrap();
+    test_db
+        .db
+        .set_cratesio_crate_pinned(&name, false)
+        .await
+        .unwrap();
+    let unpinned = test_db
+        .db
+        .get_least_recently_used_cratesio_crates(10)
+        .await
+        .unwrap();
+
+    assert!(pinned.is_empty());
+    assert_eq!(1, unpinned.len());
+    assert!(test_db
+        .db
+        .set_cratesio_crate_pinned(&NormalizedName::from_unchecked_str("unknown"), true)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn get_and_delete_cached_cratesio_crates() {
+    let test_db = TestDB::new().await;
+    let name = NormalizedName::from_unchecked_str("downloaded");
+    test_db
+        .db
+        .test_add_cached_crate("downloaded", "1.0.0")
+        .await
+        .unwrap();
+    test_db
+        .db
+        .test_add_cached_crate("indexed", "1.0.0")
+        .await
+        .unwrap();
+    let last_access = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    test_db
+        .db
+        .update_cratesio_last_access(&name, &Version::from_unchecked_str("1.0.0"), &last_access)
+        .await
+        .unwrap();
+
+    let crates = test_db.db.get_cached_cratesio_crates(0, 10).await.unwrap();
+    let second_page = test_db.db.get_cached_cratesio_crates(1, 10).await.unwrap();
+    let krate = test_db
+        .db
+        .get_cached_cratesio_crate(&name)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(2, crates.len());
+    assert_eq!("downloaded", crates[0].name);
+    assert_eq!(1, crates[0].versions.len());
+    assert_eq!(
+        Some("2024-01-02 03:04:05".to_string()),
+        crates[0].versions[0].last_access
+    );
+    assert!(crates[1].versions.is_empty());
+    assert_eq!(1, second_page.len());
+    assert_eq!("1.0.0", krate.versions[0].version);
+    assert!(!krate.pinned);
+
+    let versions = test_db.db.delete_cratesio_crate(&name).await.unwrap();
+
+    assert_eq!(vec![Version::from_unchecked_str("1.0.0")], versions);
+    assert_eq!(
+        None,
+        test_db.db.get_cached_cratesio_crate(&name).await.unwrap()
+    );
+    assert_eq!(
+        1,
+        test_db
+            .db
+            .get_cached_cratesio_crates(0, 10)
+            .await
+            .unwrap()
+            .len()
+    );
+    assert!(test_db.db.delete_cratesio_crate(&name).await.is_err());
+}
+
+#[tokio::test]
+async fn search_cratesio_crates_returns_max_version_and_total() {
+    let test_db = TestDB::new().await;
+    let index = |name: &str, vers: &str, yanked: bool| IndexMetadata {
+        name: name.to_string(),
+        vers: vers.to_string(),
+        deps: vec![],
+        cksum: "cksum".to_string(),
+        features: Default::default(),
+        yanked,
+        links: None,
+        v: Some(1),
+        features2: None,
+    };
+    for (name, indices) in [
+        (
+            "Serde",
+            vec![
+                index("Serde", "1.0.0", false),
+                index("Serde", "1.10.0", false),
+                index("Serde", "2.0.0", true),
+            ],
+        ),
+        ("serde_json", vec![index("serde_json", "0.9.0", false)]),
+        ("rand", vec![index("rand", "0.8.0", false)]),
+    ] {
+        test_db
+            .db
+            .add_cratesio_prefetch_data(
+                &OriginalName::from_unchecked_str(name.to_string()),
+                "etag",
+                "last_modified",
+                Some(format!("{name} description")),
+                &indices,
+            )
+            .await
+            .unwrap();
+    }
+
+    let result = test_db
+        .db
+        .search_cratesio_crates("SERDE", 0, 1)
+        .await
+        .unwrap();
+    let second_page = test_db
+        .db
+        .search_cratesio_crates("serde", 1, 1)
+        .await
+        .unwrap();
+    let all = test_db
+        .db
+        .search_cratesio_crates("serde", 0, 10)
+        .await
+        .unwrap();
+    let underscore = test_db.db.search_cratesio_crates("_", 0, 10).await.unwrap();
+    let percent = test_db.db.search_cratesio_crates("%", 0, 10).await.unwrap();
+    let other_upstream = test_db
+        .db
+        .with_upstream("my-registry")
+        .search_cratesio_crates("serde", 0, 10)
+        .await
+        .unwrap();
+
+    assert_eq!(2, result.meta.total);
+    assert_eq!(1, result.crates.len());
+    assert_eq!("Serde", result.crates[0].name);
+    assert_eq!("1.10.0", result.crates[0].max_version);
+    assert_eq!("Serde description", result.crates[0].description);
+    assert_eq!(2, second_page.meta.total);
+    assert_eq!("serde_json", second_page.crates[0].name);
+    assert_eq!(
+        vec!["1.10.0", "0.9.0"],
+        all.crates
+            .iter()
+            .map(|c| c.max_version.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(1, underscore.meta.total);
+    assert_eq!("serde_json", underscore.crates[0].name);
+    assert_eq!(0, percent.meta.total);
+    assert_eq!(0, other_upstream.meta.total);
+}
@@ -12,10 +12,7 @@ use fs_extra::dir::{copy, CopyOptions};
 use std::path::{Path, PathBuf};
 use storage::kellnr_crate_storage::KellnrCrateStorage;
 use tar::Archive;
-use tokio::{
-    fs::{create_dir_all, remove_dir_all, File},
-    io::AsyncReadExt,
-};
+use tokio::fs::{create_dir_all, remove_dir_all};
 use tracing::error;
 
 pub async fn doc_extraction_queue(db: Database, cs: KellnrCrateStorage, docs_path: PathBuf) {
@@ -60,12 +57,14 @@ async fn extract_docs(
     cs: &KellnrCrateStorage,
     docs_path: &Path,
 ) -> anyhow::Result<()> {
-    let crate_path = cs.crate_path(&doc.krate, &doc.version);
-
     // Unpack crate
-    let mut tar_gz = File::open(&crate_path).await?;
-    let mut contents = vec![];
-    tar_gz.read_to_end(&mut contents).await?;
+    let contents = cs.get_file(&doc.krate, &doc.version).await.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Crate file not found in storage: {}-{}",
+            doc.krate,
+            doc.version
+        )
+    })?;
     let tar = GzDecoder::new(std::io::Cursor::new(contents));
     let mut archive = Archive::new(tar);
     archive.unpack(&doc.path)?;
@@ -1,26 +1,70 @@
 use super::config_json::ConfigJson;
-use appstate::{CratesIoPrefetchSenderState, DbState, SettingsState};
+use crate::upstream_api::UpstreamApi;
+use appstate::{
+    DbState, HttpClientState, PrefetchOutcome, PrefetchQueue, PrefetchQueueState, ProxyMode,
+    ProxyModeState, ProxyPolicies, ProxyPoliciesState, SettingsState, UpstreamProxy, Upstreams,
+    UpstreamsState,
+};
 use axum::extract::{Path, State};
 use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
 use common::cratesio_prefetch_msg::{CratesioPrefetchMsg, InsertData, UpdateData};
 use common::index_metadata::IndexMetadata;
 use common::normalized_name::NormalizedName;
 use common::original_name::OriginalName;
 use common::prefetch::Prefetch;
+use common::proxy_policy::ProxyPolicy;
+use common::single_flight::SingleFlight;
 use db::provider::PrefetchState;
 use db::DbProvider;
 use hyper::StatusCode;
-use moka::future::Cache;
 use reqwest::{Client, Url};
-use serde::Deserialize;
-use std::ops::Deref;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use tracing::{error, trace, warn};
 
 static UPDATE_INTERVAL_SECS: u64 = 60 * 120; // 2h background update interval
-static UPDATE_CACHE_TIMEOUT_SECS: u64 = 60 * 30; // 30 min cache timeout
+static OFFLINE_POLL_SECS: u64 = 60; // Check interval of the background update while offline
+static ERROR_RETRY_SECS: u64 = 60; // Delay of the background update after a database error
+static FETCH_RETRIES: u32 = 3; // Retries of a failed index request
+static FETCH_RETRY_DELAY_MS: u64 = 500; // Delay before the first retry, doubled on each retry
+static METADATA_MAX_AGE_SECS: i64 = 60 * 60 * 24; // 24h until the crate metadata is fetched again
+pub static METADATA_QUEUE_SIZE: usize = 10_000; // Max. number of queued metadata fetches
+
+// Running index requests by index file URL, which is unique across all upstreams.
+static INDEX_FETCHES: SingleFlight<String, Result<Prefetch, StatusCode>> = SingleFlight::new();
+
+#[derive(Debug)]
+pub enum PrefetchError {
+    Status(StatusCode),
+    /// The crate is not cached and the proxy is in offline mode.
+    Offline(OriginalName),
+}
+
+impl From<StatusCode> for PrefetchError {
+    fn from(status: StatusCode) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl IntoResponse for PrefetchError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Status(status) => status.into_response(),
+            // Cargo shows the body of unsuccessful responses to the user.
+            Self::Offline(name) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!(
+                    "The Kellnr proxy is in offline mode and crate {} is not cached",
+                    name
+                ),
+            )
+                .into_response(),
+        }
+    }
+}
 
 pub async fn config_cratesio(State(settings): SettingsState) -> Json<ConfigJson> {
     Json(ConfigJson::from((&(*settings), "cratesio")))
@@ -30,27 +74,144 @@ pub async fn prefetch_cratesio(
     Path((_a, _b, name)): Path<(String, String, OriginalName)>,
     headers: HeaderMap,
     State(db): DbState,
-    State(sender): CratesIoPrefetchSenderState,
-) -> Result<Prefetch, StatusCode> {
-    internal_prefetch_cratesio(name, headers, &db, &sender).await
+    State(settings): SettingsState,
+    State(queue): PrefetchQueueState,
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+) -> Result<Prefetch, PrefetchError> {
+    internal_prefetch_cratesio(
+        name,
+        headers,
+        &db,
+        &client,
+        &settings.proxy.index_url,
+        settings.proxy.approval_required,
+        &policies,
+        &proxy_mode,
+        &queue,
+    )
+    .await
 }
 
 pub async fn prefetch_len2_cratesio(
     Path((_a, name)): Path<(String, OriginalName)>,
     headers: HeaderMap,
     State(db): DbState,
-    State(sender): CratesIoPrefetchSenderState,
-) -> Result<Prefetch, StatusCode> {
-    internal_prefetch_cratesio(name, headers, &db, &sender).await
+    State(settings): SettingsState,
+    State(queue): PrefetchQueueState,
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+) -> Result<Prefetch, PrefetchError> {
+    internal_prefetch_cratesio(
+        name,
+        headers,
+        &db,
+        &client,
+        &settings.proxy.index_url,
+        settings.proxy.approval_required,
+        &policies,
+        &proxy_mode,
+        &queue,
+    )
+    .await
+}
+
+pub async fn config_upstream(
+    Path(upstream): Path<String>,
+    State(settings): SettingsState,
+    State(upstreams): UpstreamsState,
+) -> Result<Json<ConfigJson>, StatusCode> {
+    let upstream = get_upstream(&upstreams, &upstream)?;
+    Ok(Json(ConfigJson::from((
+        &(*settings),
+        format!("proxy/{}", upstream.settings.name).as_str(),
+    ))))
+}
+
+pub async fn prefetch_upstream(
+    Path((upstream, _a, _b, name)): Path<(String, String, String, OriginalName)>,
+    headers: HeaderMap,
+    State(settings): SettingsState,
+    State(upstreams): UpstreamsState,
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+) -> Result<Prefetch, PrefetchError> {
+    let upstream = get_upstream(&upstreams, &upstream)?;
+    internal_prefetch_cratesio(
+        name,
+        headers,
+        &upstream.db,
+        &client,
+        &upstream.settings.index_url,
+        settings.proxy.approval_required,
+        &policies,
+        &proxy_mode,
+        &upstream.prefetch_queue,
+    )
+    .await
+}
+
+pub async fn prefetch_len2_upstream(
+    Path((upstream, _a, name)): Path<(String, String, OriginalName)>,
+    headers: HeaderMap,
+    State(settings): SettingsState,
+    State(upstreams): UpstreamsState,
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+) -> Result<Prefetch, PrefetchError> {
+    let upstream = get_upstream(&upstreams, &upstream)?;
+    internal_prefetch_cratesio(
+        name,
+        headers,
+        &upstream.db,
+        &client,
+        &upstream.settings.index_url,
+        settings.proxy.approval_required,
+        &policies,
+        &proxy_mode,
+        &upstream.prefetch_queue,
+    )
+    .await
+}
+
+fn get_upstream<'a>(upstreams: &'a Upstreams, name: &str) -> Result<&'a UpstreamProxy, StatusCode> {
+    upstreams.get(name).ok_or(StatusCode::NOT_FOUND)
 }
 
-async fn internal_prefetch_cratesio(
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn internal_prefetch_cratesio(
     name: OriginalName,
     headers: HeaderMap,
     db: &Arc<dyn DbProvider>,
-    sender: &Arc<flume::Sender<CratesioPrefetchMsg>>,
-) -> Result<Prefetch, StatusCode> {
+    client: &Client,
+    index_url: &str,
+    approval_required: bool,
+    policies: &ProxyPolicies,
+    proxy_mode: &ProxyMode,
+    queue: &PrefetchQueue,
+) -> Result<Prefetch, PrefetchError> {
     let instance = Instant::now();
+    let offline = proxy_mode.is_offline();
+    let policy = policies.get(db, approval_required).await.map_err(|e| {
+        error!("Could not get proxy rules: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let normalized_name = name.to_normalized();
+    if policy.needs_approval_request(&normalized_name) {
+        request_approval(db, policies, &normalized_name).await;
+    }
+    if policy.blocks_all_versions(&normalized_name) {
+        warn!(
+            "Blocked index request for crate {} by the proxy policy",
+            name
+        );
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
     let if_modified_since = headers
         .get("if-modified-since")
         .map(|h| h.to_str().unwrap_or_default().to_string());
@@ -79,17 +240,34 @@ async fn internal_prefetch_cratesio(
             );
             StatusCode::INTERNAL_SERVER_ERROR
         })? {
+        // Cached index data is served immediately and revalidated in the background,
+        // once it is older than the index TTL.
         PrefetchState::NeedsUpdate(p) => {
-            background_update(name.clone(), sender, if_modified_since, if_none_match);
+            if !offline {
+                background_update(name.clone(), queue, if_modified_since, if_none_match);
+            }
             trace!("Prefetching {} from crates.io cache: Needs Update", name);
-            Ok(p)
+            apply_policy(&policy, &name, p).map_err(PrefetchError::from)
         }
         PrefetchState::UpToDate => {
-            background_update(name.clone(), sender, if_modified_since, if_none_match);
+            if !offline {
+                background_update(name.clone(), queue, if_modified_since, if_none_match);
+            }
             trace!("Prefetching {} from crates.io cache: Up to Date", name);
-            Err(StatusCode::NOT_MODIFIED)
+            Err(StatusCode::NOT_MODIFIED.into())
+        }
+        PrefetchState::NotFound if queue.is_not_found(&name) => {
+            trace!("Crate {} was not found upstream recently", name);
+            Err(StatusCode::NOT_FOUND.into())
+        }
+        PrefetchState::NotFound if offline => {
+            warn!("Crate {} is not cached and the proxy is offline", name);
+            Err(PrefetchError::Offline(name))
+        }
+        PrefetchState::NotFound => {
+            let prefetch = fetch_cratesio_prefetch(client, name.clone(), index_url, queue).await?;
+            apply_policy(&policy, &name, prefetch).map_err(PrefetchError::from)
         }
-        PrefetchState::NotFound => Ok(fetch_cratesio_prefetch(name, sender).await?),
     };
     trace!(
         "internal_prefetch_cratesio {:?}",
@@ -98,37 +276,97 @@ async fn internal_prefetch_cratesio(
     res
 }
 
+/// Adds a pending rule for the crate, such that an admin can approve it.
+async fn request_approval(
+    db: &Arc<dyn DbProvider>,
+    policies: &ProxyPolicies,
+    name: &NormalizedName,
+) {
+    match policies.request_approval(db, name).await {
+        Ok(true) => warn!("Crate {} is pending approval for the proxy", name),
+        Ok(false) => trace!("Skipped approval request for crate {}", name),
+        Err(e) => error!("Could not add pending proxy rule for {}: {}", name, e),
+    }
+}
+
+/// Removes the versions blocked by the policy from the index file. The ETag is changed
+/// if versions are removed, such that cargo does not keep the filtered file once the versions
+/// are allowed.
+fn apply_policy(
+    policy: &ProxyPolicy,
+    name: &OriginalName,
+    prefetch: Prefetch,
+) -> Result<Prefetch, StatusCode> {
+    let (data, blocked) = policy.filter_index(&name.to_normalized(), &prefetch.data);
+    if blocked.is_empty() {
+        return Ok(prefetch);
+    }
+
+    warn!(
+        "Blocked versions {} of crate {} by the proxy policy",
+        blocked.join(", "),
+        name
+    );
+    if data.is_empty() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Prefetch {
+        data,
+        etag: format!("{}-policy", prefetch.etag),
+        last_modified: prefetch.last_modified,
+    })
+}
+
 fn background_update(
     name: OriginalName,
-    sender: &Arc<flume::Sender<CratesioPrefetchMsg>>,
+    queue: &PrefetchQueue,
     if_modified_since: Option<String>,
     if_none_match: Option<String>,
 ) {
-    if let Err(e) = sender.send(CratesioPrefetchMsg::Update(UpdateData {
+    // Not queued if an update of the crate is already pending or it is in backoff.
+    queue.push(CratesioPrefetchMsg::Update(UpdateData {
         name,
         etag: if_none_match,
         last_modified: if_modified_since,
-    })) {
-        error!("Could not send update message: {}", e);
-    }
+    }));
 }
 
 pub async fn background_update_thread(
     db: impl DbProvider,
-    sender: Arc<flume::Sender<CratesioPrefetchMsg>>,
+    queue: Arc<PrefetchQueue>,
+    proxy_mode: Arc<ProxyMode>,
+    api: Option<Arc<UpstreamApi>>,
 ) {
     loop {
+        if proxy_mode.is_offline() {
+            tokio::time::sleep(tokio::time::Duration::from_secs(OFFLINE_POLL_SECS)).await;
+            continue;
+        }
+
         let crates = match db.get_cratesio_index_update_list().await {
             Ok(crates) => crates,
             Err(e) => {
                 error!("Could not get crates.io index update list: {}", e);
+                tokio::time::sleep(tokio::time::Duration::from_secs(ERROR_RETRY_SECS)).await;
                 continue;
             }
         };
 
+        // Waits for free space in the queue instead of dropping updates.
+        let mut names = Vec::with_capacity(crates.len());
         for c in crates {
-            if let Err(e) = sender.send(c) {
-                error!("Could not send update message: {}", e)
+            names.push(c.name().clone());
+            queue.push_wait(c).await;
+        }
+
+        // The metadata is refreshed one crate after the other, as the upstream API is rate limited.
+        if let Some(api) = &api {
+            for name in names {
+                if proxy_mode.is_offline() {
+                    break;
+                }
+                refresh_metadata(&db, api, &name).await;
             }
         }
 
@@ -136,164 +374,195 @@ pub async fn background_update_thread(
     }
 }
 
-async fn fetch_cratesio_description(name: &str) -> Result<Option<String>, StatusCode> {
-    #[derive(Deserialize)]
-    struct Krate {
-        description: Option<String>,
+/// Fetches the metadata of a crate from the upstream API, if it was never fetched
+/// or is outdated. Failures are logged, as the metadata is only shown in the UI.
+async fn refresh_metadata(db: &impl DbProvider, api: &UpstreamApi, name: &OriginalName) {
+    let normalized_name = name.to_normalized();
+    match db.get_cratesio_metadata(&normalized_name).await {
+        Ok(Some((_, updated)))
+            if (chrono::Utc::now() - updated).num_seconds() < METADATA_MAX_AGE_SECS =>
+        {
+            return;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Could not get metadata of {} from database: {}", name, e);
+            return;
+        }
     }
-    #[derive(Deserialize)]
-    struct MinimalCrate {
-        #[serde(rename = "crate")]
-        krate: Krate,
+
+    let Ok(metadata) = api.fetch_metadata(name).await else {
+        return;
+    };
+    if let Err(e) = db
+        .update_cratesio_metadata(&normalized_name, &metadata, &chrono::Utc::now())
+        .await
+    {
+        error!("Could not update metadata of {} in database: {}", name, e);
     }
+}
 
-    //     .unwrap()
-    //     .join(name)
-    //     .unwrap();
-
-    // let response = Client::new()
-    //     .get(url)
-    //     .header("User-Agent", "kellnr.io/kellnr")
-    //     .send()
-    //     .await
-    //     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // let desc = response
-    //     .json::<MinimalCrate>()
-    //     .await
-    //     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Some("".to_owned()))
+/// Fetches the metadata of newly cached crates. The upstream API is rate limited, such that
+/// the metadata is fetched separately from the index inserts, which must not wait for it.
+pub async fn metadata_refresh_thread(
+    db: impl DbProvider,
+    mut names: mpsc::Receiver<OriginalName>,
+    proxy_mode: Arc<ProxyMode>,
+    api: Arc<UpstreamApi>,
+) {
+    while let Some(name) = names.recv().await {
+        // Skipped crates are refreshed by the background update thread later.
+        if !proxy_mode.is_offline() {
+            refresh_metadata(&db, &api, &name).await;
+        }
+    }
 }
 
+/// `metadata_queue` receives the names of newly cached crates, see `metadata_refresh_thread`.
 pub async fn cratesio_prefetch_thread(
     db: Arc<impl DbProvider>,
-    channel: Arc<flume::Receiver<CratesioPrefetchMsg>>,
+    client: Client,
+    queue: Arc<PrefetchQueue>,
+    index_url: String,
+    proxy_mode: Arc<ProxyMode>,
+    metadata_queue: Option<mpsc::Sender<OriginalName>>,
 ) {
-    let cache: Cache<String, String> = Cache::builder()
-        .time_to_live(Duration::from_secs(UPDATE_CACHE_TIMEOUT_SECS))
-        .build();
+    while let Some(msg) = queue.recv().await {
+        let outcome = match get_insert_data(&client, &msg, &index_url, &proxy_mode).await {
+            Ok(Some((metadata, etag, last_modified))) => {
+                insert_prefetch_data(db.as_ref(), msg.name(), metadata, etag, last_modified).await
+            }
+            Ok(None) => PrefetchOutcome::Success,
+            Err(outcome) => outcome,
+        };
 
-    loop {
-        if let Some((name, metadata, desc, etag, last_modified)) =
-            get_insert_data(&cache, &channel).await
-        {
-            trace!("Update crates.io prefetch data for {}", name);
-            if let Err(e) = db
-                .add_cratesio_prefetch_data(
-                    &name,
-                    &etag.unwrap_or_default(),
-                    &last_modified.unwrap_or_default(),
-                    desc,
-                    &metadata,
-                )
-                .await
-            {
-                error!(
-                    "Could not insert prefetch data from crates.io into database for {}: {}",
-                    name, e
-                );
+        if let PrefetchOutcome::Failed(e) = &outcome {
+            // Retries are controlled by the backoff of the queue.
+            error!("Could not update prefetch data for {}: {}", msg.name(), e);
+        }
+        // The metadata of cached crates is refreshed by the background update thread.
+        let new_crate = matches!(msg, CratesioPrefetchMsg::Insert(_));
+        if new_crate && outcome == PrefetchOutcome::Success {
+            if let Some(metadata_queue) = &metadata_queue {
+                // If the queue is full, the metadata is fetched by the background update.
+                if metadata_queue.try_send(msg.name().clone()).is_err() {
+                    trace!("Metadata queue is full, skipped {}", msg.name());
+                }
             }
         }
+        queue.complete(&msg, outcome);
     }
 }
 
-async fn convert_index_data(
+async fn insert_prefetch_data(
+    db: &(impl DbProvider + ?Sized),
     name: &OriginalName,
-    data: String,
-) -> Option<(Vec<IndexMetadata>, Option<String>)> {
-    let metadata: Result<Vec<IndexMetadata>, serde_json::Error> = data
-        .lines()
-        .map(serde_json::from_str::<IndexMetadata>)
-        .collect();
-
-    match metadata {
-        Ok(m) => {
-            let desc = fetch_cratesio_description(name).await.unwrap_or_else(|e| {
-                error!(
-                    "Could not fetch description for from crates.io {}: {:?}",
-                    name, e
-                );
-                None
-            });
+    metadata: Vec<IndexMetadata>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+) -> PrefetchOutcome {
+    trace!("Update crates.io prefetch data for {}", name);
+    match db
+        .add_cratesio_prefetch_data(
+            name,
+            &etag.unwrap_or_default(),
+            &last_modified.unwrap_or_default(),
+            None,
+            &metadata,
+        )
+        .await
+    {
+        Ok(_) => PrefetchOutcome::Success,
+        Err(e) => PrefetchOutcome::Failed(format!("Could not insert into database: {}", e)),
+    }
+}
 
-            Some((m, desc))
-        }
-        Err(e) => {
-            error!(
-                "Could not parse prefetch data from crates.io for {}: {}",
-                name, e
-            );
-            None
-        }
+/// Fetches the index file of a crate from the upstream and stores it, independent of
+/// the ETag of the cached file and the update queue.
+pub async fn refresh_crate_index(
+    db: &Arc<dyn DbProvider>,
+    client: &Client,
+    index_url: &str,
+    name: &OriginalName,
+) -> Result<(), String> {
+    let Some((metadata, etag, last_modified)) =
+        fetch_index_data(client, index_url, name, &None, &None).await?
+    else {
+        return Err(format!("Crate {} not found upstream", name));
+    };
+
+    match insert_prefetch_data(db.as_ref(), name, metadata, etag, last_modified).await {
+        PrefetchOutcome::Failed(e) => Err(e),
+        _ => Ok(()),
     }
 }
 
+/// Checks whether a crate exists in the index of the upstream, independent of the cache.
+pub async fn exists_upstream(
+    client: &Client,
+    index_url: &str,
+    name: &OriginalName,
+) -> Result<bool, String> {
+    Ok(fetch_index_data(client, index_url, name, &None, &None)
+        .await?
+        .is_some())
+}
+
+pub(crate) fn convert_index_data(data: &str) -> Result<Vec<IndexMetadata>, String> {
+    data.lines()
+        .map(serde_json::from_str::<IndexMetadata>)
+        .collect::<Result<Vec<IndexMetadata>, serde_json::Error>>()
+        .map_err(|e| format!("Could not parse index data: {}", e))
+}
+
+/// Returns the index data to insert for a message. `None` if the index is unchanged.
 async fn get_insert_data(
-    cache: &Cache<String, String>,
-    channel: &Arc<flume::Receiver<CratesioPrefetchMsg>>,
-) -> Option<(
-    OriginalName,
-    Vec<IndexMetadata>,
-    Option<String>,
-    Option<String>,
-    Option<String>,
-)> {
-    match channel.recv_async().await {
-        Ok(CratesioPrefetchMsg::Insert(msg)) => {
+    client: &Client,
+    msg: &CratesioPrefetchMsg,
+    index_url: &str,
+    proxy_mode: &ProxyMode,
+) -> Result<Option<IndexData>, PrefetchOutcome> {
+    match msg {
+        CratesioPrefetchMsg::Insert(msg) => {
             trace!("Inserting prefetch data from crates.io for {}", msg.name);
-            let date = chrono::Utc::now().to_rfc3339();
-            cache.insert(msg.name.to_string(), date).await;
-            convert_index_data(&msg.name, msg.data)
-                .await
-                .map(|(m, d)| (msg.name.clone(), m, d, msg.etag, msg.last_modified))
+            let metadata = convert_index_data(&msg.data).map_err(PrefetchOutcome::Failed)?;
+            Ok(Some((
+                metadata,
+                msg.etag.clone(),
+                msg.last_modified.clone(),
+            )))
         }
-        Ok(CratesioPrefetchMsg::Update(msg)) => {
-            trace!("Updating prefetch data for {}", msg.name);
-            if let Some(date) = cache.get(msg.name.deref()).await {
-                trace!("No update needed for {}. Last update: {}", msg.name, date);
-                None
-            } else {
-                cache
-                    .insert(msg.name.to_string(), chrono::Utc::now().to_rfc3339())
-                    .await;
-                fetch_index_data(msg.name, msg.etag, msg.last_modified).await
-            }
+        CratesioPrefetchMsg::Update(msg) if proxy_mode.is_offline() => {
+            trace!("Skipping update of {} as the proxy is offline", msg.name);
+            Err(PrefetchOutcome::Skipped)
         }
-        Err(e) => {
-            error!("Could not receive prefetch message: {}", e);
-            None
+        CratesioPrefetchMsg::Update(msg) => {
+            // Fresh crates are not queued, see `PrefetchQueue::with_ttl`.
+            trace!("Updating prefetch data for {}", msg.name);
+            fetch_index_data(client, index_url, &msg.name, &msg.etag, &msg.last_modified)
+                .await
+                .map_err(PrefetchOutcome::Failed)
         }
     }
 }
 
+/// Index entries, ETag and Last-Modified header of an index file.
+type IndexData = (Vec<IndexMetadata>, Option<String>, Option<String>);
+
 async fn fetch_index_data(
-    name: OriginalName,
-    etag: Option<String>,
-    last_modified: Option<String>,
-) -> Option<(
-    OriginalName,
-    Vec<IndexMetadata>,
-    Option<String>,
-    Option<String>,
-    Option<String>,
-)> {
-    let url = match Url::parse("https://rsproxy.cn/index/")
-        .unwrap()
-        .join(&crate_sub_path(&name.to_normalized()))
-    {
-        Ok(url) => url,
-        Err(e) => {
-            error!("Could not parse crates.io url for {}: {}", name, e);
-            return None;
-        }
-    };
+    client: &Client,
+    index_url: &str,
+    name: &OriginalName,
+    etag: &Option<String>,
+    last_modified: &Option<String>,
+) -> Result<Option<IndexData>, String> {
+    let url = crate_index_url(index_url, &name.to_normalized())
+        .map_err(|e| format!("Could not parse index url: {}", e))?;
 
-    let max_retries = 3;
+    let mut retries = 0;
     let r = loop {
-        let mut i = 0;
-        match Client::new()
+        match client
             .get(url.clone())
-            .header("User-Agent", "kellnr.io/kellnr")
             .header("If-None-Match", etag.clone().unwrap_or_default())
             .header(
                 "If-Modified-Since",
@@ -302,86 +571,98 @@ async fn fetch_index_data(
             .send()
             .await
         {
-            Ok(response) => break Some(response),
-            Err(e) => {
+            Ok(response) => break response,
+            Err(e) if retries < FETCH_RETRIES => {
+                retries += 1;
                 warn!(
                     "Retry {}/{} - Could not fetch index from crates.io for {}: {}",
-                    i + 1,
-                    max_retries,
-                    name,
-                    e
+                    retries, FETCH_RETRIES, name, e
                 );
-                i += 1;
+                let delay = FETCH_RETRY_DELAY_MS << (retries - 1);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Could not fetch index after {} retries: {}",
+                    FETCH_RETRIES, e
+                ))
             }
-        };
-        if i > max_retries {
-            error!(
-                "Could not fetch index from crates.io for {} after 3 tries",
-                name
-            );
-            break None;
         }
     };
 
-    if let Some(r) = r {
-        match r.status() {
-            reqwest::StatusCode::NOT_MODIFIED => {
-                trace!("Index not-modified for {}", name);
-                None
-            }
-            reqwest::StatusCode::NOT_FOUND => {
-                trace!("Index not found for {}", name);
-                None
-            }
-            reqwest::StatusCode::OK => {
-                let headers = r.headers();
-                let etag = headers
-                    .get("ETag")
-                    .map(|h| h.to_str().unwrap_or_default().to_string());
-                let last_modified = headers
-                    .get("Last-Modified")
-                    .map(|h| h.to_str().unwrap_or_default().to_string());
-
-                let data = match r.text().await {
-                    Ok(data) => data,
-                    Err(e) => {
-                        error!("Could not read index from crates.io for {}: {}", name, e);
-                        return None;
-                    }
-                };
-
-                trace!("OK convert_index_data");
-                convert_index_data(&name, data)
-                    .await
-                    .map(|(m, d)| (name, m, d, etag, last_modified))
-            }
-            s => {
-                error!("Unexpected status code from crates.io for {}: {}", name, s);
-                None
-            }
+    match r.status() {
+        reqwest::StatusCode::NOT_MODIFIED => {
+            trace!("Index not-modified for {}", name);
+            Ok(None)
+        }
+        reqwest::StatusCode::NOT_FOUND => {
+            trace!("Index not found for {}", name);
+            Ok(None)
+        }
+        reqwest::StatusCode::OK => {
+            let headers = r.headers();
+            let etag = headers
+                .get("ETag")
+                .map(|h| h.to_str().unwrap_or_default().to_string());
+            let last_modified = headers
+                .get("Last-Modified")
+                .map(|h| h.to_str().unwrap_or_default().to_string());
+
+            let data = r
+                .text()
+                .await
+                .map_err(|e| format!("Could not read index: {}", e))?;
+
+            trace!("OK convert_index_data");
+            Ok(Some((convert_index_data(&data)?, etag, last_modified)))
         }
-    } else {
-        None
+        s => Err(format!("Unexpected status code: {}", s)),
     }
 }
 
 async fn fetch_cratesio_prefetch(
+    client: &Client,
     name: OriginalName,
-    sender: &Arc<flume::Sender<CratesioPrefetchMsg>>,
+    index_url: &str,
+    queue: &PrefetchQueue,
 ) -> Result<Prefetch, StatusCode> {
-    let instance = Instant::now();
-    let url = Url::parse("https://rsproxy.cn/index/")
-        .unwrap()
-        .join(&crate_sub_path(&name.to_normalized()))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let url = crate_index_url(index_url, &name.to_normalized()).map_err(|e| {
+        error!("Could not parse crates.io url for {}: {}", name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Concurrent requests of the same index file share a single upstream request,
+    // such that the index data is also queued only once.
+    INDEX_FETCHES
+        .run(url.to_string(), || {
+            request_prefetch(client, url, name, queue)
+        })
+        .await
+}
 
-    let response = Client::new()
-        .get(url)
-        .header("User-Agent", "kellnr.io/kellnr")
-        .send()
-        .await;
+async fn request_prefetch(
+    client: &Client,
+    url: Url,
+    name: OriginalName,
+    queue: &PrefetchQueue,
+) -> Result<Prefetch, StatusCode> {
+    let instance = Instant::now();
+    let response = client.get(url).send().await;
 
     let res = match response {
+        Ok(r) if r.status() == reqwest::StatusCode::NOT_FOUND => {
+            trace!("Crate {} not found upstream", name);
+            queue.set_not_found(&name);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Ok(r) if !r.status().is_success() => {
+            error!(
+                "Error fetching prefetch data from crates.io for {}: {}",
+                name,
+                r.status()
+            );
+            Err(StatusCode::NOT_FOUND)
+        }
         Ok(r) => {
             let headers = r.headers();
             let etag = headers
@@ -405,17 +686,15 @@ async fn fetch_cratesio_prefetch(
             // Send a message to the prefetch thread to asynchronously update the database.
             // Else we need to wait for the database to update before we can return the response,
             // which would take a long time.
-            sender
-                .send(CratesioPrefetchMsg::Insert(InsertData {
-                    name,
-                    etag,
-                    last_modified,
-                    data,
-                }))
-                .map_err(|e| {
-                    error!("Could not send prefetch message: {}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?;
+            // If the queue is full, the crate is fetched again on the next request.
+            if !queue.push(CratesioPrefetchMsg::Insert(InsertData {
+                name: name.clone(),
+                etag,
+                last_modified,
+                data,
+            })) {
+                warn!("Prefetch queue is full, {} is not cached", name);
+            }
 
             Ok(prefetch)
         }
@@ -431,6 +710,13 @@ async fn fetch_cratesio_prefetch(
     res
 }
 
+/// URL of the index file of a crate in the upstream sparse index.
+fn crate_index_url(index_url: &str, name: &NormalizedName) -> anyhow::Result<Url> {
+    // Without the trailing slash, the last segment of the index URL would be replaced.
+    let index_url = Url::parse(&format!("{}/", index_url.trim_end_matches('/')))?;
+    Ok(index_url.join(&crate_sub_path(name))?)
+}
+
 fn crate_sub_path(name: &NormalizedName) -> String {
     match name.len() {
         1 => format!("1/{}", name),
@@ -458,15 +744,16 @@ mod tests {
         routing::get,
         Router,
     };
+    use common::proxy_policy::{PolicyAction, ProxyRule};
     use db::mock::MockDb;
     use http_body_util::BodyExt;
     use settings::{Protocol, Settings};
-    use std::mem;
     use tower::ServiceExt;
 
     #[tokio::test]
     async fn fetch_cratesio_description_works() {
-        let desc = fetch_cratesio_description("rocket").await.unwrap();
+        let api = UpstreamApi::new(Client::new(), "https://crates.io", Duration::ZERO);
+        let desc = api.fetch_metadata("rocket").await.unwrap().description;
         assert_eq!(
             Some(
                 "Web framework with a focus on usability, security, extensibility, and speed.\n"
@@ -478,10 +765,68 @@ mod tests {
 
     #[tokio::test]
     async fn fetch_cratesio_description_not_existent_crate() {
-        let desc = fetch_cratesio_description("does_not_exists123").await;
+        let api = UpstreamApi::new(Client::new(), "https://crates.io", Duration::ZERO);
+        let desc = api.fetch_metadata("does_not_exists123").await;
         assert_eq!(Err(StatusCode::INTERNAL_SERVER_ERROR), desc);
     }
 
+    #[test]
+    fn crate_index_url_keeps_index_path() {
+        let name = NormalizedName::from_unchecked("serde".to_string());
+
+        for index_url in [
+            "https://mirror.example/index",
+            "https://mirror.example/index/",
+        ] {
+            assert_eq!(
+                "https://mirror.example/index/se/rd/serde",
+                crate_index_url(index_url, &name).unwrap().as_str()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn config_upstream_returns_config_json() {
+        let r = app()
+            .await
+            .oneshot(
+                Request::get("/api/v1/proxy/my-registry/config.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let result_msg = r.into_body().collect().await.unwrap().to_bytes();
+        let actual = serde_json::from_slice::<ConfigJson>(&result_msg).unwrap();
+
+        assert_eq!(
+            ConfigJson::new(
+                &Protocol::Http,
+                "test.api.com",
+                1234,
+                "proxy/my-registry",
+                false
+            ),
+            actual
+        );
+    }
+
+    #[tokio::test]
+    async fn prefetch_unknown_upstream_returns_not_found() {
+        let r = app()
+            .await
+            .oneshot(
+                Request::get("/api/v1/proxy/unknown/ro/ck/rocket")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::NOT_FOUND);
+    }
+
     #[tokio::test]
     async fn fetch_cratesio_prefetch_works() {
         let r = app()
@@ -502,6 +847,90 @@ mod tests {
         assert!(prefetch.len() > 500);
     }
 
+    #[tokio::test]
+    async fn prefetch_denied_crate_returns_forbidden() {
+        let r = app()
+            .await
+            .oneshot(
+                Request::get("/api/v1/cratesio/bl/oc/blocked-crate")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn prefetch_uncached_crate_while_offline() {
+        let r = app_with_proxy_mode(ProxyMode::new(true))
+            .await
+            .oneshot(
+                Request::get("/api/v1/cratesio/ro/ck/rocket")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = r.status();
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            "The Kellnr proxy is in offline mode and crate rocket is not cached",
+            String::from_utf8_lossy(&body)
+        );
+    }
+
+    #[tokio::test]
+    async fn prefetch_crate_not_found_upstream_recently() {
+        let queue = PrefetchQueue::new(100).with_ttl(Duration::ZERO, Duration::from_secs(60));
+        queue.set_not_found(&OriginalName::from_unchecked_str("rocket".to_string()));
+
+        // Offline, such that a request to the upstream would return 503.
+        let r = app_with_queue(ProxyMode::new(true), queue)
+            .await
+            .oneshot(
+                Request::get("/api/v1/cratesio/ro/ck/rocket")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn apply_policy_filters_blocked_versions() {
+        let policy = ProxyPolicy::new(
+            &[ProxyRule {
+                id: 1,
+                pattern: "rocket".to_string(),
+                version_req: "<0.5".to_string(),
+                action: PolicyAction::Pending,
+                created: String::new(),
+            }],
+            false,
+        );
+        let name = OriginalName::from_unchecked_str("rocket".to_string());
+        let prefetch = Prefetch {
+            data: b"{\"name\":\"rocket\",\"vers\":\"0.4.0\"}\n{\"name\":\"rocket\",\"vers\":\"0.5.0\"}\n"
+                .to_vec(),
+            etag: "etag".to_string(),
+            last_modified: "date".to_string(),
+        };
+
+        let filtered = apply_policy(&policy, &name, prefetch).unwrap();
+
+        assert_eq!(
+            b"{\"name\":\"rocket\",\"vers\":\"0.5.0\"}\n".to_vec(),
+            filtered.data
+        );
+        assert_eq!("etag-policy", filtered.etag);
+    }
+
     #[tokio::test]
     async fn config_returns_config_json() {
         let r = app()
@@ -524,6 +953,14 @@ mod tests {
     }
 
     async fn app() -> Router {
+        app_with_proxy_mode(ProxyMode::new(false)).await
+    }
+
+    async fn app_with_proxy_mode(proxy_mode: ProxyMode) -> Router {
+        app_with_queue(proxy_mode, PrefetchQueue::new(100)).await
+    }
+
+    async fn app_with_queue(proxy_mode: ProxyMode, queue: PrefetchQueue) -> Router {
         let settings = Settings {
             origin: settings::Origin {
                 protocol: Protocol::Http,
@@ -549,26 +986,51 @@ mod tests {
             .expect_is_cratesio_cache_up_to_date()
             .returning(move |_, _, _| Ok(PrefetchState::NotFound));
 
-        let (sender, receiver) = flume::unbounded::<CratesioPrefetchMsg>();
-        let sender = Arc::new(sender);
-        // Make receiver undroppable, else the sender will fail, as the receiver is dropped when
-        // this function goes out of scope
-        mem::forget(receiver);
+        mock_db.expect_get_proxy_rules().returning(|| {
+            Ok(vec![ProxyRule {
+                id: 1,
+                pattern: "blocked-*".to_string(),
+                version_req: "*".to_string(),
+                action: PolicyAction::Deny,
+                created: String::new(),
+            }])
+        });
+
+        let queue = Arc::new(queue);
 
         let cratesio_prefetch = Router::new()
             .route("/config.json", get(config_cratesio))
             .route("/:_/:_/:name", get(prefetch_cratesio))
             .route("/:_/:name", get(prefetch_len2_cratesio));
 
+        let upstream_prefetch = Router::new()
+            .route("/:upstream/config.json", get(config_upstream))
+            .route("/:upstream/:_/:_/:name", get(prefetch_upstream))
+            .route("/:upstream/:_/:name", get(prefetch_len2_upstream));
+
+        let state = appstate::test_state().await;
+        let upstream = UpstreamProxy {
+            settings: settings::proxy::Upstream {
+                name: "my-registry".to_string(),
+                index_url: "https://index.my-registry.example/".to_string(),
+                download_url: "https://dl.my-registry.example/".to_string(),
+            },
+            db: state.db.clone(),
+            storage: state.cratesio_storage.clone(),
+            prefetch_queue: queue.clone(),
+        };
         let state = AppStateData {
             db: Arc::new(mock_db),
             settings: Arc::new(settings),
-            cratesio_prefetch_sender: sender,
-            ..appstate::test_state().await
+            cratesio_prefetch_queue: queue,
+            upstreams: Arc::new(Upstreams::from([("my-registry".to_string(), upstream)])),
+            proxy_mode: Arc::new(proxy_mode),
+            ..state
         };
 
         Router::new()
             .nest("/api/v1/cratesio", cratesio_prefetch)
+            .nest("/api/v1/proxy", upstream_prefetch)
             .with_state(state)
     }
 }
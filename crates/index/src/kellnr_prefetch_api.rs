@@ -43,7 +43,7 @@ async fn internal_kellnr_prefetch(
     }
 }
 
-fn needs_update(headers: &HeaderMap, prefetch: &Prefetch) -> bool {
+pub(crate) fn needs_update(headers: &HeaderMap, prefetch: &Prefetch) -> bool {
     let if_none_match = headers.get("if-none-match");
     let if_modified_since = headers.get("if-modified-since");
     match (if_none_match, if_modified_since) {
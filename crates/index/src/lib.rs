@@ -1,3 +1,6 @@
 mod config_json;
 pub mod cratesio_prefetch_api;
 pub mod kellnr_prefetch_api;
+pub mod seed;
+pub mod unified_prefetch_api;
+pub mod upstream_api;
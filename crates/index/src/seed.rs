@@ -0,0 +1,216 @@
+use crate::cratesio_prefetch_api::convert_index_data;
+use anyhow::Context;
+use common::original_name::OriginalName;
+use db::DbProvider;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SeedStats {
+    pub crates: usize,
+    pub versions: usize,
+    pub failed: usize,
+}
+
+/// Seeds the cached index of a proxy from a local checkout of its index, e.g. for
+/// air-gapped installations. The crates are inserted concurrently in batches of `batch_size`.
+/// Seeded crates have no ETag, such that they are updated on the next background update
+/// while the proxy is online.
+pub async fn seed_index(
+    db: &Arc<dyn DbProvider>,
+    index_dir: &Path,
+    batch_size: usize,
+) -> anyhow::Result<SeedStats> {
+    let files = index_files(index_dir)
+        .with_context(|| format!("Failed to read index checkout {}", index_dir.display()))?;
+    info!(
+        "Seeding {} crates from {}",
+        files.len(),
+        index_dir.display()
+    );
+
+    let mut stats = SeedStats::default();
+    for batch in files.chunks(batch_size.max(1)) {
+        let mut inserts = JoinSet::new();
+        for path in batch {
+            let metadata = match tokio::fs::read_to_string(path).await {
+                Ok(data) => convert_index_data(&data),
+                Err(e) => Err(format!("Could not read index file: {}", e)),
+            };
+            let metadata = match metadata {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    stats.failed += 1;
+                    continue;
+                }
+            };
+            // The file name is normalized, the index entries contain the original name.
+            let Some(name) = metadata.first().map(|m| m.name.clone()) else {
+                continue;
+            };
+
+            let db = db.clone();
+            inserts.spawn(async move {
+                let name = OriginalName::from_unchecked_str(name);
+                db.add_cratesio_prefetch_data(&name, "", "", None, &metadata)
+                    .await
+                    .map(|_| metadata.len())
+                    .map_err(|e| format!("Could not seed crate {}: {}", name, e))
+            });
+        }
+
+        while let Some(result) = inserts.join_next().await {
+            match result {
+                Ok(Ok(versions)) => {
+                    stats.crates += 1;
+                    stats.versions += versions;
+                }
+                Ok(Err(e)) => {
+                    error!("{}", e);
+                    stats.failed += 1;
+                }
+                Err(e) => {
+                    error!("Seed task failed: {}", e);
+                    stats.failed += 1;
+                }
+            }
+        }
+        info!(
+            "Seeded {} of {} crates",
+            stats.crates + stats.failed,
+            files.len()
+        );
+    }
+
+    Ok(stats)
+}
+
+/// Index files of all crates in an index checkout, i.e. all files except `config.json`
+/// and hidden files like the `.git` directory.
+pub fn index_files(index_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![index_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            if file_name.starts_with('.') || (dir == index_dir && file_name == "config.json") {
+                continue;
+            }
+
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::index_metadata::IndexMetadata;
+    use common::prefetch::Prefetch;
+    use common::util::generate_rand_string;
+    use db::mock::MockDb;
+    use mockall::predicate::*;
+
+    struct TestDir(PathBuf);
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            rm_rf::remove(&self.0).expect("Cannot remove test directory")
+        }
+    }
+
+    fn prefetch() -> Prefetch {
+        Prefetch {
+            data: Vec::new(),
+            etag: String::new(),
+            last_modified: String::new(),
+        }
+    }
+
+    fn index_line(name: &str, vers: &str) -> String {
+        format!(
+            r#"{{"name":"{name}","vers":"{vers}","deps":[],"cksum":"abc","features":{{}},"yanked":false}}"#
+        )
+    }
+
+    fn test_index() -> TestDir {
+        let dir = TestDir(PathBuf::from("/tmp").join(generate_rand_string(10)));
+        std::fs::create_dir_all(dir.0.join("se/rd")).unwrap();
+        std::fs::create_dir_all(dir.0.join("1")).unwrap();
+        std::fs::create_dir_all(dir.0.join(".git")).unwrap();
+        std::fs::write(dir.0.join("config.json"), r#"{"dl":"https://dl"}"#).unwrap();
+        std::fs::write(dir.0.join(".git/HEAD"), "ref: refs/heads/master").unwrap();
+        std::fs::write(
+            dir.0.join("se/rd/serde"),
+            format!(
+                "{}\n{}\n",
+                index_line("serde", "1.0.0"),
+                index_line("serde", "1.0.1")
+            ),
+        )
+        .unwrap();
+        std::fs::write(dir.0.join("1/a"), index_line("A", "0.1.0")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn index_files_skip_config_and_hidden_files() {
+        let dir = test_index();
+
+        let files = index_files(&dir.0).unwrap();
+
+        assert_eq!(vec![dir.0.join("1/a"), dir.0.join("se/rd/serde")], files);
+    }
+
+    #[tokio::test]
+    async fn seed_index_inserts_all_crates() {
+        let dir = test_index();
+        std::fs::write(dir.0.join("1/b"), "not json").unwrap();
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_add_cratesio_prefetch_data()
+            .with(
+                eq(OriginalName::from_unchecked_str("serde".to_string())),
+                eq(""),
+                eq(""),
+                eq(None::<String>),
+                function(|m: &[IndexMetadata]| m.len() == 2),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(prefetch()));
+        mock_db
+            .expect_add_cratesio_prefetch_data()
+            .with(
+                eq(OriginalName::from_unchecked_str("A".to_string())),
+                eq(""),
+                eq(""),
+                eq(None::<String>),
+                function(|m: &[IndexMetadata]| m.len() == 1),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(prefetch()));
+        let db = Arc::new(mock_db) as Arc<dyn DbProvider>;
+
+        let stats = seed_index(&db, &dir.0, 1).await.unwrap();
+
+        assert_eq!(
+            SeedStats {
+                crates: 2,
+                versions: 3,
+                failed: 1
+            },
+            stats
+        );
+    }
+}
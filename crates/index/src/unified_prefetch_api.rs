@@ -0,0 +1,297 @@
+use super::config_json::ConfigJson;
+use crate::cratesio_prefetch_api::{convert_index_data, internal_prefetch_cratesio, PrefetchError};
+use crate::kellnr_prefetch_api::needs_update;
+use appstate::{
+    DbState, HttpClientState, PrefetchQueue, PrefetchQueueState, ProxyMode, ProxyModeState,
+    ProxyPolicies, ProxyPoliciesState, SettingsState,
+};
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use common::index_metadata::IndexMetadata;
+use common::original_name::OriginalName;
+use common::prefetch::Prefetch;
+use db::error::DbError;
+use db::DbProvider;
+use hyper::StatusCode;
+use reqwest::Client;
+use settings::Settings;
+use std::sync::Arc;
+use tracing::error;
+
+// Index URLs with which crates published to Kellnr refer to crates.io dependencies.
+const CRATESIO_INDEX_URLS: [&str; 2] = [
+    "https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+pub async fn config_unified(
+    State(settings): SettingsState,
+) -> Result<Json<ConfigJson>, StatusCode> {
+    check_enabled(&settings)?;
+    Ok(Json(ConfigJson::from((&(*settings), "unified"))))
+}
+
+pub async fn prefetch_unified(
+    Path((_a, _b, name)): Path<(String, String, OriginalName)>,
+    headers: HeaderMap,
+    State(db): DbState,
+    State(settings): SettingsState,
+    State(queue): PrefetchQueueState,
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+) -> Result<Prefetch, PrefetchError> {
+    check_enabled(&settings)?;
+    internal_prefetch_unified(
+        name,
+        headers,
+        &db,
+        &settings,
+        &queue,
+        &proxy_mode,
+        &policies,
+        &client,
+    )
+    .await
+}
+
+pub async fn prefetch_len2_unified(
+    Path((_a, name)): Path<(String, OriginalName)>,
+    headers: HeaderMap,
+    State(db): DbState,
+    State(settings): SettingsState,
+    State(queue): PrefetchQueueState,
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+) -> Result<Prefetch, PrefetchError> {
+    check_enabled(&settings)?;
+    internal_prefetch_unified(
+        name,
+        headers,
+        &db,
+        &settings,
+        &queue,
+        &proxy_mode,
+        &policies,
+        &client,
+    )
+    .await
+}
+
+fn check_enabled(settings: &Settings) -> Result<(), StatusCode> {
+    match settings.proxy.enabled && settings.proxy.unified_index {
+        true => Ok(()),
+        false => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Serves the index file of a Kellnr crate, or of the crates.io crate with the same
+/// name if there is no such Kellnr crate.
+#[allow(clippy::too_many_arguments)]
+async fn internal_prefetch_unified(
+    name: OriginalName,
+    headers: HeaderMap,
+    db: &Arc<dyn DbProvider>,
+    settings: &Settings,
+    queue: &PrefetchQueue,
+    proxy_mode: &ProxyMode,
+    policies: &ProxyPolicies,
+    client: &Client,
+) -> Result<Prefetch, PrefetchError> {
+    let normalized_name = name.to_normalized();
+    match db.get_prefetch_data(&normalized_name).await {
+        Ok(prefetch) if needs_update(&headers, &prefetch) => Ok(Prefetch {
+            data: rewrite_registries(&prefetch.data)?,
+            ..prefetch
+        }),
+        Ok(_) => Err(StatusCode::NOT_MODIFIED.into()),
+        Err(DbError::CrateNotFound(_)) => {
+            internal_prefetch_cratesio(
+                name,
+                headers,
+                db,
+                client,
+                &settings.proxy.index_url,
+                settings.proxy.approval_required,
+                policies,
+                proxy_mode,
+                queue,
+            )
+            .await
+        }
+        Err(e) => {
+            error!("Could not get index of crate {}: {}", name, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// Kellnr crates refer to their crates.io dependencies with the crates.io index URL.
+/// In the unified index, crates.io crates are in the same registry, i.e. have no URL.
+fn rewrite_registries(data: &[u8]) -> Result<Vec<u8>, StatusCode> {
+    let mut indices = std::str::from_utf8(data)
+        .map_err(|e| e.to_string())
+        .and_then(convert_index_data)
+        .map_err(|e| {
+            error!("Could not parse index of Kellnr crate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    for dep in indices.iter_mut().flat_map(|i| i.deps.iter_mut()) {
+        if dep
+            .registry
+            .as_deref()
+            .is_some_and(|r| CRATESIO_INDEX_URLS.contains(&r))
+        {
+            dep.registry = None;
+        }
+    }
+
+    IndexMetadata::serialize_indices(&indices)
+        .map(String::into_bytes)
+        .map_err(|e| {
+            error!("Could not serialize index of Kellnr crate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use appstate::AppStateData;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use common::index_metadata::{DependencyKind, IndexDep};
+    use db::mock::MockDb;
+    use http_body_util::BodyExt;
+    use mockall::predicate::*;
+    use settings::Protocol;
+    use tower::ServiceExt;
+
+    fn dep(name: &str, registry: Option<&str>) -> IndexDep {
+        IndexDep {
+            name: name.to_string(),
+            req: "^1.0".to_string(),
+            features: Vec::new(),
+            optional: false,
+            default_features: true,
+            target: None,
+            kind: Some(DependencyKind::Normal),
+            registry: registry.map(String::from),
+            package: None,
+        }
+    }
+
+    fn kellnr_index() -> Vec<u8> {
+        let mut metadata = IndexMetadata::minimal("internal", "1.0.0", "cksum");
+        metadata.deps = vec![
+            dep(
+                "serde",
+                Some("https://github.com/rust-lang/crates.io-index"),
+            ),
+            dep("other-internal", None),
+            dep("foreign", Some("https://index.other-registry.example/")),
+        ];
+        IndexMetadata::serialize_indices(&[metadata])
+            .unwrap()
+            .into_bytes()
+    }
+
+    #[test]
+    fn rewrite_registries_removes_cratesio_urls() {
+        let data = rewrite_registries(&kellnr_index()).unwrap();
+
+        let metadata = convert_index_data(std::str::from_utf8(&data).unwrap()).unwrap();
+        let registries: Vec<_> = metadata[0]
+            .deps
+            .iter()
+            .map(|d| d.registry.as_deref())
+            .collect();
+        assert_eq!(
+            vec![None, None, Some("https://index.other-registry.example/")],
+            registries
+        );
+    }
+
+    #[tokio::test]
+    async fn config_returns_unified_config_json() {
+        let r = app(true)
+            .await
+            .oneshot(Request::get("/config.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let result_msg = r.into_body().collect().await.unwrap().to_bytes();
+        let actual = serde_json::from_slice::<ConfigJson>(&result_msg).unwrap();
+        assert_eq!(
+            ConfigJson::new(&Protocol::Http, "test.api.com", 1234, "unified", false),
+            actual
+        );
+    }
+
+    #[tokio::test]
+    async fn disabled_unified_index_returns_not_found() {
+        let config = app(false)
+            .await
+            .oneshot(Request::get("/config.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let prefetch = app(false)
+            .await
+            .oneshot(Request::get("/in/te/internal").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, config.status());
+        assert_eq!(StatusCode::NOT_FOUND, prefetch.status());
+    }
+
+    #[tokio::test]
+    async fn prefetch_serves_kellnr_crate() {
+        let r = app(true)
+            .await
+            .oneshot(Request::get("/in/te/internal").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let data = r.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(rewrite_registries(&kellnr_index()).unwrap(), data);
+    }
+
+    async fn app(unified_index: bool) -> Router {
+        let mut settings = Settings {
+            origin: settings::Origin {
+                protocol: Protocol::Http,
+                hostname: String::from("test.api.com"),
+                port: 1234,
+            },
+            ..Settings::default()
+        };
+        settings.proxy.enabled = true;
+        settings.proxy.unified_index = unified_index;
+
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_get_prefetch_data()
+            .with(eq("internal"))
+            .returning(|_| {
+                Ok(Prefetch {
+                    data: kellnr_index(),
+                    etag: String::from("etag"),
+                    last_modified: String::from("date"),
+                })
+            });
+
+        Router::new()
+            .route("/config.json", get(config_unified))
+            .route("/:a/:b/:name", get(prefetch_unified))
+            .route("/:a/:name", get(prefetch_len2_unified))
+            .with_state(AppStateData {
+                db: Arc::new(mock_db),
+                settings: Arc::new(settings),
+                ..appstate::test_state().await
+            })
+    }
+}
@@ -0,0 +1,83 @@
+use common::cratesio_metadata::{CratesIoCrate, CratesIoMetadata};
+use hyper::StatusCode;
+use reqwest::Client;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::error;
+
+/// Client for the web API of an upstream registry. Requests are spaced by a minimal
+/// interval, as crates.io allows at most one request per second for crawlers.
+pub struct UpstreamApi {
+    api_url: String,
+    client: Client,
+    request_interval: Duration,
+    next_request: Mutex<Instant>,
+}
+
+impl UpstreamApi {
+    pub fn new(client: Client, api_url: &str, request_interval: Duration) -> Self {
+        Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            client,
+            request_interval,
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Fetches the metadata of a crate, e.g. its description and repository.
+    pub async fn fetch_metadata(&self, name: &str) -> Result<CratesIoMetadata, StatusCode> {
+        self.wait_for_slot().await;
+
+        let url = format!("{}/api/v1/crates/{}", self.api_url, name);
+        let response = self
+            .client
+            .get(&url)
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                error!("Could not fetch metadata of {} from {}: {}", name, url, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        let krate = response.json::<CratesIoCrate>().await.map_err(|e| {
+            error!("Could not parse metadata of {}: {}", name, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(krate.krate)
+    }
+
+    /// Reserves the next free request slot and waits until it is reached.
+    async fn wait_for_slot(&self) {
+        let slot = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let slot = (*next_request).max(Instant::now());
+            *next_request = slot + self.request_interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn requests_are_spaced_by_interval() {
+        let api = UpstreamApi::new(
+            Client::new(),
+            "https://crates.io",
+            Duration::from_millis(50),
+        );
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            api.wait_for_slot().await;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
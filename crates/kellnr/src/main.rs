@@ -1,4 +1,7 @@
-use appstate::AppStateData;
+use appstate::{
+    upstream_client, AppStateData, PrefetchQueue, ProxyMode, ProxyPolicies, UpstreamProxy,
+    Upstreams,
+};
 use axum::{
     extract::DefaultBodyLimit,
     middleware,
@@ -6,33 +9,48 @@ use axum::{
     Router,
 };
 use axum_extra::extract::cookie::Key;
-use common::cratesio_prefetch_msg::CratesioPrefetchMsg;
 use db::{ConString, Database, DbProvider, PgConString, SqliteConString};
 use index::{
-    cratesio_prefetch_api::{self, background_update_thread, cratesio_prefetch_thread},
-    kellnr_prefetch_api,
+    cratesio_prefetch_api::{
+        self, background_update_thread, cratesio_prefetch_thread, metadata_refresh_thread,
+        METADATA_QUEUE_SIZE,
+    },
+    kellnr_prefetch_api, unified_prefetch_api,
+    upstream_api::UpstreamApi,
 };
 use once_cell::sync::Lazy;
-use registry::{cratesio_api, kellnr_api};
-use settings::{LogFormat, Settings};
+use registry::{
+    cache_eviction::enforce_disk_quota, cratesio_api, kellnr_api, scrub::scrub_thread, unified_api,
+};
+use reqwest::Client;
+use search::search_index::SearchIndex;
+use settings::{proxy::CRATESIO_UPSTREAM, LogFormat, Settings};
 use std::{
     convert::TryFrom,
     net::SocketAddr,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 use storage::{
     cratesio_crate_storage::CratesIoCrateStorage, kellnr_crate_storage::KellnrCrateStorage,
+    scrub::Scrubber,
 };
 use tokio::{
     fs::create_dir_all,
     net::TcpListener,
     runtime::{Builder, Runtime},
+    sync::mpsc,
 };
 use tower_http::services::{ServeDir, ServeFile};
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::fmt::format;
-use web_ui::{session, ui, user};
+use web_ui::{
+    proxy_cache, proxy_mode, proxy_policy, proxy_queue, scrub, search_index, security_events,
+    session, ui, user,
+};
+
+mod seed;
 
 #[tokio::main]
 async fn main() {
@@ -46,6 +64,11 @@ async fn main() {
     // Configure tracing subscriber
     init_tracing(&settings);
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "seed") {
+        std::process::exit(seed::run(&settings, &args[1..]).await);
+    }
+
     info!("Starting kellnr");
 
     // Initialize kellnr crate storage
@@ -57,22 +80,51 @@ async fn main() {
     let db = Database::new(&con_string)
         .await
         .expect("Failed to create database");
+    let search_index = init_search_index(&settings);
+    let db = match &search_index {
+        Some(index) => db.with_search_index(index.clone()),
+        None => db,
+    };
+    let proxy_mode = Arc::new(ProxyMode::new(settings.proxy.offline));
+    let http_client =
+        upstream_client(&settings.proxy).expect("Failed to create upstream HTTP client");
+    let upstreams =
+        Arc::new(init_upstream_proxies(&settings, &db, &proxy_mode, &http_client).await);
     let db = Arc::new(db) as Arc<dyn DbProvider>;
+    build_empty_search_index(search_index, db.clone());
 
     // Crates.io Proxy
     let cratesio_storage: Arc<CratesIoCrateStorage> = init_cratesio_proxy(&settings).await.into();
-    let (cratesio_prefetch_sender, cratesio_prefetch_receiver) =
-        flume::unbounded::<CratesioPrefetchMsg>();
-    let cratesio_prefetch_sender = Arc::new(cratesio_prefetch_sender);
-    let cratesio_prefetch_receiver = Arc::new(cratesio_prefetch_receiver);
+    let cratesio_prefetch_queue = new_prefetch_queue(&settings);
+
+    let cratesio_api = Arc::new(UpstreamApi::new(
+        http_client.clone(),
+        &settings.proxy.api_url,
+        Duration::from_millis(settings.proxy.api_request_interval_ms),
+    ));
 
-    init_cratesio_prefetch_thread(
+    init_prefetch_threads(
         get_connect_string(&settings),
-        cratesio_prefetch_sender.clone(),
-        cratesio_prefetch_receiver,
+        CRATESIO_UPSTREAM,
+        &settings.proxy.index_url,
         settings.proxy.num_threads,
+        http_client.clone(),
+        cratesio_prefetch_queue.clone(),
+        proxy_mode.clone(),
+        Some(cratesio_api),
     )
     .await;
+    init_proxy_cache_eviction(&settings, db.clone(), cratesio_storage.clone());
+
+    // Storage integrity scrub
+    let scrubber = Arc::new(Scrubber::new());
+    init_scrub_thread(
+        &settings,
+        db.clone(),
+        crate_storage.clone(),
+        cratesio_storage.clone(),
+        scrubber.clone(),
+    );
 
     // Docs hosting
     init_docs_hosting(&settings, &con_string).await;
@@ -86,7 +138,12 @@ async fn main() {
         settings,
         crate_storage,
         cratesio_storage,
-        cratesio_prefetch_sender,
+        cratesio_prefetch_queue,
+        scrubber,
+        upstreams,
+        proxy_mode,
+        proxy_policies: Arc::new(ProxyPolicies::new()),
+        http_client,
     };
 
     let user = Router::new()
@@ -160,6 +217,41 @@ async fn main() {
             auth::auth_req_token::cargo_auth_when_required,
         ));
 
+    let unified_api = Router::new()
+        .route("/:package/:version/download", get(unified_api::download))
+        .route("/config.json", get(unified_prefetch_api::config_unified))
+        .route("/:a/:b/:name", get(unified_prefetch_api::prefetch_unified))
+        .route(
+            "/:a/:name",
+            get(unified_prefetch_api::prefetch_len2_unified),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::auth_req_token::cargo_auth_when_required,
+        ));
+
+    let upstream_api = Router::new()
+        .route(
+            "/:upstream/:package/:version/download",
+            get(cratesio_api::download_upstream),
+        )
+        .route(
+            "/:upstream/config.json",
+            get(cratesio_prefetch_api::config_upstream),
+        )
+        .route(
+            "/:upstream/:a/:b/:name",
+            get(cratesio_prefetch_api::prefetch_upstream),
+        )
+        .route(
+            "/:upstream/:a/:name",
+            get(cratesio_prefetch_api::prefetch_len2_upstream),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::auth_req_token::cargo_auth_when_required,
+        ));
+
     let ui = Router::new()
         .route("/version", get(ui::kellnr_version))
         .route("/crates", get(ui::crates))
@@ -174,6 +266,32 @@ async fn main() {
             session::session_auth_when_required,
         ));
 
+    let admin = Router::new()
+        .route("/scrub", get(scrub::status))
+        .route("/scrub", post(scrub::start))
+        .route("/proxy/rules", get(proxy_policy::list_rules))
+        .route("/proxy/rules", post(proxy_policy::add_rule))
+        .route("/proxy/rules/:id", put(proxy_policy::update_rule))
+        .route("/proxy/rules/:id", delete(proxy_policy::delete_rule))
+        .route("/proxy/mode", get(proxy_mode::status))
+        .route("/proxy/mode", put(proxy_mode::set))
+        .route("/proxy/queue", get(proxy_queue::status))
+        .route("/proxy/cache/:upstream", get(proxy_cache::list))
+        .route("/proxy/cache/:upstream/warm", post(proxy_cache::warm))
+        .route("/proxy/cache/:upstream/:name", get(proxy_cache::inspect))
+        .route("/proxy/cache/:upstream/:name", delete(proxy_cache::purge))
+        .route(
+            "/proxy/cache/:upstream/:name/versions/:version",
+            delete(proxy_cache::purge_version),
+        )
+        .route(
+            "/proxy/cache/:upstream/:name/refresh",
+            post(proxy_cache::refresh),
+        )
+        .route("/proxy/cache/:upstream/:name/pin", put(proxy_cache::pin))
+        .route("/search_index/rebuild", post(search_index::rebuild))
+        .route("/security_events", get(security_events::list));
+
     let app = Router::new()
         .route("/me", get(kellnr_api::me))
         .nest("/api/v1/ui", ui)
@@ -181,6 +299,9 @@ async fn main() {
         .nest("/api/v1/docs", docs)
         .nest("/api/v1/crates", kellnr_api)
         .nest("/api/v1/cratesio", cratesio_api)
+        .nest("/api/v1/unified", unified_api)
+        .nest("/api/v1/proxy", upstream_api)
+        .nest("/api/v1/admin", admin)
         .nest_service("/docs", docs_service)
         .fallback(static_files_service)
         .with_state(state)
@@ -192,33 +313,187 @@ async fn main() {
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn init_cratesio_prefetch_thread(
+/// Sets up the registries that are proxied next to crates.io.
+async fn init_upstream_proxies(
+    settings: &Settings,
+    db: &Database,
+    proxy_mode: &Arc<ProxyMode>,
+    http_client: &Client,
+) -> Upstreams {
+    let mut upstreams = Upstreams::new();
+    if !settings.proxy.enabled {
+        return upstreams;
+    }
+
+    for upstream in &settings.proxy.upstreams {
+        if !upstream.has_valid_name() || upstreams.contains_key(&upstream.name) {
+            panic!(
+                "Invalid or duplicate proxy upstream name: {}",
+                upstream.name
+            );
+        }
+
+        let storage: Arc<CratesIoCrateStorage> =
+            CratesIoCrateStorage::for_upstream(settings, &upstream.name)
+                .await
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to create crate storage for {}: {}",
+                        upstream.name, e
+                    )
+                })
+                .into();
+        let upstream_db = Arc::new(db.with_upstream(&upstream.name)) as Arc<dyn DbProvider>;
+        let queue = new_prefetch_queue(settings);
+
+        init_prefetch_threads(
+            get_connect_string(settings),
+            &upstream.name,
+            &upstream.index_url,
+            settings.proxy.num_threads,
+            http_client.clone(),
+            queue.clone(),
+            proxy_mode.clone(),
+            None,
+        )
+        .await;
+        init_proxy_cache_eviction(settings, upstream_db.clone(), storage.clone());
+
+        upstreams.insert(
+            upstream.name.clone(),
+            UpstreamProxy {
+                settings: upstream.clone(),
+                db: upstream_db,
+                storage,
+                prefetch_queue: queue,
+            },
+        );
+    }
+    upstreams
+}
+
+fn new_prefetch_queue(settings: &Settings) -> Arc<PrefetchQueue> {
+    let queue = PrefetchQueue::new(settings.proxy.update_queue_size).with_ttl(
+        Duration::from_secs(settings.proxy.index_ttl_secs),
+        Duration::from_secs(settings.proxy.not_found_ttl_secs),
+    );
+    Arc::new(queue)
+}
+
+/// `api` is the web API to fetch the crate metadata from, which only crates.io provides.
+#[allow(clippy::too_many_arguments)]
+async fn init_prefetch_threads(
     con_string: ConString,
-    sender: Arc<flume::Sender<CratesioPrefetchMsg>>,
-    recv: Arc<flume::Receiver<CratesioPrefetchMsg>>,
+    upstream: &str,
+    index_url: &str,
     num_threads: usize,
+    client: Client,
+    queue: Arc<PrefetchQueue>,
+    proxy_mode: Arc<ProxyMode>,
+    api: Option<Arc<UpstreamApi>>,
 ) {
-    // Threads that takes messages to update the crates.io index
-    let db = Arc::new(
-        Database::new(&con_string)
-            .await
-            .expect("Failed to create database connection for crates.io prefetch thread"),
-    );
+    // Threads that takes messages to update the index of the upstream
+    let db = Database::new(&con_string)
+        .await
+        .expect("Failed to create database connection for prefetch thread");
+    let db = Arc::new(db.with_upstream(upstream));
+
+    // Thread that fetches the metadata of new crates, as the rate limited upstream API
+    // would slow down the index updates.
+    let metadata_queue = api.clone().map(|api| {
+        let (sender, receiver) = mpsc::channel(METADATA_QUEUE_SIZE);
+        let con_string = con_string.clone();
+        let upstream = upstream.to_string();
+        let proxy_mode = proxy_mode.clone();
+        tokio::spawn(async move {
+            let db = Database::new(&con_string)
+                .await
+                .expect("Failed to create database connection for metadata thread");
+            metadata_refresh_thread(db.with_upstream(&upstream), receiver, proxy_mode, api).await;
+        });
+        sender
+    });
+
     for _ in 0..num_threads {
-        let recv2 = recv.clone();
+        let queue = queue.clone();
         let db2 = db.clone();
+        let index_url = index_url.to_string();
+        let proxy_mode = proxy_mode.clone();
+        let metadata_queue = metadata_queue.clone();
+        let client = client.clone();
         tokio::spawn(async move {
-            cratesio_prefetch_thread(db2, recv2).await;
+            cratesio_prefetch_thread(db2, client, queue, index_url, proxy_mode, metadata_queue)
+                .await;
         });
     }
 
-    // Thread that periodically checks if the crates.io index needs to be updated.
+    // Thread that periodically checks if the index of the upstream needs to be updated.
     // It sends an update message to the thread above which then updates the index.
+    let upstream = upstream.to_string();
     tokio::spawn(async move {
         let db = Database::new(&con_string)
             .await
-            .expect("Failed to create database connection for crates.io update thread");
-        background_update_thread(db, sender).await;
+            .expect("Failed to create database connection for update thread");
+        background_update_thread(db.with_upstream(&upstream), queue, proxy_mode, api).await;
+    });
+}
+
+/// Enforces the disk quota of a proxy cache on startup, e.g. if the quota was lowered.
+/// Afterwards, the quota is checked whenever a new crate is added to the cache.
+fn init_proxy_cache_eviction(
+    settings: &Settings,
+    db: Arc<dyn DbProvider>,
+    cratesio_storage: Arc<CratesIoCrateStorage>,
+) {
+    // Evicted crates cannot be downloaded again while offline.
+    if settings.proxy.max_cache_size == 0 || settings.proxy.offline {
+        return;
+    }
+
+    let max_bytes = settings.proxy.max_cache_size * 1024 * 1024;
+    tokio::spawn(async move {
+        enforce_disk_quota(&db, &cratesio_storage, max_bytes).await;
+    });
+}
+
+fn init_search_index(settings: &Settings) -> Option<Arc<SearchIndex>> {
+    if !settings.registry.full_text_search {
+        return None;
+    }
+
+    let index = SearchIndex::open(&settings.search_index_path())
+        .expect("Failed to open full-text search index");
+    Some(Arc::new(index))
+}
+
+/// Builds a new full-text search index, e.g. after the search was enabled.
+fn build_empty_search_index(search_index: Option<Arc<SearchIndex>>, db: Arc<dyn DbProvider>) {
+    if !search_index.is_some_and(|index| index.is_empty()) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        match db.rebuild_search_index().await {
+            Ok(crates) => info!("Built full-text search index with {} crates", crates),
+            Err(e) => error!("Failed to build full-text search index: {}", e),
+        }
+    });
+}
+
+fn init_scrub_thread(
+    settings: &Settings,
+    db: Arc<dyn DbProvider>,
+    crate_storage: Arc<KellnrCrateStorage>,
+    cratesio_storage: Arc<CratesIoCrateStorage>,
+    scrubber: Arc<Scrubber>,
+) {
+    if settings.storage.scrub_interval_hours == 0 {
+        return;
+    }
+
+    let interval = Duration::from_secs(settings.storage.scrub_interval_hours * 60 * 60);
+    tokio::spawn(async move {
+        scrub_thread(db, crate_storage, cratesio_storage, scrubber, interval).await;
     });
 }
 
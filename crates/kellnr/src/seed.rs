@@ -0,0 +1,271 @@
+use chrono::Utc;
+use common::original_name::OriginalName;
+use common::version::Version;
+use db::{Database, DbProvider};
+use index::seed::{index_files, seed_index};
+use settings::{proxy::CRATESIO_UPSTREAM, Settings};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use storage::cached_crate_storage::CachedCrateStorage;
+use storage::cratesio_crate_storage::CratesIoCrateStorage;
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+const USAGE: &str =
+    "Usage: kellnr seed --index <dir> [--crates <dir>] [--upstream <name>] [--batch-size <n>]
+
+Seeds the proxy cache of crates.io or a proxied upstream, e.g. for air-gapped installations.
+  --index <dir>       Local checkout of the index of the upstream
+  --crates <dir>      Directory with .crate files to cache, named <name>-<version>.crate
+  --upstream <name>   Proxied upstream to seed (default: crates-io)
+  --batch-size <n>    Number of crates inserted concurrently (default: 100)";
+
+#[derive(Debug, PartialEq, Eq)]
+struct SeedArgs {
+    index: PathBuf,
+    crates: Option<PathBuf>,
+    upstream: String,
+    batch_size: usize,
+}
+
+impl SeedArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut index = None;
+        let mut crates = None;
+        let mut upstream = CRATESIO_UPSTREAM.to_string();
+        let mut batch_size = 100;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--index" => index = Some(PathBuf::from(value()?)),
+                "--crates" => crates = Some(PathBuf::from(value()?)),
+                "--upstream" => upstream = value()?,
+                "--batch-size" => {
+                    batch_size = value()?
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or("Batch size must be a positive number")?
+                }
+                _ => return Err(format!("Unknown argument: {arg}")),
+            }
+        }
+
+        Ok(Self {
+            index: index.ok_or("Missing argument: --index")?,
+            crates,
+            upstream,
+            batch_size,
+        })
+    }
+}
+
+/// Runs the `seed` command. Returns the exit code of the process.
+pub async fn run(settings: &Settings, args: &[String]) -> i32 {
+    let args = match SeedArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    match seed(settings, &args).await {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("Failed to seed the proxy cache: {:#}", e);
+            1
+        }
+    }
+}
+
+async fn seed(settings: &Settings, args: &SeedArgs) -> anyhow::Result<()> {
+    // The storage has to be created first, as it creates the data directory of sqlite.
+    let storage = if args.upstream == CRATESIO_UPSTREAM {
+        CratesIoCrateStorage::new(settings).await?
+    } else if settings
+        .proxy
+        .upstreams
+        .iter()
+        .any(|u| u.name == args.upstream)
+    {
+        CratesIoCrateStorage::for_upstream(settings, &args.upstream).await?
+    } else {
+        anyhow::bail!("Proxy upstream {} is not configured", args.upstream);
+    };
+    let db = Database::new(&crate::get_connect_string(settings))
+        .await?
+        .with_upstream(&args.upstream);
+    let db = Arc::new(db) as Arc<dyn DbProvider>;
+
+    let stats = seed_index(&db, &args.index, args.batch_size).await?;
+    info!(
+        "Seeded {} crates with {} versions into {}, {} failed",
+        stats.crates, stats.versions, args.upstream, stats.failed
+    );
+
+    if let Some(crates) = &args.crates {
+        let added = seed_crate_files(&db, Arc::new(storage), crates, args.batch_size).await?;
+        info!(
+            "Added {} crate files to the cache of {}",
+            added, args.upstream
+        );
+    }
+    Ok(())
+}
+
+/// Adds the `.crate` files in `crates_dir` to the cache. Files of crate versions that are
+/// not in the seeded index or do not match its checksum are skipped.
+async fn seed_crate_files(
+    db: &Arc<dyn DbProvider>,
+    storage: Arc<CratesIoCrateStorage>,
+    crates_dir: &Path,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    let files: Vec<PathBuf> = index_files(crates_dir)?
+        .into_iter()
+        .filter(|f| f.extension().is_some_and(|e| e == "crate"))
+        .collect();
+
+    let mut added = 0;
+    for batch in files.chunks(batch_size) {
+        let mut inserts = JoinSet::new();
+        for path in batch.iter().cloned() {
+            let db = db.clone();
+            let storage = storage.clone();
+            inserts.spawn(async move {
+                let result = seed_crate_file(&db, &storage, &path).await;
+                if let Err(e) = &result {
+                    warn!("Skipping {}: {}", path.display(), e);
+                }
+                result.is_ok()
+            });
+        }
+
+        while let Some(result) = inserts.join_next().await {
+            if matches!(result, Ok(true)) {
+                added += 1;
+            }
+        }
+    }
+    Ok(added)
+}
+
+async fn seed_crate_file(
+    db: &Arc<dyn DbProvider>,
+    storage: &CratesIoCrateStorage,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let (name, version) = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(split_crate_file_name)
+        .ok_or_else(|| anyhow::anyhow!("File name is not <name>-<version>.crate"))?;
+    let normalized_name = name.to_normalized();
+
+    let Some(expected) = db
+        .get_cratesio_crate_cksum(&normalized_name, &version)
+        .await?
+    else {
+        anyhow::bail!("Crate version is not in the index");
+    };
+    if storage.exists(&name, &version).await? {
+        return Ok(());
+    }
+
+    let data = tokio::fs::read(path).await?;
+    let actual = CachedCrateStorage::cksum(&data);
+    if actual != expected {
+        anyhow::bail!("Checksum mismatch: expected {}, got {}", expected, actual);
+    }
+
+    storage.add_bin_package(&name, &version, &data).await?;
+    db.update_cratesio_last_access(&normalized_name, &version, &Utc::now())
+        .await?;
+    Ok(())
+}
+
+/// Splits the stem of a crate file into name and version. Crate names may contain `-`,
+/// so the name ends at the first `-` which is followed by a valid version.
+fn split_crate_file_name(stem: &str) -> Option<(OriginalName, Version)> {
+    stem.match_indices('-').find_map(|(i, _)| {
+        let version = Version::try_from(&stem[i + 1..]).ok()?;
+        let name = OriginalName::try_from(&stem[..i]).ok()?;
+        Some((name, version))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_seed_args() {
+        let parsed = SeedArgs::parse(&args(&[
+            "--index",
+            "/data/index",
+            "--crates",
+            "/data/crates",
+            "--upstream",
+            "my-registry",
+            "--batch-size",
+            "10",
+        ]));
+
+        assert_eq!(
+            Ok(SeedArgs {
+                index: PathBuf::from("/data/index"),
+                crates: Some(PathBuf::from("/data/crates")),
+                upstream: "my-registry".to_string(),
+                batch_size: 10,
+            }),
+            parsed
+        );
+    }
+
+    #[test]
+    fn parse_seed_args_defaults() {
+        let parsed = SeedArgs::parse(&args(&["--index", "/data/index"])).unwrap();
+
+        assert_eq!(None, parsed.crates);
+        assert_eq!(CRATESIO_UPSTREAM, parsed.upstream);
+        assert_eq!(100, parsed.batch_size);
+    }
+
+    #[test]
+    fn parse_seed_args_rejects_invalid_args() {
+        assert!(SeedArgs::parse(&args(&[])).is_err());
+        assert!(SeedArgs::parse(&args(&["--index"])).is_err());
+        assert!(SeedArgs::parse(&args(&["--index", "i", "--batch-size", "0"])).is_err());
+        assert!(SeedArgs::parse(&args(&["--index", "i", "--unknown"])).is_err());
+    }
+
+    #[test]
+    fn split_crate_file_names() {
+        let split = |stem| split_crate_file_name(stem).map(|(n, v)| (n.to_string(), v.to_string()));
+
+        assert_eq!(
+            Some(("serde".to_string(), "1.0.0".to_string())),
+            split("serde-1.0.0")
+        );
+        assert_eq!(
+            Some(("sdl2-sys".to_string(), "0.35.2".to_string())),
+            split("sdl2-sys-0.35.2")
+        );
+        assert_eq!(
+            Some(("foo".to_string(), "1.0.0-beta-1".to_string())),
+            split("foo-1.0.0-beta-1")
+        );
+        assert_eq!(None, split("serde"));
+    }
+}
@@ -0,0 +1,136 @@
+use db::DbProvider;
+use std::sync::Arc;
+use storage::cratesio_crate_storage::CratesIoCrateStorage;
+use tracing::{error, info};
+
+/// Number of crate versions loaded from the database per eviction round.
+const EVICTION_BATCH_SIZE: u64 = 100;
+
+/// Removes the least recently downloaded crates from the crates.io proxy cache until
+/// the used disk space is below `max_bytes`. Returns the number of evicted crate files.
+pub async fn enforce_disk_quota(
+    db: &Arc<dyn DbProvider>,
+    storage: &CratesIoCrateStorage,
+    max_bytes: u64,
+) -> usize {
+    let Some(_guard) = storage.try_lock_eviction() else {
+        return 0;
+    };
+
+    let mut evicted = 0;
+    while storage.usage() > max_bytes {
+        let lru = match db
+            .get_least_recently_used_cratesio_crates(EVICTION_BATCH_SIZE)
+            .await
+        {
+            Ok(lru) if lru.is_empty() => break,
+            Ok(lru) => lru,
+            Err(e) => {
+                error!("Unable to load least recently used crates: {}", e);
+                break;
+            }
+        };
+
+        for (name, version) in lru {
+            if storage.usage() <= max_bytes {
+                break;
+            }
+
+            match storage.exists(&name, &version).await {
+                Ok(true) => match storage.delete(&name, &version).await {
+                    Ok(()) => evicted += 1,
+                    Err(e) => {
+                        error!("Unable to evict cached crate {}-{}: {}", name, version, e);
+                        return evicted;
+                    }
+                },
+                Ok(false) => (),
+                Err(e) => {
+                    error!("Unable to check cached crate {}-{}: {}", name, version, e);
+                    return evicted;
+                }
+            }
+
+            // The crate is not cached anymore and is not considered again until it is
+            // downloaded the next time.
+            if let Err(e) = db
+                .clear_cratesio_last_access(&name.to_normalized(), &version)
+                .await
+            {
+                error!("Unable to reset last access of {}-{}: {}", name, version, e);
+                return evicted;
+            }
+        }
+    }
+
+    if evicted > 0 {
+        info!(
+            "Evicted {} crates from the crates.io proxy cache, {} bytes in use",
+            evicted,
+            storage.usage()
+        );
+    }
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::original_name::OriginalName;
+    use common::util::generate_rand_string;
+    use common::version::Version;
+    use db::mock::MockDb;
+    use mockall::predicate::eq;
+    use settings::Settings;
+
+    #[tokio::test]
+    async fn evicts_least_recently_used_crates_until_below_quota() {
+        let data_dir = "/tmp/".to_string() + &generate_rand_string(10);
+        let settings = Settings {
+            registry: settings::Registry {
+                data_dir: data_dir.clone(),
+                ..settings::Registry::default()
+            },
+            ..Settings::default()
+        };
+        let storage = CratesIoCrateStorage::new(&settings).await.unwrap();
+        for name in ["old", "new"] {
+            storage
+                .add_bin_package(
+                    &OriginalName::from_unchecked_str(name.to_string()),
+                    &Version::from_unchecked_str("1.0.0"),
+                    &[0x1, 0x2, 0x3],
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut db = MockDb::new();
+        db.expect_get_least_recently_used_cratesio_crates()
+            .with(eq(EVICTION_BATCH_SIZE))
+            .returning(|_| {
+                Ok(vec![
+                    (
+                        OriginalName::from_unchecked_str("old".to_string()),
+                        Version::from_unchecked_str("1.0.0"),
+                    ),
+                    (
+                        OriginalName::from_unchecked_str("new".to_string()),
+                        Version::from_unchecked_str("1.0.0"),
+                    ),
+                ])
+            });
+        db.expect_clear_cratesio_last_access()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let db: Arc<dyn DbProvider> = Arc::new(db);
+
+        let evicted = enforce_disk_quota(&db, &storage, 3).await;
+
+        assert_eq!(1, evicted);
+        assert_eq!(3, storage.usage());
+        assert!(!storage.exists("old", "1.0.0").await.unwrap());
+        assert!(storage.exists("new", "1.0.0").await.unwrap());
+        rm_rf::remove(data_dir).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::response::Response;
+use std::ops::Range;
+use storage::cached_crate_storage::{CachedCrateStorage, CrateBody};
+use tracing::error;
+
+const CRATE_CONTENT_TYPE: &str = "application/gzip";
+
+#[derive(Debug, PartialEq, Eq)]
+enum RangeRequest {
+    Full,
+    Partial(Range<u64>),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header of the form `bytes=start-end`, `bytes=start-` or `bytes=-suffix`.
+/// Invalid or multi-range headers are ignored and the whole file is served.
+fn parse_range(headers: &HeaderMap, size: u64) -> RangeRequest {
+    let Some(spec) = headers
+        .get(header::RANGE)
+        .and_then(|r| r.to_str().ok())
+        .and_then(|r| r.trim().strip_prefix("bytes="))
+    else {
+        return RangeRequest::Full;
+    };
+
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+
+    let Some((start, end)) = spec.split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(_) if size == 0 => RangeRequest::Unsatisfiable,
+            Ok(suffix) => RangeRequest::Partial(size.saturating_sub(suffix)..size),
+            Err(_) => RangeRequest::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) if start >= size => RangeRequest::Unsatisfiable,
+            Ok(start) => RangeRequest::Partial(start..size),
+            Err(_) => RangeRequest::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if end < start => RangeRequest::Full,
+            (Ok(start), Ok(_)) if start >= size => RangeRequest::Unsatisfiable,
+            (Ok(start), Ok(end)) => RangeRequest::Partial(start..end.saturating_add(1).min(size)),
+            _ => RangeRequest::Full,
+        },
+    }
+}
+
+fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.split(',').any(|t| t.trim() == etag || t.trim() == "*"))
+        .unwrap_or(false)
+}
+
+/// Only full `GET` requests or the first chunk of a ranged download count as a download.
+/// `HEAD` requests and resumed downloads do not increase the download counter.
+pub fn is_download(method: &Method, headers: &HeaderMap) -> bool {
+    *method == Method::GET
+        && headers
+            .get(header::RANGE)
+            .and_then(|r| r.to_str().ok())
+            .map(|r| r.trim().starts_with("bytes=0-"))
+            .unwrap_or(true)
+}
+
+/// Builds the response for a crate download. Supports `HEAD` requests, single byte ranges
+/// and conditional requests with `If-None-Match` if the checksum of the crate is known.
+pub async fn crate_response(
+    storage: &CachedCrateStorage,
+    name: &str,
+    version: &str,
+    cksum: Option<String>,
+    method: &Method,
+    headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
+    let size = match storage.size(name, version).await {
+        Ok(Some(size)) => size,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to get size of crate {}-{}: {}", name, version, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, CRATE_CONTENT_TYPE)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    if let Some(etag) = cksum.map(|c| format!("\"{}\"", c)) {
+        let is_not_modified = not_modified(headers, &etag);
+        builder = builder.header(header::ETAG, etag);
+        if is_not_modified {
+            return build(builder.status(StatusCode::NOT_MODIFIED), Body::empty());
+        }
+    }
+
+    let range = match parse_range(headers, size) {
+        RangeRequest::Full => None,
+        RangeRequest::Partial(range) => Some(range),
+        RangeRequest::Unsatisfiable => {
+            return build(
+                builder
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", size)),
+                Body::empty(),
+            );
+        }
+    };
+
+    builder = match &range {
+        Some(range) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, size),
+            )
+            .header(header::CONTENT_LENGTH, range.end - range.start),
+        None => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, size),
+    };
+
+    if *method == Method::HEAD {
+        return build(builder, Body::empty());
+    }
+
+    let body = match storage.read(name, version, range).await {
+        Ok(Some(CrateBody::Bytes(data))) => Body::from(data),
+        Ok(Some(CrateBody::Stream(stream))) => Body::from_stream(stream),
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to read crate {}-{}: {}", name, version, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    build(builder, body)
+}
+
+fn build(builder: axum::http::response::Builder, body: Body) -> Result<Response, StatusCode> {
+    builder.body(body).map_err(|e| {
+        error!("Failed to build crate download response: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range_header(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn parse_range_without_header_is_full() {
+        assert_eq!(RangeRequest::Full, parse_range(&HeaderMap::new(), 100));
+    }
+
+    #[test]
+    fn parse_range_start_end() {
+        assert_eq!(
+            RangeRequest::Partial(10..20),
+            parse_range(&range_header("bytes=10-19"), 100)
+        );
+        assert_eq!(
+            RangeRequest::Partial(90..100),
+            parse_range(&range_header("bytes=90-200"), 100)
+        );
+    }
+
+    #[test]
+    fn parse_range_end_at_max_is_clamped() {
+        assert_eq!(
+            RangeRequest::Partial(0..100),
+            parse_range(&range_header(&format!("bytes=0-{}", u64::MAX)), 100)
+        );
+    }
+
+    #[test]
+    fn parse_range_open_end() {
+        assert_eq!(
+            RangeRequest::Partial(40..100),
+            parse_range(&range_header("bytes=40-"), 100)
+        );
+    }
+
+    #[test]
+    fn parse_range_suffix() {
+        assert_eq!(
+            RangeRequest::Partial(70..100),
+            parse_range(&range_header("bytes=-30"), 100)
+        );
+        assert_eq!(
+            RangeRequest::Partial(0..100),
+            parse_range(&range_header("bytes=-300"), 100)
+        );
+    }
+
+    #[test]
+    fn parse_range_unsatisfiable() {
+        assert_eq!(
+            RangeRequest::Unsatisfiable,
+            parse_range(&range_header("bytes=100-"), 100)
+        );
+        assert_eq!(
+            RangeRequest::Unsatisfiable,
+            parse_range(&range_header("bytes=-0"), 100)
+        );
+    }
+
+    #[test]
+    fn parse_range_invalid_is_full() {
+        assert_eq!(
+            RangeRequest::Full,
+            parse_range(&range_header("bytes=20-10"), 100)
+        );
+        assert_eq!(
+            RangeRequest::Full,
+            parse_range(&range_header("bytes=0-1,5-6"), 100)
+        );
+        assert_eq!(
+            RangeRequest::Full,
+            parse_range(&range_header("items=0-1"), 100)
+        );
+    }
+
+    #[test]
+    fn is_download_only_for_first_chunk() {
+        assert!(is_download(&Method::GET, &HeaderMap::new()));
+        assert!(is_download(&Method::GET, &range_header("bytes=0-99")));
+        assert!(!is_download(&Method::GET, &range_header("bytes=100-")));
+        assert!(!is_download(&Method::HEAD, &HeaderMap::new()));
+    }
+}
@@ -0,0 +1,334 @@
+use error::error::{ApiError, ApiResult};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+
+// Size of a tar header. It counts toward the unpacked size, such that archives with
+// many empty entries are limited as well.
+const HEADER_BYTES: u64 = 512;
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: ManifestPackage,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    name: String,
+    version: String,
+}
+
+/// Checks that the `.crate` file of a publish is a gzipped tarball of the crate with the
+/// published name and version. All entries have to be inside the `<name>-<version>/` directory,
+/// links must not point outside of it and the unpacked size is limited to `max_unpacked_bytes`.
+pub(crate) fn check_crate_file(
+    cratedata: &[u8],
+    name: &str,
+    vers: &str,
+    max_unpacked_bytes: u64,
+) -> ApiResult<()> {
+    let root = PathBuf::from(format!("{}-{}", name, vers));
+    let mut archive = Archive::new(GzDecoder::new(cratedata));
+    let mut unpacked_bytes: u64 = 0;
+    let mut manifest = None;
+
+    for entry in archive.entries().map_err(invalid_crate_file)? {
+        let mut entry = entry.map_err(invalid_crate_file)?;
+        let path = entry.path().map_err(invalid_crate_file)?.into_owned();
+        let Some(relative) = relative_path(&root, &path) else {
+            return Err(ApiError::from(&format!(
+                "Crate file contains path outside of {}/: {}",
+                root.display(),
+                path.display()
+            )));
+        };
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+            // Hard links refer to another entry of the archive.
+            EntryType::Link => check_link(&root, &path, entry.link_name(), |target| {
+                relative_path(&root, target).is_some()
+            })?,
+            EntryType::Symlink => check_link(&root, &path, entry.link_name(), |target| {
+                stays_inside(relative.parent().unwrap_or(Path::new("")), target)
+            })?,
+            entry_type => {
+                return Err(ApiError::from(&format!(
+                    "Crate file contains unsupported entry type {:?}: {}",
+                    entry_type,
+                    path.display()
+                )))
+            }
+        }
+
+        let entry_bytes = entry.header().size().unwrap_or(u64::MAX);
+        unpacked_bytes = unpacked_bytes.saturating_add(HEADER_BYTES.saturating_add(entry_bytes));
+        if unpacked_bytes > max_unpacked_bytes {
+            return Err(ApiError::from(&format!(
+                "Unpacked crate file exceeds the max. size of {} bytes",
+                max_unpacked_bytes
+            )));
+        }
+
+        if relative == Path::new("Cargo.toml") {
+            if manifest.is_some() {
+                return Err(ApiError::from(
+                    "Crate file contains Cargo.toml more than once",
+                ));
+            }
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(|e| ApiError::new("Invalid Cargo.toml in crate file.", &e))?;
+            manifest = Some(content);
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| {
+        ApiError::from(&format!(
+            "Crate file does not contain {}/Cargo.toml",
+            root.display()
+        ))
+    })?;
+    check_manifest(&manifest, name, vers)
+}
+
+fn invalid_crate_file(e: std::io::Error) -> ApiError {
+    ApiError::new("Invalid crate file.", &e)
+}
+
+/// Path of an archive entry relative to the root directory of the crate, if it is inside it.
+fn relative_path<'a>(root: &Path, path: &'a Path) -> Option<&'a Path> {
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+    path.strip_prefix(root).ok()
+}
+
+fn check_link(
+    root: &Path,
+    path: &Path,
+    link_name: std::io::Result<Option<std::borrow::Cow<Path>>>,
+    is_inside: impl FnOnce(&Path) -> bool,
+) -> ApiResult<()> {
+    match link_name.map_err(invalid_crate_file)? {
+        Some(target) if is_inside(&target) => Ok(()),
+        _ => Err(ApiError::from(&format!(
+            "Crate file contains link pointing outside of {}/: {}",
+            root.display(),
+            path.display()
+        ))),
+    }
+}
+
+/// Whether the relative `target` of a symlink in the directory `dir` stays inside the root
+/// directory of the crate. `dir` is relative to the root directory.
+fn stays_inside(dir: &Path, target: &Path) -> bool {
+    let mut depth = dir.components().count();
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+fn check_manifest(manifest: &str, name: &str, vers: &str) -> ApiResult<()> {
+    let manifest: Manifest = toml::from_str(manifest)
+        .map_err(|e| ApiError::new("Invalid Cargo.toml in crate file.", &e))?;
+
+    if manifest.package.name != name {
+        return Err(ApiError::from(&format!(
+            "Crate name in Cargo.toml does not match the published name: {} != {}",
+            manifest.package.name, name
+        )));
+    }
+    if manifest.package.version != vers {
+        return Err(ApiError::from(&format!(
+            "Crate version in Cargo.toml does not match the published version: {} != {}",
+            manifest.package.version, vers
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, Header};
+
+    const MAX_BYTES: u64 = 10_000;
+
+    fn manifest(name: &str, version: &str) -> String {
+        format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\nedition = \"2021\"\n")
+    }
+
+    fn raw_header(path: &str, entry_type: EntryType, size: usize) -> Header {
+        let mut header = Header::new_gnu();
+        // Written directly, as the builder rejects invalid paths.
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(size as u64);
+        header.set_mode(0o644);
+        header
+    }
+
+    fn crate_file(entries: &[(&str, &str)]) -> Vec<u8> {
+        crate_file_with(|builder| {
+            for (path, content) in entries {
+                let mut header = raw_header(path, EntryType::Regular, content.len());
+                header.set_cksum();
+                builder.append(&header, content.as_bytes()).unwrap();
+            }
+        })
+    }
+
+    fn crate_file_with(f: impl FnOnce(&mut Builder<GzEncoder<Vec<u8>>>)) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        f(&mut builder);
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn link(path: &str, entry_type: EntryType, target: &str) -> Vec<u8> {
+        let manifest = manifest("foo", "1.0.0");
+        crate_file_with(|builder| {
+            let mut header = raw_header("foo-1.0.0/Cargo.toml", EntryType::Regular, manifest.len());
+            header.set_cksum();
+            builder.append(&header, manifest.as_bytes()).unwrap();
+
+            let mut header = raw_header(path, entry_type, 0);
+            header.set_link_name(target).unwrap();
+            header.set_cksum();
+            builder.append(&header, std::io::empty()).unwrap();
+        })
+    }
+
+    fn check(cratedata: &[u8]) -> ApiResult<()> {
+        check_crate_file(cratedata, "foo", "1.0.0", MAX_BYTES)
+    }
+
+    fn error(result: ApiResult<()>) -> String {
+        result.unwrap_err().errors[0].detail.clone()
+    }
+
+    #[test]
+    fn valid_crate_file() {
+        let cratedata = crate_file(&[
+            ("foo-1.0.0/Cargo.toml", &manifest("foo", "1.0.0")),
+            ("foo-1.0.0/src/lib.rs", "pub fn foo() {}"),
+        ]);
+
+        assert!(check(&cratedata).is_ok());
+    }
+
+    #[test]
+    fn valid_published_test_crate() {
+        let pub_data = std::fs::read("../test_data/pub_data.bin").unwrap();
+        let metadata_end = 4 + u32::from_le_bytes(pub_data[0..4].try_into().unwrap()) as usize;
+        let cratedata = &pub_data[metadata_end + 4..];
+
+        let result = check_crate_file(cratedata, "test_lib", "0.2.0", MAX_BYTES);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn manifest_must_match_metadata() {
+        let other_name = crate_file(&[("foo-1.0.0/Cargo.toml", &manifest("bar", "1.0.0"))]);
+        let other_version = crate_file(&[("foo-1.0.0/Cargo.toml", &manifest("foo", "2.0.0"))]);
+        let missing = crate_file(&[("foo-1.0.0/src/lib.rs", "")]);
+        let invalid = crate_file(&[("foo-1.0.0/Cargo.toml", "[package")]);
+
+        assert!(error(check(&other_name)).contains("name in Cargo.toml does not match"));
+        assert!(error(check(&other_version)).contains("version in Cargo.toml does not match"));
+        assert!(error(check(&missing)).contains("does not contain foo-1.0.0/Cargo.toml"));
+        assert!(error(check(&invalid)).contains("Invalid Cargo.toml"));
+    }
+
+    #[test]
+    fn paths_must_be_inside_crate_directory() {
+        let manifest = manifest("foo", "1.0.0");
+        for path in [
+            "bar-1.0.0/src/lib.rs",
+            "/etc/passwd",
+            "foo-1.0.0/../../etc/passwd",
+            "Cargo.toml",
+        ] {
+            let cratedata = crate_file(&[("foo-1.0.0/Cargo.toml", &manifest), (path, "")]);
+
+            assert!(
+                error(check(&cratedata)).contains("path outside of foo-1.0.0/"),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn links_must_stay_inside_crate_directory() {
+        assert!(check(&link(
+            "foo-1.0.0/README.md",
+            EntryType::Symlink,
+            "docs/README.md"
+        ))
+        .is_ok());
+        assert!(check(&link("foo-1.0.0/src/a.rs", EntryType::Symlink, "../b.rs")).is_ok());
+        assert!(check(&link(
+            "foo-1.0.0/a",
+            EntryType::Link,
+            "foo-1.0.0/Cargo.toml"
+        ))
+        .is_ok());
+
+        for (entry_type, target) in [
+            (EntryType::Symlink, "../../etc/passwd"),
+            (EntryType::Symlink, "/etc/passwd"),
+            (EntryType::Link, "bar-1.0.0/Cargo.toml"),
+        ] {
+            let cratedata = link("foo-1.0.0/src/a.rs", entry_type, target);
+
+            assert!(
+                error(check(&cratedata)).contains("link pointing outside of foo-1.0.0/"),
+                "{target}"
+            );
+        }
+    }
+
+    #[test]
+    fn unpacked_size_is_limited() {
+        let large = "a".repeat(MAX_BYTES as usize);
+        let cratedata = crate_file(&[
+            ("foo-1.0.0/Cargo.toml", &manifest("foo", "1.0.0")),
+            ("foo-1.0.0/large.txt", &large),
+        ]);
+
+        assert!(error(check(&cratedata)).contains("exceeds the max. size"));
+    }
+
+    #[test]
+    fn headers_count_toward_unpacked_size() {
+        let manifest = manifest("foo", "1.0.0");
+        let paths = (0..MAX_BYTES / HEADER_BYTES)
+            .map(|i| format!("foo-1.0.0/empty{i}"))
+            .collect::<Vec<_>>();
+        let mut entries = vec![("foo-1.0.0/Cargo.toml", manifest.as_str())];
+        entries.extend(paths.iter().map(|p| (p.as_str(), "")));
+        let cratedata = crate_file(&entries);
+
+        assert!(error(check(&cratedata)).contains("exceeds the max. size"));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert!(error(check(&[0x00, 0x11, 0x22, 0x33])).contains("Invalid crate file"));
+    }
+}
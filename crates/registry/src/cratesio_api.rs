@@ -1,50 +1,162 @@
-use appstate::{CrateIoStorageState, DbState, SettingsState};
+use appstate::{
+    CrateIoStorageState, DbState, HttpClientState, ProxyModeState, ProxyPolicies,
+    ProxyPoliciesState, SettingsState, UpstreamsState,
+};
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
 };
-use common::{original_name::OriginalName, version::Version};
-use error::error::{ApiError, ApiResult};
-use reqwest::Url;
-use tracing::{debug, error, trace};
+use chrono::Utc;
+use common::proxy_policy::PolicyAction;
+use common::search_result::{self, SearchResult};
+use common::single_flight::SingleFlight;
+use common::{normalized_name::NormalizedName, original_name::OriginalName, version::Version};
+use db::{DbProvider, SecurityEventKind};
+use error::error::ApiResult;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use settings::proxy::Proxy;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::cached_crate_storage::CachedCrateStorage;
+use storage::cratesio_crate_storage::CratesIoCrateStorage;
+use tracing::{debug, error, trace, warn};
 
+use crate::cache_eviction::enforce_disk_quota;
+use crate::crate_download::{crate_response, is_download};
 use crate::search_params::SearchParams;
 
-pub async fn search(params: SearchParams) -> ApiResult<String> {
-    let url = match Url::parse(&format!(
-        "https://crates.io/api/v1/crates?q={}&per_page={}",
-        params.q, params.per_page.0
-    )) {
-        Ok(url) => url,
-        Err(e) => {
-            return Err(ApiError::from(&e.to_string()));
-        }
-    };
+// The index entry of a crate is stored asynchronously after cargo fetched the index file.
+// A download right afterwards waits for it up to CKSUM_RETRIES * CKSUM_RETRY_DELAY.
+const CKSUM_RETRIES: u32 = 10;
+const CKSUM_RETRY_DELAY: Duration = Duration::from_millis(200);
 
-    trace!("url {}", url);
+// Running crate downloads by download URL, which is unique across all upstreams.
+static DOWNLOADS: SingleFlight<String, Result<(), StatusCode>> = SingleFlight::new();
 
-    let client = match reqwest::Client::builder().user_agent("kellnr").build() {
-        Ok(client) => client,
-        Err(e) => {
-            return Err(ApiError::from(&e.to_string()));
+/// Searches crates.io. The cached crates are searched instead if crates.io is unavailable,
+/// or in addition if `proxy.local_search` is set.
+pub async fn search(
+    State(settings): SettingsState,
+    State(proxy_mode): ProxyModeState,
+    State(db): DbState,
+    State(client): HttpClientState,
+    params: SearchParams,
+) -> ApiResult<Json<SearchResult>> {
+    let per_page = params.per_page.0;
+    let upstream = if proxy_mode.is_offline() {
+        None
+    } else {
+        match search_upstream(&client, &settings.proxy, &params).await {
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("Search on crates.io failed, searching cached crates: {}", e);
+                None
+            }
         }
     };
 
-    let response = match client.get(url).send().await {
-        Ok(response) => response,
-        Err(e) => {
-            return Err(ApiError::from(&e.to_string()));
+    let result = match upstream {
+        // The cached crates are only merged into the first page, as their position in the
+        // upstream results is unknown.
+        Some(upstream) if !settings.proxy.local_search || params.page > 1 => upstream,
+        Some(upstream) => {
+            let local = db
+                .search_cratesio_crates(&params.q, 0, per_page as u64)
+                .await?;
+            merge_search_results(upstream, local, per_page)
+        }
+        None => {
+            let offset = (params.page - 1).saturating_mul(per_page);
+            db.search_cratesio_crates(&params.q, offset as u64, per_page as u64)
+                .await?
         }
     };
 
-    let body = match response.text().await {
-        Ok(body) => body,
-        Err(e) => {
-            return Err(ApiError::from(&e.to_string()));
+    Ok(Json(result))
+}
+
+async fn search_upstream(
+    client: &Client,
+    proxy: &Proxy,
+    params: &SearchParams,
+) -> Result<SearchResult, String> {
+    // crates.io returns `null` for crates without description.
+    #[derive(Deserialize)]
+    struct UpstreamCrate {
+        name: String,
+        max_version: String,
+        description: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct UpstreamSearchResult {
+        crates: Vec<UpstreamCrate>,
+        meta: search_result::Meta,
+    }
+
+    let url = Url::parse_with_params(
+        &proxy.api_endpoint("/api/v1/crates"),
+        &[
+            ("q", params.q.to_string()),
+            ("per_page", params.per_page.0.to_string()),
+            ("page", params.page.to_string()),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    trace!("url {}", url);
+
+    let result = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json::<UpstreamSearchResult>()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(SearchResult {
+        crates: result
+            .crates
+            .into_iter()
+            .map(|c| search_result::Crate {
+                name: c.name,
+                max_version: c.max_version,
+                description: c
+                    .description
+                    .unwrap_or_else(|| "No description set".to_string()),
+            })
+            .collect(),
+        meta: result.meta,
+    })
+}
+
+/// Appends the cached crates that are missing in the upstream results, as far as the page
+/// has room. The cached crates are a subset of crates.io, such that the upstream total
+/// already counts them.
+fn merge_search_results(
+    upstream: SearchResult,
+    local: SearchResult,
+    per_page: usize,
+) -> SearchResult {
+    let mut crates = upstream.crates;
+    for krate in local.crates {
+        if !crates
+            .iter()
+            .any(|c| c.name.eq_ignore_ascii_case(&krate.name))
+        {
+            crates.push(krate);
         }
-    };
+    }
+    crates.truncate(per_page);
 
-    Ok(body)
+    SearchResult {
+        crates,
+        meta: upstream.meta,
+    }
 }
 
 pub async fn download(
@@ -52,80 +164,358 @@ pub async fn download(
     State(settings): SettingsState,
     State(crate_storage): CrateIoStorageState,
     State(db): DbState,
-) -> Result<Vec<u8>, StatusCode> {
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     // Return NotFound if the feature is disabled
     match settings.proxy.enabled {
         true => (),
         _ => return Err(StatusCode::NOT_FOUND),
     };
 
-    let file_path = crate_storage.crate_path(&package.to_string(), &version.to_string());
+    let target = settings.proxy.crate_download_url(&package, &version);
+    proxy_download(
+        &client,
+        package,
+        version,
+        target,
+        &crate_storage,
+        &db,
+        &policies,
+        &settings.proxy,
+        proxy_mode.is_offline(),
+        method,
+        headers,
+    )
+    .await
+}
+
+pub async fn download_upstream(
+    Path((upstream, package, version)): Path<(String, OriginalName, Version)>,
+    State(settings): SettingsState,
+    State(upstreams): UpstreamsState,
+    State(proxy_mode): ProxyModeState,
+    State(policies): ProxyPoliciesState,
+    State(client): HttpClientState,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let Some(upstream) = upstreams.get(&upstream) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
 
-    trace!(
-        "Downloading crate: {} ({}) from path {}",
+    let target = upstream.settings.crate_download_url(&package, &version);
+    proxy_download(
+        &client,
         package,
         version,
-        file_path.display()
-    );
+        target,
+        &upstream.storage,
+        &upstream.db,
+        &policies,
+        &settings.proxy,
+        proxy_mode.is_offline(),
+        method,
+        headers,
+    )
+    .await
+}
 
-    if !std::path::Path::exists(&file_path) {
-        debug!("Crate not found on disk, downloading from crates.io");
-        let target = format!(
-            "https://rsproxy.cn/api/v1/crates/{}/{}/download",
+/// Serves a crate from the proxy cache. The crate is downloaded from the upstream
+/// registry first, if it is not cached yet.
+#[allow(clippy::too_many_arguments)]
+async fn proxy_download(
+    client: &Client,
+    package: OriginalName,
+    version: Version,
+    target: String,
+    crate_storage: &Arc<CratesIoCrateStorage>,
+    db: &Arc<dyn DbProvider>,
+    policies: &ProxyPolicies,
+    proxy_settings: &Proxy,
+    offline: bool,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    trace!("Downloading crate: {} ({})", package, version);
+
+    check_policy(db, policies, proxy_settings, &package, &version).await?;
+    let max_cache_size = proxy_settings.max_cache_size;
+
+    let exists = crate_storage
+        .exists(&package, &version)
+        .await
+        .map_err(|e| {
+            error!("Failed to check if crate exists in storage: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if !exists && offline {
+        warn!(
+            "Crate {} ({}) is not cached and the proxy is offline",
             package, version
         );
-        match reqwest::get(target).await {
-            Ok(response) => match response.status() == 200 {
-                true => match response.bytes().await {
-                    Ok(crate_data) => {
-                        // Check again after the download, as another thread maybe
-                        // added the crate already to disk and we can skip the step.
-                        if !std::path::Path::exists(&file_path) {
-                            if let Err(e) = crate_storage
-                                .add_bin_package(&package, &version, &crate_data)
-                                .await
-                            {
-                                error!("Failed to save crate to disk: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to get crate data from response: {}", e);
-                        return Err(StatusCode::NOT_FOUND);
-                    }
-                },
-                // crates.io returned a 404 or another error -> Return NotFound
-                false => return Err(StatusCode::NOT_FOUND),
+        // Cargo shows the body of unsuccessful responses to the user.
+        return Ok((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "The Kellnr proxy is in offline mode and crate {} ({}) is not cached",
+                package, version
+            ),
+        )
+            .into_response());
+    }
+
+    if !exists {
+        debug!("Crate not found in storage, downloading from upstream");
+        download_to_cache(
+            client,
+            &target,
+            &package,
+            &version,
+            crate_storage,
+            db,
+            max_cache_size,
+        )
+        .await?;
+    } else {
+        trace!("Crate found in cache, skipping download");
+    }
+
+    let normalized_name = package.to_normalized();
+    let cksum = db
+        .get_cratesio_crate_cksum(&normalized_name, &version)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to get checksum of cached crate: {}", e);
+            None
+        });
+
+    let response =
+        crate_response(crate_storage, &package, &version, cksum, &method, &headers).await?;
+
+    if response.status().is_success() && is_download(&method, &headers) {
+        db.increase_cached_download_counter(&normalized_name, &version)
+            .await
+            .unwrap_or_else(|e| error!("Failed to increase download counter: {}", e));
+        db.update_cratesio_last_access(&normalized_name, &version, &Utc::now())
+            .await
+            .unwrap_or_else(|e| error!("Failed to update last access: {}", e));
+    }
+
+    Ok(response)
+}
+
+/// Adds a crate to the proxy cache ahead of its first download, e.g. to warm the cache
+/// before going offline. Returns `false` if the crate was cached already.
+#[allow(clippy::too_many_arguments)]
+pub async fn cache_crate(
+    client: &Client,
+    target: &str,
+    package: &OriginalName,
+    version: &Version,
+    crate_storage: &Arc<CratesIoCrateStorage>,
+    db: &Arc<dyn DbProvider>,
+    policies: &ProxyPolicies,
+    proxy_settings: &Proxy,
+) -> Result<bool, StatusCode> {
+    check_policy(db, policies, proxy_settings, package, version).await?;
+
+    let exists = crate_storage.exists(package, version).await.map_err(|e| {
+        error!("Failed to check if crate exists in storage: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if !exists {
+        download_to_cache(
+            client,
+            target,
+            package,
+            version,
+            crate_storage,
+            db,
+            proxy_settings.max_cache_size,
+        )
+        .await?;
+        // Crates without last access are neither listed as cached nor evicted.
+        db.update_cratesio_last_access(&package.to_normalized(), version, &Utc::now())
+            .await
+            .unwrap_or_else(|e| error!("Failed to update last access: {}", e));
+    }
+    Ok(!exists)
+}
+
+async fn download_to_cache(
+    client: &Client,
+    target: &str,
+    package: &OriginalName,
+    version: &Version,
+    crate_storage: &Arc<CratesIoCrateStorage>,
+    db: &Arc<dyn DbProvider>,
+    max_cache_size: u64,
+) -> Result<(), StatusCode> {
+    // Concurrent requests of the same crate share a single download.
+    DOWNLOADS
+        .run(target.to_string(), || {
+            fetch_crate(
+                client,
+                target,
+                package,
+                version,
+                crate_storage,
+                db,
+                max_cache_size,
+            )
+        })
+        .await
+}
+
+/// Downloads a crate from the upstream registry, verifies its checksum and adds it to the cache.
+async fn fetch_crate(
+    client: &Client,
+    target: &str,
+    package: &OriginalName,
+    version: &Version,
+    crate_storage: &Arc<CratesIoCrateStorage>,
+    db: &Arc<dyn DbProvider>,
+    max_cache_size: u64,
+) -> Result<(), StatusCode> {
+    let crate_data = match client.get(target).send().await {
+        Ok(response) => match response.status() == 200 {
+            true => match response.bytes().await {
+                Ok(crate_data) => crate_data,
+                Err(e) => {
+                    error!("Failed to get crate data from response: {}", e);
+                    return Err(StatusCode::NOT_FOUND);
+                }
             },
+            // crates.io returned a 404 or another error -> Return NotFound
+            false => return Err(StatusCode::NOT_FOUND),
+        },
+        Err(e) => {
+            error!("Failed to download crate from upstream: {}", e);
+            return Err(StatusCode::NOT_FOUND);
+        }
+    };
+
+    verify_cksum(db, package, version, &crate_data).await?;
+
+    match crate_storage
+        .add_bin_package(package, version, &crate_data)
+        .await
+    {
+        Ok(_) if max_cache_size > 0 => {
+            let db = db.clone();
+            let crate_storage = crate_storage.clone();
+            let max_bytes = max_cache_size * 1024 * 1024;
+            tokio::spawn(async move {
+                enforce_disk_quota(&db, &crate_storage, max_bytes).await;
+            });
+        }
+        Ok(_) => (),
+        Err(e) => error!("Failed to save crate to storage: {}", e),
+    }
+    Ok(())
+}
+
+/// Compares the checksum of a crate downloaded from the upstream with the checksum of the
+/// cached index entry. A mismatching crate is neither cached nor served, such that a
+/// compromised upstream cannot poison the cache.
+async fn verify_cksum(
+    db: &Arc<dyn DbProvider>,
+    package: &OriginalName,
+    version: &Version,
+    crate_data: &[u8],
+) -> Result<(), StatusCode> {
+    let normalized_name = package.to_normalized();
+    let Some(expected) = index_cksum(db, &normalized_name, version).await else {
+        error!(
+            "No index checksum found for crate {} ({}), refusing to cache it",
+            package, version
+        );
+        return Err(StatusCode::BAD_GATEWAY);
+    };
+
+    let actual = CachedCrateStorage::cksum(crate_data);
+    if actual == expected {
+        return Ok(());
+    }
+
+    error!(
+        "Checksum mismatch of crate {} ({}) from upstream: expected {}, got {}",
+        package, version, expected, actual
+    );
+    db.add_security_event(
+        SecurityEventKind::ChecksumMismatch,
+        &normalized_name,
+        version,
+        &format!("Expected checksum {}, got {}", expected, actual),
+        &Utc::now(),
+    )
+    .await
+    .unwrap_or_else(|e| error!("Failed to record security event: {}", e));
+
+    Err(StatusCode::BAD_GATEWAY)
+}
+
+async fn index_cksum(
+    db: &Arc<dyn DbProvider>,
+    name: &NormalizedName,
+    version: &Version,
+) -> Option<String> {
+    for _ in 0..CKSUM_RETRIES {
+        match db.get_cratesio_crate_cksum(name, version).await {
+            Ok(Some(cksum)) => return Some(cksum),
+            Ok(None) => tokio::time::sleep(CKSUM_RETRY_DELAY).await,
             Err(e) => {
-                error!("Failed to download crate from crates.io: {}", e);
-                return Err(StatusCode::NOT_FOUND);
+                error!("Failed to get checksum of cached crate: {}", e);
+                return None;
             }
         }
-    } else {
-        trace!("Crate found in cache, skipping download");
     }
+    None
+}
 
-    match crate_storage.get_file(file_path).await {
-        Some(file) => {
-            let normalized_name = package.to_normalized();
-            db.increase_cached_download_counter(&normalized_name, &version)
-                .await
-                .unwrap_or_else(|e| error!("Failed to increase download counter: {}", e));
-            Ok(file)
+/// Rejects the download if the crate version is denied or pending approval.
+/// Cached crates are checked as well, as the policy may have changed after caching.
+async fn check_policy(
+    db: &Arc<dyn DbProvider>,
+    policies: &ProxyPolicies,
+    proxy_settings: &Proxy,
+    package: &OriginalName,
+    version: &Version,
+) -> Result<(), StatusCode> {
+    let policy = policies
+        .get(db, proxy_settings.approval_required)
+        .await
+        .map_err(|e| {
+            error!("Failed to get proxy rules: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    match policy.action(&package.to_normalized(), version) {
+        PolicyAction::Allow => Ok(()),
+        action => {
+            warn!(
+                "Blocked download of crate {} ({}) by the proxy policy: {}",
+                package, version, action
+            );
+            Err(StatusCode::FORBIDDEN)
         }
-        None => Err(StatusCode::NOT_FOUND),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use appstate::AppStateData;
+    use appstate::{AppStateData, ProxyMode};
     use axum::body::Body;
     use axum::http::Request;
     use axum::routing::get;
     use axum::Router;
+    use common::proxy_policy::ProxyRule;
     use common::util::generate_rand_string;
     use db::mock::MockDb;
     use http_body_util::BodyExt;
@@ -153,6 +543,24 @@ mod tests {
         assert_eq!(r.status(), StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn download_from_unknown_upstream() {
+        let settings = get_settings();
+        let kellnr = TestKellnr::new(settings).await;
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/proxy/unknown/test/0.1.0/download")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::NOT_FOUND);
+    }
+
     #[tokio::test]
     async fn download_invalid_package_name() {
         let settings = get_settings();
@@ -227,6 +635,137 @@ mod tests {
         assert_eq!(12778, body.len());
     }
 
+    #[tokio::test]
+    async fn download_denied_version() {
+        let settings = get_settings();
+        let kellnr = TestKellnr::new(settings).await;
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/cratesio/adler/0.2.3/download")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn download_uncached_crate_while_offline() {
+        let mut settings = get_settings();
+        settings.proxy.offline = true;
+        let kellnr = TestKellnr::new(settings).await;
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/cratesio/adler/1.0.2/download")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn download_with_checksum_mismatch() {
+        let settings = get_settings();
+        let kellnr = TestKellnr::new(settings).await;
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/cratesio/adler32/1.2.0/download")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn search_cached_crates_while_offline() {
+        let mut settings = get_settings();
+        settings.proxy.offline = true;
+        let kellnr = TestKellnr::new(settings).await;
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/cratesio/?q=adler&per_page=10")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::OK);
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let result = serde_json::from_slice::<SearchResult>(&body).unwrap();
+        assert_eq!(1, result.meta.total);
+        assert_eq!("Cached crate at 0", result.crates[0].description);
+    }
+
+    #[tokio::test]
+    async fn search_cached_crates_while_offline_skips_previous_pages() {
+        let mut settings = get_settings();
+        settings.proxy.offline = true;
+        let kellnr = TestKellnr::new(settings).await;
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/cratesio/?q=adler&per_page=10&page=3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(r.status(), StatusCode::OK);
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let result = serde_json::from_slice::<SearchResult>(&body).unwrap();
+        assert_eq!("Cached crate at 20", result.crates[0].description);
+    }
+
+    #[test]
+    fn merge_search_results_keeps_upstream_total() {
+        let krate = |name: &str| search_result::Crate {
+            name: name.to_string(),
+            max_version: "1.0.0".to_string(),
+            description: String::new(),
+        };
+        let upstream = SearchResult {
+            crates: vec![krate("serde"), krate("serde_json")],
+            meta: search_result::Meta { total: 5 },
+        };
+        let local = SearchResult {
+            crates: vec![krate("Serde"), krate("serde_yaml_ng")],
+            meta: search_result::Meta { total: 2 },
+        };
+
+        let merged = merge_search_results(upstream, local, 3);
+
+        assert_eq!(5, merged.meta.total);
+        assert_eq!(
+            vec!["serde", "serde_json", "serde_yaml_ng"],
+            merged
+                .crates
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    const ADLER_CKSUM: &str = "f26201604c87b1e01bd3d98f8d5d9a8fcbb815e8cedb41ffccbeb4bf593a35fe";
+
     struct TestKellnr {
         path: PathBuf,
         client: Router,
@@ -268,8 +807,38 @@ mod tests {
         let mut db = MockDb::new();
         db.expect_increase_cached_download_counter()
             .returning(|_, _| Ok(()));
+        db.expect_get_cratesio_crate_cksum()
+            .returning(|name, _| match name.as_str() {
+                "adler" => Ok(Some(ADLER_CKSUM.to_string())),
+                _ => Ok(Some("invalid".to_string())),
+            });
+        db.expect_add_security_event()
+            .returning(|_, _, _, _, _| Ok(()));
+        db.expect_update_cratesio_last_access()
+            .returning(|_, _, _| Ok(()));
+        db.expect_search_cratesio_crates()
+            .returning(|contains, offset, _| {
+                Ok(SearchResult {
+                    crates: vec![search_result::Crate {
+                        name: contains.to_string(),
+                        max_version: "1.0.0".to_string(),
+                        description: format!("Cached crate at {offset}"),
+                    }],
+                    meta: search_result::Meta { total: 1 },
+                })
+            });
+        db.expect_get_proxy_rules().returning(|| {
+            Ok(vec![ProxyRule {
+                id: 1,
+                pattern: "adler".to_string(),
+                version_req: "<1".to_string(),
+                action: PolicyAction::Deny,
+                created: String::new(),
+            }])
+        });
 
         let state = AppStateData {
+            proxy_mode: ProxyMode::new(settings.proxy.offline).into(),
             settings: settings.into(),
             cratesio_storage: cs.into(),
             db: std::sync::Arc::<MockDb>::new(db),
@@ -280,8 +849,14 @@ mod tests {
             .route("/", get(search))
             .route("/:package/:version/download", get(download));
 
+        let upstream_routes = Router::new().route(
+            "/:upstream/:package/:version/download",
+            get(download_upstream),
+        );
+
         Router::new()
             .nest("/api/v1/cratesio", routes)
+            .nest("/api/v1/proxy", upstream_routes)
             .with_state(state)
     }
 }
@@ -1,4 +1,8 @@
+use crate::crate_download::{crate_response, is_download};
+use crate::crate_file_check::check_crate_file;
+use crate::name_check::check_crate_name;
 use crate::owner;
+use crate::policy_check::check_policy;
 use crate::pub_data::PubData;
 use crate::pub_success::PubDataSuccess;
 use crate::search_params::SearchParams;
@@ -9,8 +13,8 @@ use appstate::DbState;
 use auth::token;
 use axum::extract::Path;
 use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::Redirect;
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::response::{Redirect, Response};
 use axum::Json;
 use chrono::Utc;
 use common::normalized_name::NormalizedName;
@@ -21,8 +25,11 @@ use common::version::Version;
 use db::DbProvider;
 use error::error::{ApiError, ApiResult};
 use std::convert::TryFrom;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::warn;
+use storage::cached_crate_storage::StagedCrate;
+use storage::kellnr_crate_storage::KellnrCrateStorage;
+use tracing::{error, warn};
 
 pub async fn check_ownership(
     crate_name: &NormalizedName,
@@ -97,9 +104,11 @@ pub async fn search(
     State(db): DbState,
     params: SearchParams,
 ) -> ApiResult<Json<search_result::SearchResult>> {
-    let crates = db
-        .search_in_crate_name(&params.q)
-        .await?
+    let per_page = params.per_page.0 as u64;
+    let offset = (params.page as u64 - 1).saturating_mul(per_page);
+    let page = db.search_crates(&params.q, offset, per_page).await?;
+    let crates = page
+        .crates
         .into_iter()
         .map(|c| search_result::Crate {
             name: c.original_name,
@@ -108,12 +117,11 @@ pub async fn search(
                 .description
                 .unwrap_or_else(|| "No description set".to_string()),
         })
-        .take(params.per_page.0)
         .collect::<Vec<Crate>>();
 
     Ok(Json(SearchResult {
         meta: search_result::Meta {
-            total: crates.len() as i32,
+            total: page.total as i32,
         },
         crates,
     }))
@@ -122,23 +130,33 @@ pub async fn search(
 pub async fn download(
     State(state): AppState,
     Path((package, version)): Path<(OriginalName, Version)>,
-) -> Result<Vec<u8>, StatusCode> {
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     let db = state.db;
     let cs = state.crate_storage;
+    let normalized_name = package.to_normalized();
 
-    let file_path = cs.crate_path(&package.to_string(), &version.to_string());
-
-    if let Err(e) = db
-        .increase_download_counter(&package.to_normalized(), &version)
+    let cksum = db
+        .get_crate_cksum(&normalized_name, &version)
         .await
-    {
-        warn!("Failed to increase download counter: {}", e);
-    }
+        .unwrap_or_else(|e| {
+            warn!("Failed to get checksum of crate: {}", e);
+            None
+        });
+
+    let response = crate_response(&cs, &package, &version, cksum, &method, &headers).await?;
 
-    match cs.get_file(file_path).await {
-        Some(file) => Ok(file),
-        None => Err(StatusCode::NOT_FOUND),
+    if response.status().is_success() && is_download(&method, &headers) {
+        if let Err(e) = db
+            .increase_download_counter(&normalized_name, &version)
+            .await
+        {
+            warn!("Failed to increase download counter: {}", e);
+        }
     }
+
+    Ok(response)
 }
 
 pub async fn publish(
@@ -151,6 +169,16 @@ pub async fn publish(
     let cs = state.crate_storage;
     let orig_name = OriginalName::try_from(&pub_data.metadata.name)?;
     let normalized_name = orig_name.to_normalized();
+    let version = Version::try_from(&pub_data.metadata.vers)?;
+
+    // Concurrent publishes of the same crate version are rejected,
+    // such that they do not race for the storage and the database.
+    let Some(_publish_guard) = cs.try_lock_publish(&normalized_name, &version) else {
+        return Err(ApiError::from(&format!(
+            "Crate with version is already being published: {}-{}",
+            &pub_data.metadata.name, &pub_data.metadata.vers
+        )));
+    };
 
     // Check if user from token is an owner of the crate.
     // If not, he is not allowed push a new version.
@@ -166,29 +194,119 @@ pub async fn publish(
         }
     }
 
-    // Set SHA256 from crate file
-    let version = Version::try_from(&pub_data.metadata.vers)?;
-    let cksum = cs
-        .add_bin_package(&orig_name, &version, &pub_data.cratedata)
+    let mut warnings = check_policy(&pub_data.metadata, &settings.policy)?;
+
+    // Unpacking the crate file is blocking, as it may be large.
+    let cratedata = pub_data.cratedata.clone();
+    let (name, vers) = (
+        pub_data.metadata.name.clone(),
+        pub_data.metadata.vers.clone(),
+    );
+    let max_unpacked_bytes = settings.registry.max_unpacked_crate_size * 1_000_000;
+    tokio::task::spawn_blocking(move || {
+        check_crate_file(&cratedata, &name, &vers, max_unpacked_bytes)
+    })
+    .await
+    .map_err(|e| ApiError::new("Failed to check crate file.", &e))??;
+
+    let name_warnings = check_crate_name(
+        &orig_name,
+        &token,
+        &settings,
+        &db,
+        &state.http_client,
+        &state.proxy_mode,
+    )
+    .await?;
+    warnings.extend(name_warnings);
+
+    // The crate file is only moved into place after the crate is added to the DB.
+    // Until then, it is not served and a failed publish can be retried.
+    let staged = cs
+        .stage_bin_package(&orig_name, &version, &pub_data.cratedata)
         .await?;
 
+    // Add crate to queue for doc extraction if there is no documentation value set already
+    let doc_queue_path = if settings.docs.enabled && pub_data.metadata.documentation.is_none() {
+        match cs.create_rand_doc_queue_path().await {
+            Ok(path) => Some(path),
+            Err(e) => {
+                clean_up_failed_publish(&cs, staged, None).await;
+                return Err(e.into());
+            }
+        }
+    } else {
+        None
+    };
+
+    // Add crate and doc queue entry to DB in a single transaction
     let created = Utc::now();
+    if let Err(e) = db
+        .publish_crate(
+            &pub_data.metadata,
+            &staged.cksum,
+            &created,
+            &token.user,
+            doc_queue_path.as_deref(),
+        )
+        .await
+    {
+        clean_up_failed_publish(&cs, staged, doc_queue_path).await;
+        return Err(e.into());
+    }
 
-    // Add crate to DB
-    db.add_crate(&pub_data.metadata, &cksum, &created, &token.user)
-        .await?;
+    if let Err(e) = cs.commit_staged(&staged).await {
+        roll_back_publish(&db, &normalized_name, &version, doc_queue_path.as_deref()).await;
+        clean_up_failed_publish(&cs, staged, doc_queue_path).await;
+        return Err(e.into());
+    }
 
-    // Add crate to queue for doc extraction if there is no documentation value set already
-    if settings.docs.enabled && pub_data.metadata.documentation.is_none() {
-        db.add_doc_queue(
-            &normalized_name,
-            &version,
-            &cs.create_rand_doc_queue_path().await?,
-        )
-        .await?;
+    Ok(Json(PubDataSuccess::with_warnings(warnings)))
+}
+
+/// Removes the crate version and its doc queue entry that `publish_crate` added to the DB.
+/// Errors are only logged, as the error that caused the failure is returned to the client.
+async fn roll_back_publish(
+    db: &Arc<dyn DbProvider>,
+    name: &NormalizedName,
+    version: &Version,
+    doc_queue_path: Option<&std::path::Path>,
+) {
+    if let Err(e) = db.delete_crate(name, version).await {
+        error!("Failed to roll back crate {}-{}: {}", name, version, e);
     }
+    let Some(path) = doc_queue_path else {
+        return;
+    };
+    let entry = match db.get_doc_queue().await {
+        Ok(queue) => queue.into_iter().find(|e| e.path == path),
+        Err(e) => {
+            error!("Failed to get doc queue: {}", e);
+            return;
+        }
+    };
+    if let Some(entry) = entry {
+        if let Err(e) = db.delete_doc_queue(entry.id).await {
+            error!("Failed to remove doc queue entry {:?}: {}", path, e);
+        }
+    }
+}
 
-    Ok(Json(PubDataSuccess::new()))
+/// Removes the staged crate file and the doc queue folder of a failed publish.
+/// Errors are only logged, as the error that caused the failure is returned to the client.
+async fn clean_up_failed_publish(
+    cs: &KellnrCrateStorage,
+    staged: StagedCrate,
+    doc_queue_path: Option<PathBuf>,
+) {
+    if let Err(e) = cs.discard_staged(staged).await {
+        error!("Failed to remove staged crate: {}", e);
+    }
+    if let Some(path) = doc_queue_path {
+        if let Err(e) = tokio::fs::remove_dir_all(&path).await {
+            error!("Failed to remove doc queue folder {:?}: {}", path, e);
+        }
+    }
 }
 
 pub async fn yank(
@@ -221,20 +339,20 @@ pub async fn unyank(
 mod reg_api_tests {
     use super::*;
     use appstate::AppStateData;
-    use axum::body::Body;
+    use axum::body::{Body, Bytes};
     use axum::http::Request;
     use axum::routing::{delete, get, put};
     use axum::Router;
+    use common::crate_overview::{CrateOverview, CrateOverviewPage};
     use db::mock::MockDb;
     use db::{ConString, Database, SqliteConString};
     use http_body_util::BodyExt;
     use hyper::header;
     use mockall::predicate::*;
     use rand::{distributions::Alphanumeric, thread_rng, Rng};
+    use settings::policy::RuleAction;
     use settings::Settings;
-    use std::path::PathBuf;
     use std::{iter, path};
-    use storage::kellnr_crate_storage::KellnrCrateStorage;
     use tokio::fs::read;
     use tower::ServiceExt;
 
@@ -484,13 +602,67 @@ mod reg_api_tests {
         assert_eq!(r.status(), StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn download_range_and_head() {
+        let settings = get_settings();
+        let kellnr = TestKellnr::new(settings).await;
+        let valid_pub_package = read("../test_data/pub_data.bin")
+            .await
+            .expect("Cannot open valid package file.");
+        let _ = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::put("/api/v1/crates/new")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, TOKEN)
+                    .body(Body::from(valid_pub_package))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/crates/test_lib/0.2.0/download")
+                    .header(header::RANGE, "bytes=0-9")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::PARTIAL_CONTENT, r.status());
+        assert_eq!("application/gzip", r.headers()[header::CONTENT_TYPE]);
+        assert!(r.headers().contains_key(header::ETAG));
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(10, body.len());
+
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::head("/api/v1/crates/test_lib/0.2.0/download")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        assert_eq!("bytes", r.headers()[header::ACCEPT_RANGES]);
+        assert!(r.headers().contains_key(header::CONTENT_LENGTH));
+    }
+
     #[tokio::test]
     async fn search_verify_query_and_default() {
         let mut mock_db = MockDb::new();
         mock_db
-            .expect_search_in_crate_name()
-            .with(eq("foo"))
-            .returning(|_| Ok(vec![]));
+            .expect_search_crates()
+            .with(eq("foo"), eq(0), eq(10))
+            .returning(|_, _, _| Ok(CrateOverviewPage::default()));
 
         let kellnr = app_search(Arc::new(mock_db)).await;
         let r = kellnr
@@ -510,9 +682,9 @@ mod reg_api_tests {
     async fn search_verify_per_page() {
         let mut mock_db = MockDb::new();
         mock_db
-            .expect_search_in_crate_name()
-            .with(eq("foo"))
-            .returning(|_| Ok(vec![]));
+            .expect_search_crates()
+            .with(eq("foo"), eq(0), eq(20))
+            .returning(|_, _, _| Ok(CrateOverviewPage::default()));
 
         let kellnr = app_search(Arc::new(mock_db)).await;
         let r = kellnr
@@ -528,6 +700,40 @@ mod reg_api_tests {
         assert!(serde_json::from_slice::<SearchResult>(&result_msg).is_ok());
     }
 
+    #[tokio::test]
+    async fn search_verify_page_and_total() {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_search_crates()
+            .with(eq("foo bar"), eq(20), eq(10))
+            .returning(|_, _, _| {
+                Ok(CrateOverviewPage {
+                    crates: vec![CrateOverview {
+                        original_name: "foo".to_string(),
+                        max_version: "1.0.0".to_string(),
+                        ..CrateOverview::default()
+                    }],
+                    total: 21,
+                })
+            });
+
+        let kellnr = app_search(Arc::new(mock_db)).await;
+        let r = kellnr
+            .oneshot(
+                Request::get("/api/v1/crates?q=foo%20bar&page=3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let result_msg = r.into_body().collect().await.unwrap().to_bytes();
+        let result = serde_json::from_slice::<SearchResult>(&result_msg).unwrap();
+        assert_eq!(21, result.meta.total);
+        assert_eq!(1, result.crates.len());
+        assert_eq!("foo", result.crates[0].name);
+    }
+
     #[tokio::test]
     async fn search_verify_per_page_out_of_range() {
         let settings = get_settings();
@@ -705,6 +911,64 @@ mod reg_api_tests {
         );
     }
 
+    async fn publish_with_policy(policy: settings::Policy) -> (TestKellnr, Bytes) {
+        let valid_pub_package = read("../test_data/pub_data.bin")
+            .await
+            .expect("Cannot open valid package file.");
+        let settings = Settings {
+            policy,
+            ..get_settings()
+        };
+        let kellnr = TestKellnr::new(settings).await;
+        let r = kellnr
+            .client
+            .clone()
+            .oneshot(
+                Request::put("/api/v1/crates/new")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, TOKEN)
+                    .body(Body::from(valid_pub_package))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let msg = r.into_body().collect().await.unwrap().to_bytes();
+        (kellnr, msg)
+    }
+
+    #[tokio::test]
+    async fn publish_package_with_policy_warnings() {
+        let mut policy = settings::Policy::default();
+        policy.rules.description = RuleAction::Warn;
+
+        let (kellnr, msg) = publish_with_policy(policy).await;
+
+        let success: PubDataSuccess =
+            serde_json::from_slice(&msg).expect("Cannot deserialize success message");
+        assert_eq!(
+            Some(vec!["Publish policy: No description is set".to_string()]),
+            success.warnings.unwrap().other
+        );
+        assert_eq!(1, kellnr.db.get_crate_meta_list(1).await.unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn publish_package_violating_policy_is_rejected() {
+        let mut policy = settings::Policy::default();
+        policy.rules.repository = RuleAction::Deny;
+
+        let (kellnr, msg) = publish_with_policy(policy).await;
+
+        let error: ApiError =
+            serde_json::from_slice(&msg).expect("Cannot deserialize error message");
+        assert_eq!(
+            "ERROR: Crate test_lib violates the publish policy: No repository URL is set",
+            error.errors[0].detail
+        );
+        let name = NormalizedName::from_unchecked_str("test_lib");
+        assert!(kellnr.db.get_crate_id(&name).await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn publish_existing_package() {
         // Use valid crate publish data to test.
@@ -752,6 +1016,114 @@ mod reg_api_tests {
         );
     }
 
+    #[tokio::test]
+    async fn publish_failure_leaves_no_crate_file() {
+        // Adding the crate to the DB fails.
+        let settings = get_settings();
+        let mut mock_db = admin_mock_db();
+        mock_db
+            .expect_publish_crate()
+            .returning(|_, _, _, _, _| Err(db::error::DbError::FailedToCountCrates));
+        let cs = publish_with_mock_db(mock_db, &settings).await;
+        assert!(!cs.exists("test_lib", "0.2.0").await.unwrap());
+        assert_eq!(0, cs.usage());
+        rm_rf::remove(&settings.registry.data_dir).expect("Cannot remove test data dir");
+
+        // Moving the crate file into place fails after the crate was added to the DB,
+        // as a file with the same name was added in the meantime.
+        let mut settings = get_settings();
+        settings.docs.enabled = true;
+        let crate_file = settings.bin_path().join("test_lib-0.2.0.crate");
+        let doc_queue_path = Arc::new(std::sync::Mutex::new(None));
+        let mut mock_db = admin_mock_db();
+        let conflicting_file = crate_file.clone();
+        let queued_path = doc_queue_path.clone();
+        mock_db
+            .expect_publish_crate()
+            .returning(move |_, _, _, _, path| {
+                *queued_path.lock().unwrap() = path.map(|p| p.to_path_buf());
+                std::fs::write(&conflicting_file, b"conflict").unwrap();
+                Ok(1)
+            });
+        mock_db
+            .expect_delete_crate()
+            .with(
+                eq(NormalizedName::from_unchecked_str("test_lib")),
+                eq(Version::from_unchecked_str("0.2.0")),
+            )
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let queued_path = doc_queue_path.clone();
+        mock_db.expect_get_doc_queue().returning(move || {
+            Ok(vec![db::DocQueueEntry {
+                id: 7,
+                krate: NormalizedName::from_unchecked_str("test_lib"),
+                version: "0.2.0".to_string(),
+                path: queued_path.lock().unwrap().clone().unwrap(),
+            }])
+        });
+        mock_db
+            .expect_delete_doc_queue()
+            .with(eq(7))
+            .times(1)
+            .returning(|_| Ok(()));
+        let cs = publish_with_mock_db(mock_db, &settings).await;
+        std::fs::remove_file(&crate_file).unwrap();
+        assert!(!cs.exists("test_lib", "0.2.0").await.unwrap());
+        assert_eq!(0, cs.usage());
+        let doc_queue_path = doc_queue_path.lock().unwrap().clone().unwrap();
+        assert!(!doc_queue_path.exists());
+        rm_rf::remove(&settings.registry.data_dir).expect("Cannot remove test data dir");
+    }
+
+    fn admin_mock_db() -> MockDb {
+        let mut mock_db = MockDb::new();
+        mock_db.expect_get_user_from_token().returning(|_| {
+            Ok(db::User {
+                id: 0,
+                name: "admin".to_string(),
+                pwd: "".to_string(),
+                salt: "".to_string(),
+                is_admin: true,
+            })
+        });
+        mock_db.expect_get_crate_id().returning(|_| Ok(None));
+        mock_db
+    }
+
+    /// Publishes the test package, which is expected to fail, and returns the crate storage.
+    async fn publish_with_mock_db(mock_db: MockDb, settings: &Settings) -> Arc<KellnrCrateStorage> {
+        let valid_pub_package = read("../test_data/pub_data.bin")
+            .await
+            .expect("Cannot open valid package file.");
+        std::fs::create_dir_all(&settings.registry.data_dir).unwrap();
+        let cs = Arc::new(KellnrCrateStorage::new(settings).await.unwrap());
+
+        let client = Router::new()
+            .route("/api/v1/crates/new", put(publish))
+            .with_state(AppStateData {
+                db: Arc::new(mock_db),
+                settings: settings.clone().into(),
+                crate_storage: cs.clone(),
+                ..appstate::test_state().await
+            });
+
+        let r = client
+            .oneshot(
+                Request::put("/api/v1/crates/new")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::AUTHORIZATION, TOKEN)
+                    .body(Body::from(valid_pub_package))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let msg = r.into_body().collect().await.unwrap().to_bytes();
+
+        assert!(serde_json::from_slice::<ApiError>(&msg).is_ok());
+        cs
+    }
+
     struct TestKellnr {
         path: PathBuf,
         client: Router,
@@ -1,7 +1,14 @@
+pub mod cache_eviction;
+mod crate_download;
+mod crate_file_check;
 pub mod cratesio_api;
 pub mod kellnr_api;
+mod name_check;
 mod owner;
+mod policy_check;
 pub mod pub_data;
 mod pub_success;
+pub mod scrub;
 pub mod search_params;
+pub mod unified_api;
 mod yank_success;
@@ -0,0 +1,281 @@
+use appstate::ProxyMode;
+use auth::token::Token;
+use common::original_name::OriginalName;
+use db::DbProvider;
+use error::error::{ApiError, ApiResult};
+use index::cratesio_prefetch_api::exists_upstream;
+use reqwest::Client;
+use settings::registry::NameCollision;
+use settings::Settings;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Checks the name of a crate before it is published. Crates with a reserved prefix can only
+/// be published by admins. Names that also exist on crates.io are handled according to
+/// `registry.name_collision`, as builds that use both registries are vulnerable to
+/// dependency confusion. Returns the warnings for the publisher.
+pub(crate) async fn check_crate_name(
+    name: &OriginalName,
+    token: &Token,
+    settings: &Settings,
+    db: &Arc<dyn DbProvider>,
+    client: &Client,
+    proxy_mode: &ProxyMode,
+) -> ApiResult<Vec<String>> {
+    if settings.registry.is_reserved(&name.to_normalized()) && !token.is_admin {
+        return Err(ApiError::from(&format!(
+            "Crate name {} has a reserved prefix and can only be published by an admin",
+            name
+        )));
+    }
+
+    let mode = settings.registry.name_collision;
+    if mode == NameCollision::Allow {
+        return Ok(Vec::new());
+    }
+
+    let exists = match exists_on_cratesio(name, settings, db, client, proxy_mode).await {
+        Ok(exists) => exists,
+        Err(e) => {
+            warn!(
+                "Could not check if crate {} exists on crates.io: {}",
+                name, e
+            );
+            // Only accept the crate if it would be accepted with a collision as well.
+            return match mode {
+                NameCollision::Admin if !token.is_admin => Err(could_not_verify(name)),
+                NameCollision::Reject => Err(could_not_verify(name)),
+                _ => Ok(vec![format!(
+                    "Could not check if crate {} exists on crates.io",
+                    name
+                )]),
+            };
+        }
+    };
+    if !exists {
+        return Ok(Vec::new());
+    }
+
+    warn!("Published crate {} also exists on crates.io", name);
+    let message = format!(
+        "Crate {} also exists on crates.io, which makes builds that use both registries vulnerable to dependency confusion",
+        name
+    );
+    match mode {
+        NameCollision::Admin if !token.is_admin => Err(ApiError::from(&format!(
+            "{}. Only an admin can publish it.",
+            message
+        ))),
+        NameCollision::Reject => Err(ApiError::from(&message)),
+        _ => Ok(vec![message]),
+    }
+}
+
+fn could_not_verify(name: &OriginalName) -> ApiError {
+    ApiError::from(&format!(
+        "Could not verify that crate {} does not exist on crates.io, please retry",
+        name
+    ))
+}
+
+/// Checks the cached crates.io index first and the upstream index otherwise. Fails if the
+/// crate is not cached and the proxy is disabled or offline, as crates.io cannot be checked.
+async fn exists_on_cratesio(
+    name: &OriginalName,
+    settings: &Settings,
+    db: &Arc<dyn DbProvider>,
+    client: &Client,
+    proxy_mode: &ProxyMode,
+) -> Result<bool, String> {
+    let cached = db
+        .get_cached_cratesio_crate(&name.to_normalized())
+        .await
+        .map_err(|e| e.to_string())?;
+    if cached.is_some() {
+        return Ok(true);
+    }
+    if !settings.proxy.enabled {
+        return Err("The crates.io proxy is disabled".to_string());
+    }
+    if proxy_mode.is_offline() {
+        return Err("The crates.io proxy is offline".to_string());
+    }
+
+    exists_upstream(client, &settings.proxy.index_url, name).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::cached_crate::CachedCrate;
+    use db::error::DbError;
+    use db::mock::MockDb;
+    use settings::Registry;
+
+    fn token(is_admin: bool) -> Token {
+        Token {
+            token: "token".to_string(),
+            user: "user".to_string(),
+            is_admin,
+        }
+    }
+
+    fn settings(name_collision: NameCollision) -> Settings {
+        Settings {
+            registry: Registry {
+                name_collision,
+                reserved_prefixes: vec!["acme-".to_string()],
+                ..Registry::default()
+            },
+            ..Settings::default()
+        }
+    }
+
+    fn db(cached: bool) -> Arc<dyn DbProvider> {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_get_cached_cratesio_crate()
+            .returning(move |name| {
+                Ok(cached.then(|| CachedCrate {
+                    name: name.to_string(),
+                    pinned: false,
+                    total_downloads: 0,
+                    versions: Vec::new(),
+                }))
+            });
+        Arc::new(mock_db)
+    }
+
+    fn failing_db() -> Arc<dyn DbProvider> {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_get_cached_cratesio_crate()
+            .returning(|name| Err(DbError::CrateNotFound(name.to_string())));
+        Arc::new(mock_db)
+    }
+
+    async fn check(
+        name: &str,
+        is_admin: bool,
+        name_collision: NameCollision,
+        cached: bool,
+    ) -> ApiResult<Vec<String>> {
+        check_with_db(name, is_admin, name_collision, db(cached)).await
+    }
+
+    async fn check_with_db(
+        name: &str,
+        is_admin: bool,
+        name_collision: NameCollision,
+        db: Arc<dyn DbProvider>,
+    ) -> ApiResult<Vec<String>> {
+        check_crate_name(
+            &OriginalName::from_unchecked_str(name.to_string()),
+            &token(is_admin),
+            &settings(name_collision),
+            &db,
+            &Client::new(),
+            &ProxyMode::new(false),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn reserved_prefix_requires_admin() {
+        assert!(check("acme-utils", false, NameCollision::Allow, false)
+            .await
+            .is_err());
+        assert!(check("Acme-Utils", false, NameCollision::Allow, false)
+            .await
+            .is_err());
+        assert!(check("acme-utils", true, NameCollision::Allow, false)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn uncached_name_cannot_be_verified_without_proxy() {
+        // The proxy is disabled, such that only the cache can be checked.
+        assert!(check("internal", false, NameCollision::Allow, false)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            1,
+            check("internal", false, NameCollision::Warn, false)
+                .await
+                .unwrap()
+                .len()
+        );
+        assert!(check("internal", false, NameCollision::Reject, false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn uncached_name_is_rejected_while_offline() {
+        let mut settings = settings(NameCollision::Reject);
+        settings.proxy.enabled = true;
+
+        let result = check_crate_name(
+            &OriginalName::from_unchecked_str("internal".to_string()),
+            &token(false),
+            &settings,
+            &db(false),
+            &Client::new(),
+            &ProxyMode::new(true),
+        )
+        .await;
+
+        assert!(result.unwrap_err().errors[0]
+            .detail
+            .contains("please retry"));
+    }
+
+    #[tokio::test]
+    async fn colliding_name_is_handled_by_mode() {
+        assert!(check("serde", false, NameCollision::Allow, true)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            1,
+            check("serde", false, NameCollision::Warn, true)
+                .await
+                .unwrap()
+                .len()
+        );
+        assert!(check("serde", false, NameCollision::Reject, true)
+            .await
+            .is_err());
+        assert!(check("serde", true, NameCollision::Reject, true)
+            .await
+            .is_err());
+        assert!(check("serde", false, NameCollision::Admin, true)
+            .await
+            .is_err());
+        assert_eq!(
+            1,
+            check("serde", true, NameCollision::Admin, true)
+                .await
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_lookup_is_handled_by_mode() {
+        let check = |is_admin, name_collision| {
+            check_with_db("serde", is_admin, name_collision, failing_db())
+        };
+
+        assert_eq!(1, check(false, NameCollision::Warn).await.unwrap().len());
+        assert_eq!(1, check(true, NameCollision::Admin).await.unwrap().len());
+        assert!(
+            check(false, NameCollision::Admin).await.unwrap_err().errors[0]
+                .detail
+                .contains("please retry")
+        );
+        assert!(check(true, NameCollision::Reject).await.is_err());
+    }
+}
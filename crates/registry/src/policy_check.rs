@@ -0,0 +1,276 @@
+use common::publish_metadata::PublishMetadata;
+use error::error::{ApiError, ApiResult};
+use semver::{Op, VersionReq};
+use settings::policy::{Policy, RuleAction};
+
+/// Checks the metadata of a published crate against the publish policy. Violations of
+/// "deny" rules reject the publish, violations of "warn" rules are returned as warnings
+/// for the publisher.
+pub(crate) fn check_policy(metadata: &PublishMetadata, policy: &Policy) -> ApiResult<Vec<String>> {
+    let rules = policy.rules_for(&metadata.name);
+    let violations = [
+        (
+            rules.license,
+            license_violation(metadata, &rules.allowed_licenses),
+        ),
+        (
+            rules.repository,
+            is_blank(&metadata.repository).then(|| "No repository URL is set".to_string()),
+        ),
+        (
+            rules.description,
+            is_blank(&metadata.description).then(|| "No description is set".to_string()),
+        ),
+        (rules.wildcard_dependencies, wildcard_violation(metadata)),
+    ];
+
+    let mut denied = Vec::new();
+    let mut warnings = Vec::new();
+    for (action, violation) in violations {
+        match (action, violation) {
+            (RuleAction::Deny, Some(v)) => denied.push(v),
+            (RuleAction::Warn, Some(v)) => warnings.push(format!("Publish policy: {}", v)),
+            _ => {}
+        }
+    }
+
+    if !denied.is_empty() {
+        return Err(ApiError::from(&format!(
+            "Crate {} violates the publish policy: {}",
+            metadata.name,
+            denied.join("; ")
+        )));
+    }
+    Ok(warnings)
+}
+
+fn is_blank(value: &Option<String>) -> bool {
+    value.as_deref().unwrap_or_default().trim().is_empty()
+}
+
+fn license_violation(metadata: &PublishMetadata, allowed: &[String]) -> Option<String> {
+    let Some(license) = metadata.license.as_deref().filter(|l| !l.trim().is_empty()) else {
+        return match (is_blank(&metadata.license_file), allowed.is_empty()) {
+            (false, true) => None,
+            (false, false) => Some(format!(
+                "Only a license file is set, but the license has to be one of: {}",
+                allowed.join(", ")
+            )),
+            (true, _) => Some("No license is set".to_string()),
+        };
+    };
+    if allowed.is_empty() {
+        return None;
+    }
+
+    let not_allowed = license_ids(license)
+        .into_iter()
+        .filter(|id| !allowed.iter().any(|a| a.eq_ignore_ascii_case(id)))
+        .collect::<Vec<_>>();
+    if not_allowed.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "License {} is not allowed, it has to be one of: {}",
+        not_allowed.join(", "),
+        allowed.join(", ")
+    ))
+}
+
+/// License identifiers of an SPDX expression like `(MIT OR Apache-2.0) AND Unicode-DFS-2016`.
+/// The deprecated `/` separator is supported as well. Exceptions after `WITH` are skipped.
+fn license_ids(expression: &str) -> Vec<&str> {
+    let mut ids = Vec::new();
+    let mut is_exception = false;
+    for token in expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '/')
+        .filter(|t| !t.is_empty())
+    {
+        match token {
+            "OR" | "AND" => {}
+            "WITH" => is_exception = true,
+            _ if is_exception => is_exception = false,
+            _ => ids.push(token),
+        }
+    }
+    ids
+}
+
+fn wildcard_violation(metadata: &PublishMetadata) -> Option<String> {
+    let deps = metadata
+        .deps
+        .iter()
+        .filter(|d| is_wildcard(&d.version_req))
+        .map(|d| format!("{} ({})", d.name, d.version_req))
+        .collect::<Vec<_>>();
+    if deps.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "Dependencies must not have wildcard versions: {}",
+        deps.join(", ")
+    ))
+}
+
+/// Whether the version requirement has a wildcard like `*`, `1.*` or `1.x`. Requirements
+/// that cannot be parsed are checked for a `*`, as cargo validated them already.
+fn is_wildcard(version_req: &str) -> bool {
+    match VersionReq::parse(version_req) {
+        Ok(req) => {
+            req.comparators.is_empty() || req.comparators.iter().any(|c| c.op == Op::Wildcard)
+        }
+        Err(_) => version_req.contains('*'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::publish_metadata::RegistryDep;
+    use settings::policy::{PolicyOverride, PolicyRules};
+
+    fn dep(name: &str, version_req: &str) -> RegistryDep {
+        RegistryDep {
+            name: name.to_string(),
+            version_req: version_req.to_string(),
+            features: None,
+            optional: false,
+            default_features: true,
+            target: None,
+            kind: Some("normal".to_string()),
+            registry: None,
+            explicit_name_in_toml: None,
+        }
+    }
+
+    fn metadata() -> PublishMetadata {
+        PublishMetadata {
+            description: Some("A crate".to_string()),
+            license: Some("MIT OR Apache-2.0".to_string()),
+            repository: Some("https://git.example/foo".to_string()),
+            deps: vec![dep("serde", "^1.0")],
+            ..PublishMetadata::minimal("foo", "1.0.0")
+        }
+    }
+
+    fn policy(action: RuleAction) -> Policy {
+        Policy {
+            rules: PolicyRules {
+                license: action,
+                allowed_licenses: vec!["MIT".to_string(), "Apache-2.0".to_string()],
+                repository: action,
+                description: action,
+                wildcard_dependencies: action,
+            },
+            overrides: Vec::new(),
+        }
+    }
+
+    fn error(result: ApiResult<Vec<String>>) -> String {
+        result.unwrap_err().errors[0].detail.clone()
+    }
+
+    #[test]
+    fn compliant_crate_has_no_warnings() {
+        assert!(check_policy(&metadata(), &policy(RuleAction::Deny))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn deny_rules_reject_crate_with_all_violations() {
+        let metadata = PublishMetadata {
+            description: None,
+            repository: Some(" ".to_string()),
+            deps: vec![
+                dep("serde", "*"),
+                dep("rand", "0.8"),
+                dep("log", "0.4.*"),
+                dep("regex", "1.x"),
+                dep("tokio", ">=1, <2"),
+            ],
+            ..metadata()
+        };
+
+        let e = error(check_policy(&metadata, &policy(RuleAction::Deny)));
+
+        assert_eq!(
+            "ERROR: Crate foo violates the publish policy: No repository URL is set; \
+             No description is set; \
+             Dependencies must not have wildcard versions: serde (*), log (0.4.*), regex (1.x)",
+            e
+        );
+    }
+
+    #[test]
+    fn warn_rules_return_warnings() {
+        let metadata = PublishMetadata {
+            description: None,
+            ..metadata()
+        };
+
+        let warnings = check_policy(&metadata, &policy(RuleAction::Warn)).unwrap();
+
+        assert_eq!(vec!["Publish policy: No description is set"], warnings);
+    }
+
+    #[test]
+    fn off_rules_are_ignored() {
+        let metadata = PublishMetadata::minimal("foo", "1.0.0");
+
+        assert!(check_policy(&metadata, &policy(RuleAction::Off))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn licenses_have_to_be_allowed() {
+        let check = |license: Option<&str>, license_file: Option<&str>, allowed: &[&str]| {
+            let metadata = PublishMetadata {
+                license: license.map(String::from),
+                license_file: license_file.map(String::from),
+                ..metadata()
+            };
+            let mut policy = policy(RuleAction::Deny);
+            policy.rules.allowed_licenses = allowed.iter().map(|a| a.to_string()).collect();
+            check_policy(&metadata, &policy)
+        };
+
+        assert!(check(
+            Some("(MIT OR Apache-2.0) AND mit"),
+            None,
+            &["MIT", "Apache-2.0"]
+        )
+        .is_ok());
+        assert!(check(Some("MIT/Apache-2.0"), None, &["MIT", "Apache-2.0"]).is_ok());
+        assert!(check(
+            Some("Apache-2.0 WITH LLVM-exception"),
+            None,
+            &["Apache-2.0"]
+        )
+        .is_ok());
+        assert!(check(None, Some("LICENSE"), &[]).is_ok());
+        assert!(error(check(Some("MIT OR GPL-3.0"), None, &["MIT"]))
+            .contains("License GPL-3.0 is not allowed, it has to be one of: MIT"));
+        assert!(error(check(None, Some("LICENSE"), &["MIT"])).contains("Only a license file"));
+        assert!(error(check(None, None, &[])).contains("No license is set"));
+    }
+
+    #[test]
+    fn overrides_change_rules_of_matching_crates() {
+        let mut policy = policy(RuleAction::Deny);
+        policy.overrides.push(PolicyOverride {
+            pattern: "foo*".to_string(),
+            description: Some(RuleAction::Warn),
+            ..PolicyOverride::default()
+        });
+        let metadata = PublishMetadata {
+            description: None,
+            ..metadata()
+        };
+
+        let warnings = check_policy(&metadata, &policy).unwrap();
+
+        assert_eq!(vec!["Publish policy: No description is set"], warnings);
+    }
+}
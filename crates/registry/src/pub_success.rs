@@ -16,4 +16,18 @@ impl PubDataSuccess {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Warnings that cargo shows to the publisher, e.g. about the crate name.
+    pub fn with_warnings(other: Vec<String>) -> Self {
+        if other.is_empty() {
+            return Self::new();
+        }
+
+        Self {
+            warnings: Some(Warnings {
+                other: Some(other),
+                ..Warnings::default()
+            }),
+        }
+    }
 }
@@ -0,0 +1,122 @@
+use chrono::Utc;
+use db::DbProvider;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::cratesio_crate_storage::CratesIoCrateStorage;
+use storage::kellnr_crate_storage::KellnrCrateStorage;
+use storage::scrub::{scrub_storage, ScrubReport, ScrubResult, Scrubber};
+use tracing::{error, info};
+
+/// Verifies the stored Kellnr and crates.io proxy crates against the checksums in the index.
+/// Only corrupted proxy crates are quarantined, as they are downloaded again on the next request.
+/// Corrupted Kellnr crates cannot be restored automatically and are only reported.
+pub async fn scrub(
+    db: &Arc<dyn DbProvider>,
+    crate_storage: &KellnrCrateStorage,
+    cratesio_storage: &CratesIoCrateStorage,
+    quarantine: bool,
+) -> ScrubReport {
+    let started = Utc::now().to_rfc3339();
+
+    let kellnr = match db.get_indexed_crates().await {
+        Ok(indexed) => scrub_storage(crate_storage, &indexed, false).await,
+        Err(e) => failed_result(format!("Unable to load crate index: {e}")),
+    };
+
+    let cratesio = match db.get_cratesio_stored_crates().await {
+        Ok(indexed) => scrub_storage(cratesio_storage, &indexed, quarantine).await,
+        Err(e) => failed_result(format!("Unable to load crates.io index: {e}")),
+    };
+
+    ScrubReport {
+        started,
+        finished: Utc::now().to_rfc3339(),
+        kellnr,
+        cratesio,
+    }
+}
+
+fn failed_result(msg: String) -> ScrubResult {
+    error!("{}", msg);
+    ScrubResult {
+        errors: vec![msg],
+        ..ScrubResult::default()
+    }
+}
+
+/// Starts a scrub run in the background. Returns `false` if a run is already in progress.
+pub fn spawn_scrub(
+    db: Arc<dyn DbProvider>,
+    crate_storage: Arc<KellnrCrateStorage>,
+    cratesio_storage: Arc<CratesIoCrateStorage>,
+    scrubber: Arc<Scrubber>,
+    quarantine: bool,
+) -> bool {
+    if !scrubber.try_start() {
+        return false;
+    }
+
+    tokio::spawn(async move {
+        info!("Starting storage scrub");
+        let report = scrub(&db, &crate_storage, &cratesio_storage, quarantine).await;
+        info!(
+            "Storage scrub finished: {} missing, {} orphaned, {} corrupted crate files",
+            report.kellnr.missing.len() + report.cratesio.missing.len(),
+            report.kellnr.orphaned.len() + report.cratesio.orphaned.len(),
+            report.kellnr.corrupted.len() + report.cratesio.corrupted.len(),
+        );
+        scrubber.finish(report);
+    });
+
+    true
+}
+
+/// Periodically scrubs the crate storages. Corrupted proxy crates are quarantined.
+pub async fn scrub_thread(
+    db: Arc<dyn DbProvider>,
+    crate_storage: Arc<KellnrCrateStorage>,
+    cratesio_storage: Arc<CratesIoCrateStorage>,
+    scrubber: Arc<Scrubber>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        if !spawn_scrub(
+            db.clone(),
+            crate_storage.clone(),
+            cratesio_storage.clone(),
+            scrubber.clone(),
+            true,
+        ) {
+            info!("Storage scrub still running, skipping scheduled run");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::indexed_crate::IndexedCrate;
+    use db::mock::MockDb;
+
+    #[tokio::test]
+    async fn scrub_reports_missing_crates() {
+        let mut db = MockDb::new();
+        db.expect_get_indexed_crates().returning(|| {
+            Ok(vec![IndexedCrate {
+                name: "missing".to_string(),
+                version: "1.0.0".to_string(),
+                cksum: "cksum".to_string(),
+            }])
+        });
+        db.expect_get_cratesio_stored_crates()
+            .returning(|| Ok(vec![]));
+        let db: Arc<dyn DbProvider> = Arc::new(db);
+        let state = appstate::test_state().await;
+
+        let report = scrub(&db, &state.crate_storage, &state.cratesio_storage, true).await;
+
+        assert_eq!(vec!["missing-1.0.0.crate"], report.kellnr.missing);
+        assert_eq!(0, report.cratesio.checked);
+    }
+}
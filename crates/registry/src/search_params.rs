@@ -1,11 +1,13 @@
 use axum::{extract::Query, http::request::Parts, RequestPartsExt};
-use common::original_name::OriginalName;
 use hyper::StatusCode;
 use std::{collections::HashMap, convert::TryFrom, usize};
 
 pub struct SearchParams {
-    pub q: OriginalName,
+    /// Whitespace separated search terms.
+    pub q: String,
     pub per_page: PerPage,
+    /// 1-based number of the requested page.
+    pub page: usize,
 }
 
 pub struct PerPage(pub usize);
@@ -47,7 +49,7 @@ impl<S> axum::extract::FromRequestParts<S> for SearchParams {
         let q = query_params
             .get("q")
             .ok_or((StatusCode::BAD_REQUEST, "missing q".to_owned()))?;
-        let q = OriginalName::try_from(q).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        let q = q.trim().to_string();
 
         let per_page = query_params
             .get("per_page")
@@ -62,7 +64,18 @@ impl<S> axum::extract::FromRequestParts<S> for SearchParams {
         let per_page =
             PerPage::try_from(per_page).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
-        Ok(Self { q, per_page })
+        let page = query_params
+            .get("page")
+            .unwrap_or(&"1".to_string())
+            .parse::<usize>()
+            .ok()
+            .filter(|p| *p > 0)
+            .ok_or((
+                StatusCode::BAD_REQUEST,
+                "page has to be a positive number.".to_owned(),
+            ))?;
+
+        Ok(Self { q, per_page, page })
     }
 }
 
@@ -0,0 +1,108 @@
+use crate::{cratesio_api, kellnr_api};
+use appstate::AppState;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::response::Response;
+use common::{original_name::OriginalName, version::Version};
+use tracing::error;
+
+/// Downloads a crate of the unified index. Kellnr crates take precedence over crates.io
+/// crates of the same name, like in the index.
+pub async fn download(
+    State(state): AppState,
+    Path((package, version)): Path<(OriginalName, Version)>,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if !(state.settings.proxy.enabled && state.settings.proxy.unified_index) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let kellnr_crate = state
+        .db
+        .get_crate_id(&package.to_normalized())
+        .await
+        .map_err(|e| {
+            error!("Failed to look up crate {}: {}", package, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if kellnr_crate.is_some() {
+        return kellnr_api::download(State(state), Path((package, version)), method, headers).await;
+    }
+
+    cratesio_api::download(
+        Path((package, version)),
+        State(state.settings),
+        State(state.cratesio_storage),
+        State(state.db),
+        State(state.proxy_mode),
+        State(state.http_client),
+        method,
+        headers,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use appstate::AppStateData;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::get;
+    use axum::Router;
+    use db::mock::MockDb;
+    use settings::Settings;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn app(unified_index: bool) -> Router {
+        let mut settings = Settings::default();
+        settings.proxy.enabled = true;
+        settings.proxy.unified_index = unified_index;
+
+        let mut mock_db = MockDb::new();
+        mock_db.expect_get_crate_id().returning(|_| Ok(Some(1)));
+        mock_db.expect_get_crate_cksum().returning(|_, _| Ok(None));
+
+        Router::new()
+            .route("/:package/:version/download", get(download))
+            .with_state(AppStateData {
+                db: Arc::new(mock_db),
+                settings: Arc::new(settings),
+                ..appstate::test_state().await
+            })
+    }
+
+    #[tokio::test]
+    async fn download_requires_unified_index() {
+        let r = app(false)
+            .await
+            .oneshot(
+                Request::get("/serde/1.0.0/download")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, r.status());
+    }
+
+    #[tokio::test]
+    async fn download_serves_kellnr_crate() {
+        // The Kellnr crate takes precedence, so crates.io is not contacted.
+        let r = app(true)
+            .await
+            .oneshot(
+                Request::get("/serde/1.0.0/download")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // The crate file is not in the test storage.
+        assert_eq!(StatusCode::NOT_FOUND, r.status());
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::Mutex;
+use tantivy::collector::{Count, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{Query, QueryParser};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED, STRING, TEXT,
+};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, Searcher, TantivyDocument, Term};
+
+const WRITER_MEMORY_BYTES: usize = 50_000_000;
+const SNIPPET_MAX_CHARS: usize = 200;
+
+/// Indexed data of the latest version of a crate.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchDocument {
+    pub name: String,
+    pub original_name: String,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub readme: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    /// Normalized name of the crate.
+    pub name: String,
+    /// HTML snippet of the readme or description with the matches highlighted in `<b>` tags.
+    pub snippet: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchHits {
+    pub hits: Vec<SearchHit>,
+    pub total: usize,
+}
+
+struct Fields {
+    name: Field,
+    original_name: Field,
+    description: Field,
+    keywords: Field,
+    categories: Field,
+    readme: Field,
+}
+
+/// Full-text index over the description, keywords, categories and readme of the crates.
+/// There is at most one document per crate, identified by its normalized name.
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+}
+
+impl SearchIndex {
+    /// Opens the index in `path` or creates a new one if there is none.
+    pub fn open(path: &Path) -> Result<Self> {
+        std::fs::create_dir_all(path)?;
+        let (schema, fields) = Self::schema();
+        let index = Index::open_or_create(MmapDirectory::open(path)?, schema)?;
+        Self::from_index(index, fields)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        let (schema, fields) = Self::schema();
+        Self::from_index(Index::create_in_ram(schema), fields)
+    }
+
+    fn from_index(index: Index, fields: Fields) -> Result<Self> {
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer(WRITER_MEMORY_BYTES)?;
+        Ok(Self {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+        })
+    }
+
+    fn schema() -> (Schema, Fields) {
+        // Prose is stemmed, such that e.g. "parsing" finds "parser".
+        let prose = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("en_stem")
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            )
+            .set_stored();
+
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            name: builder.add_text_field("name", STRING | STORED),
+            original_name: builder.add_text_field("original_name", TEXT),
+            description: builder.add_text_field("description", prose.clone()),
+            keywords: builder.add_text_field("keywords", TEXT),
+            categories: builder.add_text_field("categories", TEXT),
+            readme: builder.add_text_field("readme", prose),
+        };
+        (builder.build(), fields)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.searcher().num_docs() == 0
+    }
+
+    /// Adds the crate to the index or replaces its existing document.
+    pub fn upsert(&self, doc: &SearchDocument) -> Result<()> {
+        self.write(|writer| {
+            writer.delete_term(Term::from_field_text(self.fields.name, &doc.name));
+            writer.add_document(self.to_document(doc))?;
+            Ok(())
+        })
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        self.write(|writer| {
+            writer.delete_term(Term::from_field_text(self.fields.name, name));
+            Ok(())
+        })
+    }
+
+    /// Replaces all documents of the index.
+    pub fn rebuild(&self, docs: &[SearchDocument]) -> Result<()> {
+        self.write(|writer| {
+            writer.delete_all_documents()?;
+            for doc in docs {
+                writer.add_document(self.to_document(doc))?;
+            }
+            Ok(())
+        })
+    }
+
+    fn write(&self, f: impl FnOnce(&mut IndexWriter) -> Result<()>) -> Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow!("Search index writer is poisoned"))?;
+        if let Err(e) = f(&mut writer) {
+            writer.rollback()?;
+            return Err(e);
+        }
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    fn to_document(&self, doc: &SearchDocument) -> TantivyDocument {
+        let mut document = TantivyDocument::default();
+        document.add_text(self.fields.name, &doc.name);
+        document.add_text(self.fields.original_name, &doc.original_name);
+        if let Some(description) = &doc.description {
+            document.add_text(self.fields.description, description);
+        }
+        for keyword in &doc.keywords {
+            document.add_text(self.fields.keywords, keyword);
+        }
+        for category in &doc.categories {
+            document.add_text(self.fields.categories, category);
+        }
+        if let Some(readme) = &doc.readme {
+            document.add_text(self.fields.readme, readme);
+        }
+        document
+    }
+
+    /// Searches the crates with a query in the tantivy query language, e.g. `http AND client`.
+    /// Returns the hits ordered by relevance.
+    pub fn search(&self, query: &str, offset: usize, limit: usize) -> Result<SearchHits> {
+        let mut parser = QueryParser::for_index(
+            &self.index,
+            vec![
+                self.fields.original_name,
+                self.fields.description,
+                self.fields.keywords,
+                self.fields.categories,
+                self.fields.readme,
+            ],
+        );
+        parser.set_field_boost(self.fields.original_name, 4.0);
+        parser.set_field_boost(self.fields.keywords, 2.0);
+        parser.set_field_boost(self.fields.categories, 2.0);
+        // Syntax errors are ignored, as most queries are plain words typed into the UI.
+        let (query, _errors) = parser.parse_query_lenient(query);
+
+        let searcher = self.reader.searcher();
+        let total = searcher.search(&query, &Count)?;
+        // The number of hits is limited, as the collector allocates memory for all of them.
+        let limit = limit.min(total.saturating_sub(offset));
+        if limit == 0 {
+            return Ok(SearchHits {
+                hits: Vec::new(),
+                total,
+            });
+        }
+
+        let snippets = Snippets::new(&searcher, &*query, &self.fields)?;
+        let mut hits = Vec::new();
+        for (_score, address) in
+            searcher.search(&query, &TopDocs::with_limit(limit).and_offset(offset))?
+        {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let Some(name) = doc.get_first(self.fields.name).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            hits.push(SearchHit {
+                name: name.to_string(),
+                snippet: snippets.snippet(&doc),
+            });
+        }
+
+        Ok(SearchHits { hits, total })
+    }
+}
+
+struct Snippets {
+    readme: SnippetGenerator,
+    description: SnippetGenerator,
+}
+
+impl Snippets {
+    fn new(searcher: &Searcher, query: &dyn Query, fields: &Fields) -> Result<Self> {
+        let mut readme = SnippetGenerator::create(searcher, query, fields.readme)?;
+        readme.set_max_num_chars(SNIPPET_MAX_CHARS);
+        let mut description = SnippetGenerator::create(searcher, query, fields.description)?;
+        description.set_max_num_chars(SNIPPET_MAX_CHARS);
+        Ok(Self {
+            readme,
+            description,
+        })
+    }
+
+    /// Snippet of the readme, or of the description if the readme does not match.
+    fn snippet(&self, doc: &TantivyDocument) -> String {
+        let readme = self.readme.snippet_from_doc(doc);
+        if !readme.is_empty() {
+            return readme.to_html();
+        }
+        self.description.snippet_from_doc(doc).to_html()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(name: &str, description: &str, readme: Option<&str>) -> SearchDocument {
+        SearchDocument {
+            name: name.to_string(),
+            original_name: name.to_string(),
+            description: Some(description.to_string()),
+            readme: readme.map(String::from),
+            ..SearchDocument::default()
+        }
+    }
+
+    fn names(hits: &SearchHits) -> Vec<&str> {
+        hits.hits.iter().map(|h| h.name.as_str()).collect()
+    }
+
+    #[test]
+    fn search_finds_crates_by_readme_and_metadata() {
+        let index = SearchIndex::in_memory().unwrap();
+        index
+            .upsert(&doc(
+                "fast_http",
+                "An HTTP client",
+                Some("Sends requests with connection pooling."),
+            ))
+            .unwrap();
+        index
+            .upsert(&SearchDocument {
+                keywords: vec!["pooling".to_string()],
+                ..doc("pool", "Object pools", None)
+            })
+            .unwrap();
+        index
+            .upsert(&doc("logger", "Structured logging", Some("Logs to files.")))
+            .unwrap();
+
+        let hits = index.search("pooling", 0, 10).unwrap();
+
+        assert_eq!(2, hits.total);
+        // Keywords weigh more than the readme.
+        assert_eq!(vec!["pool", "fast_http"], names(&hits));
+        assert_eq!(
+            "Sends requests with connection <b>pooling</b>",
+            hits.hits[1].snippet
+        );
+    }
+
+    #[test]
+    fn search_highlights_description_without_readme_match() {
+        let index = SearchIndex::in_memory().unwrap();
+        index
+            .upsert(&doc(
+                "parser",
+                "A fast JSON parser",
+                Some("Nothing to see."),
+            ))
+            .unwrap();
+
+        let hits = index.search("json", 0, 10).unwrap();
+
+        assert_eq!("A fast <b>JSON</b> parser", hits.hits[0].snippet);
+    }
+
+    #[test]
+    fn search_pages_hits() {
+        let index = SearchIndex::in_memory().unwrap();
+        for name in ["a", "b", "c"] {
+            index.upsert(&doc(name, "A client", None)).unwrap();
+        }
+
+        let page = index.search("client", 1, 1).unwrap();
+        let beyond = index.search("client", 3, 10).unwrap();
+
+        assert_eq!(3, page.total);
+        assert_eq!(1, page.hits.len());
+        assert_eq!(3, beyond.total);
+        assert!(beyond.hits.is_empty());
+    }
+
+    #[test]
+    fn upsert_replaces_and_remove_deletes_crate() {
+        let index = SearchIndex::in_memory().unwrap();
+        index
+            .upsert(&doc("crate1", "Old description", None))
+            .unwrap();
+        index
+            .upsert(&doc("crate1", "New description", None))
+            .unwrap();
+
+        assert_eq!(0, index.search("old", 0, 10).unwrap().total);
+        assert_eq!(vec!["crate1"], names(&index.search("new", 0, 10).unwrap()));
+
+        index.remove("crate1").unwrap();
+
+        assert!(index.is_empty());
+    }
+
+    #[test]
+    fn rebuild_replaces_all_crates() {
+        let index = SearchIndex::in_memory().unwrap();
+        index.upsert(&doc("old", "A client", None)).unwrap();
+
+        index
+            .rebuild(&[doc("new1", "A client", None), doc("new2", "A server", None)])
+            .unwrap();
+
+        assert_eq!(vec!["new1"], names(&index.search("client", 0, 10).unwrap()));
+        assert_eq!(2, index.reader.searcher().num_docs());
+    }
+}
@@ -2,12 +2,14 @@ pub mod docs;
 pub mod local;
 pub mod log;
 pub mod origin;
+pub mod policy;
 pub mod postgresql;
 pub mod protocol;
 pub mod proxy;
 pub mod registry;
 pub mod settings;
 pub mod setup;
+pub mod storage;
 pub mod constants;
 mod deserialize_with;
 
@@ -20,6 +22,9 @@ pub use log::LogLevel;
 pub use docs::Docs;
 pub use local::Local;
 pub use origin::Origin;
+pub use policy::Policy;
 pub use proxy::Proxy;
 pub use registry::Registry;
 pub use setup::Setup;
+pub use storage::Storage;
+pub use storage::StorageBackend;
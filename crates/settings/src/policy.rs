@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+/// Rules that the metadata of published crates has to follow, e.g. to enforce the
+/// conventions of an organization.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Default)]
+pub struct Policy {
+    /// Rules of all crates without a matching override.
+    #[serde(flatten)]
+    pub rules: PolicyRules,
+    /// Rules of the crates whose name matches a pattern. If several overrides match,
+    /// the later ones take precedence.
+    #[serde(default)]
+    pub overrides: Vec<PolicyOverride>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct PolicyRules {
+    /// A license has to be set. If `allowed_licenses` is not empty, all licenses
+    /// of the SPDX expression have to be in the list.
+    pub license: RuleAction,
+    pub allowed_licenses: Vec<String>,
+    /// A repository URL has to be set.
+    pub repository: RuleAction,
+    /// A description has to be set.
+    pub description: RuleAction,
+    /// Dependencies must not have wildcard versions like `*` or `1.*`.
+    pub wildcard_dependencies: RuleAction,
+}
+
+/// Rules of the crates whose name matches `pattern`. Unset rules are inherited.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Default)]
+pub struct PolicyOverride {
+    /// Crate name, where `*` matches any characters, e.g. `acme-*`. Case is ignored.
+    pub pattern: String,
+    pub license: Option<RuleAction>,
+    pub allowed_licenses: Option<Vec<String>>,
+    pub repository: Option<RuleAction>,
+    pub description: Option<RuleAction>,
+    pub wildcard_dependencies: Option<RuleAction>,
+}
+
+/// What happens if a published crate violates a rule.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    #[default]
+    Off,
+    /// The crate is published and cargo shows a warning.
+    Warn,
+    /// The publish is rejected.
+    Deny,
+}
+
+impl Policy {
+    /// Rules of the crate with the given name, with all matching overrides applied.
+    pub fn rules_for(&self, crate_name: &str) -> PolicyRules {
+        let mut rules = self.rules.clone();
+        for o in self
+            .overrides
+            .iter()
+            .filter(|o| matches_pattern(&o.pattern, crate_name))
+        {
+            rules.license = o.license.unwrap_or(rules.license);
+            if let Some(allowed_licenses) = &o.allowed_licenses {
+                rules.allowed_licenses = allowed_licenses.clone();
+            }
+            rules.repository = o.repository.unwrap_or(rules.repository);
+            rules.description = o.description.unwrap_or(rules.description);
+            rules.wildcard_dependencies = o
+                .wildcard_dependencies
+                .unwrap_or(rules.wildcard_dependencies);
+        }
+        rules
+    }
+}
+
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+    let mut parts = pattern.split('*');
+    // Without a `*`, there is a single part which has to match the whole name.
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let Some(last) = parts.next_back() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy_override(pattern: &str, description: RuleAction) -> PolicyOverride {
+        PolicyOverride {
+            pattern: pattern.to_string(),
+            description: Some(description),
+            ..PolicyOverride::default()
+        }
+    }
+
+    #[test]
+    fn deserialize_policy_with_overrides() {
+        let toml = r#"
+            license = "deny"
+            allowed_licenses = ["MIT"]
+
+            [[overrides]]
+            pattern = "legacy-*"
+            license = "warn"
+        "#;
+
+        let policy: Policy = toml::from_str(toml).unwrap();
+
+        assert_eq!(
+            Policy {
+                rules: PolicyRules {
+                    license: RuleAction::Deny,
+                    allowed_licenses: vec!["MIT".to_string()],
+                    ..PolicyRules::default()
+                },
+                overrides: vec![PolicyOverride {
+                    pattern: "legacy-*".to_string(),
+                    license: Some(RuleAction::Warn),
+                    ..PolicyOverride::default()
+                }],
+            },
+            policy
+        );
+    }
+
+    #[test]
+    fn pattern_matches_crate_names() {
+        assert!(matches_pattern("serde", "Serde"));
+        assert!(!matches_pattern("serde", "serde_json"));
+        assert!(matches_pattern("acme-*", "acme-core"));
+        assert!(matches_pattern("*-sys", "openssl-sys"));
+        assert!(matches_pattern("a*b*c", "abc"));
+        assert!(matches_pattern("*", "anything"));
+        assert!(!matches_pattern("a*b*c", "acb"));
+        assert!(!matches_pattern("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn rules_for_applies_matching_overrides_in_order() {
+        let policy = Policy {
+            rules: PolicyRules {
+                license: RuleAction::Deny,
+                allowed_licenses: vec!["MIT".to_string()],
+                description: RuleAction::Warn,
+                ..PolicyRules::default()
+            },
+            overrides: vec![
+                policy_override("acme-*", RuleAction::Deny),
+                policy_override("acme-legacy-*", RuleAction::Off),
+                PolicyOverride {
+                    allowed_licenses: Some(vec!["GPL-3.0".to_string()]),
+                    ..policy_override("other", RuleAction::Deny)
+                },
+            ],
+        };
+
+        assert_eq!(policy.rules, policy.rules_for("foo"));
+        assert_eq!(RuleAction::Deny, policy.rules_for("acme-core").description);
+        assert_eq!(
+            RuleAction::Off,
+            policy.rules_for("acme-legacy-x").description
+        );
+        let other = policy.rules_for("other");
+        assert_eq!(RuleAction::Deny, other.license);
+        assert_eq!(vec!["GPL-3.0".to_string()], other.allowed_licenses);
+    }
+}
@@ -1,13 +1,258 @@
 use serde::{Deserialize, Serialize};
 
+/// Name of the crates.io upstream, which is configured by the top-level `[proxy]` settings.
+pub const CRATESIO_UPSTREAM: &str = "crates-io";
+
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
 pub struct Proxy {
     pub enabled: bool,
     pub num_threads: usize,
+    #[serde(default)]
+    pub max_cache_size: u64,
+    /// Maximal number of queued index updates of each upstream.
+    #[serde(default = "default_update_queue_size")]
+    pub update_queue_size: usize,
+    /// Seconds a cached index file is served without checking the upstream for updates.
+    /// Afterwards, the cached file is still served, but updated in the background.
+    #[serde(default = "default_index_ttl_secs")]
+    pub index_ttl_secs: u64,
+    /// Seconds a crate that does not exist upstream is not requested again.
+    #[serde(default = "default_not_found_ttl_secs")]
+    pub not_found_ttl_secs: u64,
+    /// Sparse index of the upstream registry.
+    #[serde(default = "default_index_url")]
+    pub index_url: String,
+    /// Download URL of the upstream registry. Supports the same markers as the `dl` field
+    /// of a registry `config.json`: `{crate}`, `{version}`, `{prefix}` and `{lowerprefix}`.
+    #[serde(default = "default_download_url")]
+    pub download_url: String,
+    /// Web API of the upstream registry, used for search and crate metadata.
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// Minimal interval between two requests to the upstream web API in milliseconds.
+    #[serde(default = "default_api_request_interval_ms")]
+    pub api_request_interval_ms: u64,
+    /// Additional registries that are proxied next to crates.io.
+    #[serde(default)]
+    pub upstreams: Vec<Upstream>,
+    /// If set, crates without a matching proxy rule are blocked until an admin approves them.
+    #[serde(default)]
+    pub approval_required: bool,
+    /// Serve only cached crates and never contact an upstream. Can be changed at runtime.
+    #[serde(default)]
+    pub offline: bool,
+    /// Merge the cached crates into the search results of crates.io. The cached crates
+    /// are always searched if crates.io is unavailable.
+    #[serde(default)]
+    pub local_search: bool,
+    /// Serve the Kellnr crates and the proxied crates.io crates from one merged index at
+    /// `/api/v1/unified`. Kellnr crates take precedence over crates.io crates of the same name.
+    #[serde(default)]
+    pub unified_index: bool,
+    /// HTTP(S) proxy for all requests to the upstream registries, e.g. `http://proxy:3128`.
+    /// Empty if no proxy is used.
+    #[serde(default)]
+    pub http_proxy: String,
+    /// Comma separated hosts that are not accessed through the HTTP proxy, like `NO_PROXY`.
+    #[serde(default)]
+    pub no_proxy: String,
+    /// PEM file with additional root certificates, e.g. of a TLS intercepting proxy.
+    /// Empty if only the system root certificates are trusted.
+    #[serde(default)]
+    pub ca_cert: String,
+    /// Timeout to connect to an upstream registry in seconds.
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Timeout of a request to an upstream registry in seconds, including reading
+    /// the response. 0 disables the timeout.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// User-Agent header of the requests to the upstream registries.
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+}
+
+/// Registry that is proxied next to crates.io.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
+pub struct Upstream {
+    /// Unique name of the upstream, used in the proxy URL and the storage directory.
+    pub name: String,
+    /// Sparse index of the upstream registry.
+    pub index_url: String,
+    /// Download URL of the upstream registry, see `Proxy::download_url`.
+    pub download_url: String,
+}
+
+fn default_update_queue_size() -> usize {
+    10_000
+}
+
+fn default_index_ttl_secs() -> u64 {
+    60 * 30
+}
+
+fn default_not_found_ttl_secs() -> u64 {
+    60 * 10
+}
+
+fn default_index_url() -> String {
+    String::from("https://rsproxy.cn/index/")
+}
+
+fn default_download_url() -> String {
+    String::from("https://rsproxy.cn/api/v1/crates/{crate}/{version}/download")
+}
+
+fn default_api_url() -> String {
+    String::from("https://crates.io")
+}
+
+fn default_api_request_interval_ms() -> u64 {
+    1000
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+fn default_user_agent() -> String {
+    String::from("kellnr.io/kellnr")
 }
 
 impl Default for Proxy {
     fn default() -> Self {
-        Self { enabled: false, num_threads: 10 }
+        Self {
+            enabled: false,
+            num_threads: 10,
+            max_cache_size: 0,
+            update_queue_size: default_update_queue_size(),
+            index_ttl_secs: default_index_ttl_secs(),
+            not_found_ttl_secs: default_not_found_ttl_secs(),
+            index_url: default_index_url(),
+            download_url: default_download_url(),
+            api_url: default_api_url(),
+            api_request_interval_ms: default_api_request_interval_ms(),
+            upstreams: Vec::new(),
+            approval_required: false,
+            offline: false,
+            local_search: false,
+            unified_index: false,
+            http_proxy: String::new(),
+            no_proxy: String::new(),
+            ca_cert: String::new(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            timeout_secs: default_timeout_secs(),
+            user_agent: default_user_agent(),
+        }
+    }
+}
+
+impl Proxy {
+    /// URL to download a crate from. Like cargo, `/{crate}/{version}/download` is appended
+    /// if the download URL contains no markers.
+    pub fn crate_download_url(&self, name: &str, version: &str) -> String {
+        crate_download_url(&self.download_url, name, version)
+    }
+
+    /// URL of an endpoint of the upstream web API, e.g. `/api/v1/crates`.
+    pub fn api_endpoint(&self, path: &str) -> String {
+        format!("{}{}", self.api_url.trim_end_matches('/'), path)
+    }
+}
+
+impl Upstream {
+    /// URL to download a crate from, see `Proxy::crate_download_url`.
+    pub fn crate_download_url(&self, name: &str, version: &str) -> String {
+        crate_download_url(&self.download_url, name, version)
+    }
+
+    /// The name is used as a path segment, such that only lowercase alphanumeric
+    /// characters, `-` and `_` are allowed. The crates.io upstream name is reserved.
+    pub fn has_valid_name(&self) -> bool {
+        !self.name.is_empty()
+            && self.name != CRATESIO_UPSTREAM
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    }
+}
+
+/// URL to download a crate from a registry with the given download URL,
+/// see `Proxy::crate_download_url`.
+pub fn crate_download_url(download_url: &str, name: &str, version: &str) -> String {
+    const MARKERS: [&str; 4] = ["{crate}", "{version}", "{prefix}", "{lowerprefix}"];
+
+    if MARKERS.iter().any(|m| download_url.contains(m)) {
+        let prefix = prefix(name);
+        download_url
+            .replace("{crate}", name)
+            .replace("{version}", version)
+            .replace("{lowerprefix}", &prefix.to_lowercase())
+            .replace("{prefix}", &prefix)
+    } else {
+        format!(
+            "{}/{}/{}/download",
+            download_url.trim_end_matches('/'),
+            name,
+            version
+        )
+    }
+}
+
+/// Directory of a crate in a registry index, e.g. `se/rd` for `serde`.
+fn prefix(name: &str) -> String {
+    match name.len() {
+        1 => String::from("1"),
+        2 => String::from("2"),
+        3 => format!("3/{}", &name[0..1]),
+        _ => format!("{}/{}", &name[0..2], &name[2..4]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(download_url: &str) -> Proxy {
+        Proxy {
+            download_url: download_url.to_string(),
+            ..Proxy::default()
+        }
+    }
+
+    #[test]
+    fn crate_download_url_replaces_markers() {
+        assert_eq!(
+            "https://mirror.example/crates/se/rd/serde/serde-1.0.0.crate",
+            proxy("https://mirror.example/crates/{prefix}/{crate}/{crate}-{version}.crate")
+                .crate_download_url("serde", "1.0.0")
+        );
+    }
+
+    #[test]
+    fn crate_download_url_without_markers() {
+        assert_eq!(
+            "https://static.crates.io/crates/serde/1.0.0/download",
+            proxy("https://static.crates.io/crates/").crate_download_url("serde", "1.0.0")
+        );
+    }
+
+    #[test]
+    fn upstream_name_is_validated() {
+        let upstream = |name: &str| Upstream {
+            name: name.to_string(),
+            index_url: String::new(),
+            download_url: String::new(),
+        };
+
+        assert!(upstream("my-registry_2").has_valid_name());
+        assert!(!upstream("").has_valid_name());
+        assert!(!upstream("My/Registry").has_valid_name());
+        assert!(!upstream(CRATESIO_UPSTREAM).has_valid_name());
     }
 }
@@ -5,19 +5,65 @@ use serde::{Deserialize, Serialize};
 pub struct Registry {
     pub data_dir: String,
     pub session_age_seconds: u64,
+    /// Deprecated, the in-memory cache is limited by `cache_size_mb`. Kept such that
+    /// existing configurations that disable the cache with 0 still work.
     pub cache_size: u64,
+    #[serde(default = "default_cache_size_mb")]
+    pub cache_size_mb: u64,
     pub max_crate_size: u64,
+    #[serde(default = "default_max_unpacked_crate_size")]
+    pub max_unpacked_crate_size: u64,
     pub auth_required: bool,
+    #[serde(default)]
+    pub name_collision: NameCollision,
+    #[serde(default)]
+    pub reserved_prefixes: Vec<String>,
+    #[serde(default)]
+    pub full_text_search: bool,
 } 
 
+fn default_cache_size_mb() -> u64 {
+    100
+}
+
+fn default_max_unpacked_crate_size() -> u64 {
+    512
+}
+
+/// Handling of a published crate whose name also exists on crates.io.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NameCollision {
+    #[default]
+    Allow,
+    Warn,
+    Reject,
+    /// Only admins can publish the crate.
+    Admin,
+}
+
+impl Registry {
+    /// Crates with a reserved prefix can only be published by admins.
+    pub fn is_reserved(&self, crate_name: &str) -> bool {
+        self.reserved_prefixes
+            .iter()
+            .any(|p| !p.is_empty() && crate_name.starts_with(&p.to_lowercase()))
+    }
+}
+
 impl Default for Registry {
     fn default() -> Self {
         Self {
             data_dir: String::from("/tmp/kellnr"),
             session_age_seconds: 60*60*8,
             cache_size: 1000,
+            cache_size_mb: default_cache_size_mb(),
             max_crate_size: 10*1000,
+            max_unpacked_crate_size: default_max_unpacked_crate_size(),
             auth_required: false,
+            name_collision: NameCollision::default(),
+            reserved_prefixes: Vec::new(),
+            full_text_search: false,
         }
     }
 }
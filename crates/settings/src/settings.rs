@@ -11,10 +11,12 @@ use crate::docs::Docs;
 use crate::local::Local;
 use crate::log::Log;
 use crate::origin::Origin;
+use crate::policy::Policy;
 use crate::postgresql::Postgresql;
 use crate::proxy::Proxy;
 use crate::registry::Registry;
 use crate::setup::Setup;
+use crate::storage::Storage;
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Default, Clone)]
 pub struct Settings {
@@ -26,6 +28,8 @@ pub struct Settings {
     pub local: Local,
     pub origin: Origin,
     pub postgresql: Postgresql,
+    pub storage: Storage,
+    pub policy: Policy,
 }
 
 impl TryFrom<&Path> for Settings {
@@ -86,6 +90,10 @@ impl Settings {
         path::PathBuf::from(&self.registry.data_dir).join("docs")
     }
 
+    pub fn search_index_path(&self) -> path::PathBuf {
+        path::PathBuf::from(&self.registry.data_dir).join("search_index")
+    }
+
     pub fn base_path(&self) -> path::PathBuf {
         path::PathBuf::from(&self.registry.data_dir).join("git")
     }
@@ -93,6 +101,12 @@ impl Settings {
     pub fn crates_io_bin_path(&self) -> path::PathBuf {
         path::PathBuf::from(&self.registry.data_dir).join("cratesio")
     }
+
+    pub fn upstream_bin_path(&self, upstream: &str) -> path::PathBuf {
+        path::PathBuf::from(&self.registry.data_dir)
+            .join("proxy")
+            .join(upstream)
+    }
 }
 
 pub fn get_settings() -> Result<Settings, ConfigError> {
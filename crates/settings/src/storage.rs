@@ -0,0 +1,138 @@
+use crate::deserialize_with::DeserializeWith;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt::Display;
+
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Default)]
+pub struct Storage {
+    #[serde(deserialize_with = "StorageBackend::deserialize_with")]
+    pub backend: StorageBackend,
+    /// Hours between two integrity scrubs of the stored crates. `0` disables the background scrub.
+    #[serde(default)]
+    pub scrub_interval_hours: u64,
+    pub s3: S3,
+}
+
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
+pub struct S3 {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    #[serde(skip_serializing, default)]
+    pub secret_key: String,
+    pub allow_http: bool,
+}
+
+impl Default for S3 {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from(""),
+            region: String::from("us-east-1"),
+            bucket: String::from("kellnr"),
+            access_key: String::from(""),
+            secret_key: String::from(""),
+            allow_http: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum StorageBackend {
+    #[default]
+    Filesystem,
+    S3,
+}
+
+impl Display for StorageBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageBackend::Filesystem => write!(f, "filesystem"),
+            StorageBackend::S3 => write!(f, "s3"),
+        }
+    }
+}
+
+impl Serialize for StorageBackend {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl DeserializeWith for StorageBackend {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?.to_lowercase();
+
+        match s.as_ref() {
+            "filesystem" => Ok(StorageBackend::Filesystem),
+            "s3" => Ok(StorageBackend::S3),
+            _ => Err(serde::de::Error::custom(format!(
+                "error trying to deserialize storage backend: {s}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_storage_s3() {
+        let toml = r#"
+            backend = "S3"
+
+            [s3]
+            endpoint = "http://localhost:9000"
+            region = "eu-central-1"
+            bucket = "crates"
+            access_key = "minio"
+            secret_key = "minio123"
+            allow_http = true
+        "#;
+
+        let storage: Storage = toml::from_str(toml).unwrap();
+        assert_eq!(storage.backend, StorageBackend::S3);
+        assert_eq!(storage.s3.endpoint, "http://localhost:9000");
+        assert_eq!(storage.s3.secret_key, "minio123");
+        assert!(storage.s3.allow_http);
+    }
+
+    #[test]
+    fn test_deserialize_storage_backend_error() {
+        let toml = r#"
+            backend = "ftp"
+
+            [s3]
+            endpoint = ""
+            region = ""
+            bucket = ""
+            access_key = ""
+            allow_http = false
+        "#;
+
+        let storage: Result<Storage, toml::de::Error> = toml::from_str(toml);
+        assert!(storage.is_err());
+    }
+
+    #[test]
+    fn test_serialize_skips_secret_key() {
+        let storage = Storage {
+            backend: StorageBackend::S3,
+            s3: S3 {
+                secret_key: String::from("secret"),
+                ..S3::default()
+            },
+            ..Storage::default()
+        };
+
+        let json = serde_json::to_string(&storage).unwrap();
+        assert!(json.contains(r#""backend":"s3""#));
+        assert!(!json.contains("secret"));
+    }
+}
@@ -1,39 +1,122 @@
+use crate::crate_store::{init_crate_store, CrateStore, CrateStream};
 use anyhow::{bail, Context, Result};
+use bytes::Bytes;
 use common::original_name::OriginalName;
 use common::util::generate_rand_string;
 use common::version::Version;
+use futures::StreamExt;
 use hex::ToHex;
 use moka::future::Cache;
 use settings::Settings;
 use sha2::{Digest, Sha256};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use tokio::{
-    fs::{create_dir_all, DirBuilder, File},
-    io::{AsyncReadExt, AsyncWriteExt},
-};
-use tracing::{error, warn};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::fs::DirBuilder;
+use tracing::error;
 
-pub type CrateCache = Cache<PathBuf, Vec<u8>>;
+pub type CrateCache = Cache<String, Bytes>;
+
+/// Crates larger than this are streamed from the backend instead of being cached in memory.
+pub const MAX_CACHED_CRATE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Suffix appended to the key of crate files that were moved out of the way by the scrubber.
+pub const QUARANTINE_SUFFIX: &str = ".quarantined";
+
+/// Suffix of crate files that are written but not yet published.
+pub const STAGING_SUFFIX: &str = ".staged";
+
+/// A crate file written to a temporary key. It is not served until it is committed.
+#[derive(Debug)]
+pub struct StagedCrate {
+    key: String,
+    staging_key: String,
+    size: u64,
+    pub cksum: String,
+}
+
+/// Content of a crate file, either from the in-memory cache or streamed from the backend.
+pub enum CrateBody {
+    Bytes(Bytes),
+    Stream(CrateStream),
+}
 
 pub struct CachedCrateStorage {
-    crate_folder: PathBuf,
+    store: Arc<dyn CrateStore>,
     pub doc_queue_path: PathBuf,
     cache: Option<CrateCache>,
+    used_bytes: AtomicU64,
 }
 
 impl CachedCrateStorage {
-    pub async fn new(crate_folder: PathBuf, settings: &Settings) -> Result<Self, anyhow::Error> {
-        let cs = Self {
-            crate_folder,
+    /// Creates a crate storage on top of the backend configured in the settings.
+    /// The `crate_folder` is used for the filesystem backend, the `prefix` for the object storage backend.
+    pub async fn new(
+        crate_folder: PathBuf,
+        prefix: &str,
+        settings: &Settings,
+    ) -> Result<Self, anyhow::Error> {
+        let store = init_crate_store(settings, crate_folder, prefix).await?;
+        let storage = Self::with_store(store, settings);
+        storage.remove_staged().await?;
+        storage.calculate_usage().await?;
+        Ok(storage)
+    }
+
+    pub fn with_store(store: Arc<dyn CrateStore>, settings: &Settings) -> Self {
+        Self {
+            store,
             doc_queue_path: settings.doc_queue_path(),
-            cache: if settings.registry.cache_size > 0 {
-                Some(Cache::new(settings.registry.cache_size))
+            cache: if settings.registry.cache_size > 0 && settings.registry.cache_size_mb > 0 {
+                Some(Self::crate_cache(settings.registry.cache_size_mb))
             } else {
                 None
             },
-        };
-        Self::create_bin_path(&cs.crate_folder).await?;
-        Ok(cs)
+            used_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// In-memory cache weighted by the size of the cached crates. The `size` is in MB.
+    fn crate_cache(size: u64) -> CrateCache {
+        Cache::builder()
+            .weigher(|_key: &String, krate: &Bytes| -> u32 {
+                krate.len().try_into().unwrap_or(u32::MAX)
+            })
+            .max_capacity(size * 1024 * 1024)
+            .build()
+    }
+
+    /// Removes staged crates left over from publishes that were interrupted, e.g. by a restart.
+    async fn remove_staged(&self) -> Result<()> {
+        for file in self.store.list().await? {
+            if file.key.ends_with(STAGING_SUFFIX) {
+                self.store.delete(&file.key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sums up the size of all crate files in the storage and uses it as the
+    /// starting point for the tracked disk usage.
+    pub async fn calculate_usage(&self) -> Result<u64> {
+        let used = self.store.list().await?.iter().map(|f| f.size).sum();
+        self.used_bytes.store(used, Ordering::SeqCst);
+        Ok(used)
+    }
+
+    /// Disk space in bytes used by the stored crate files.
+    pub fn usage(&self) -> u64 {
+        self.used_bytes.load(Ordering::SeqCst)
+    }
+
+    fn release(&self, bytes: u64) {
+        // The closure always returns Some, such that the update cannot fail.
+        let _ = self
+            .used_bytes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                Some(used.saturating_sub(bytes))
+            });
     }
 
     pub async fn add_bin_package(
@@ -42,120 +125,197 @@ impl CachedCrateStorage {
         version: &Version,
         crate_data: &[u8],
     ) -> Result<String> {
-        if !self.crate_folder.exists() {
-            create_dir_all(&self.crate_folder)
-                .await
-                .with_context(|| "Failed to create crate_folder")?
+        let key = Self::crate_key(name, version);
+        if self.store.exists(&key).await? {
+            bail!("Crate with version already exists: {}-{}", &name, &version)
         }
 
-        let file_path = self.crate_path(name, version);
-        if Path::new(&file_path).exists() {
+        self.store
+            .put(&key, Bytes::copy_from_slice(crate_data))
+            .await
+            .with_context(|| format!("Unable to store crate: {}", key))?;
+        self.used_bytes
+            .fetch_add(crate_data.len() as u64, Ordering::SeqCst);
+
+        Ok(Self::cksum(crate_data))
+    }
+
+    /// Writes the crate to a temporary key. Use [`Self::commit_staged`] to publish it
+    /// or [`Self::discard_staged`] to remove it again.
+    pub async fn stage_bin_package(
+        &self,
+        name: &OriginalName,
+        version: &Version,
+        crate_data: &[u8],
+    ) -> Result<StagedCrate> {
+        let key = Self::crate_key(name, version);
+        if self.store.exists(&key).await? {
             bail!("Crate with version already exists: {}-{}", &name, &version)
         }
 
-        let mut file = File::create(&file_path).await.with_context(|| {
-            format!(
-                "Unable to create file on storage: {}",
-                file_path.to_string_lossy()
-            )
-        })?;
+        let staging_key = format!("{key}.{}{STAGING_SUFFIX}", generate_rand_string(10));
+        self.store
+            .put(&staging_key, Bytes::copy_from_slice(crate_data))
+            .await
+            .with_context(|| format!("Unable to stage crate: {}", key))?;
+        self.used_bytes
+            .fetch_add(crate_data.len() as u64, Ordering::SeqCst);
 
-        file.write_all(crate_data).await.with_context(|| {
-            format!(
-                "Unable to write crate to file: {}",
-                file_path.to_string_lossy()
-            )
-        })?;
+        Ok(StagedCrate {
+            key,
+            staging_key,
+            size: crate_data.len() as u64,
+            cksum: Self::cksum(crate_data),
+        })
+    }
 
-        let sha256 = match self.calc_sha256(&file_path).await {
-            Ok(s) => s,
-            Err(e) => return Err(e),
-        };
+    /// Moves a staged crate to its final key. Fails if the crate exists already,
+    /// in which case the staged crate is left in place and has to be discarded.
+    pub async fn commit_staged(&self, staged: &StagedCrate) -> Result<()> {
+        self.store
+            .rename(&staged.staging_key, &staged.key)
+            .await
+            .with_context(|| format!("Unable to publish staged crate: {}", staged.key))
+    }
 
-        Ok(sha256)
+    pub async fn discard_staged(&self, staged: StagedCrate) -> Result<()> {
+        self.store.delete(&staged.staging_key).await?;
+        self.release(staged.size);
+        Ok(())
     }
 
-    pub fn crate_path(&self, name: &str, version: &str) -> PathBuf {
-        self.crate_folder
-            .join(format!("{}-{}.crate", name, version))
+    pub fn crate_key(name: &str, version: &str) -> String {
+        format!("{}-{}.crate", name, version)
     }
 
-    async fn calc_sha256(&self, file_path: &Path) -> Result<String> {
-        let mut tries = 0;
-        let max_tries = 5;
+    /// Sha256 checksum of a crate file, as used in the `cksum` field of the index.
+    pub fn cksum(crate_data: &[u8]) -> String {
+        Sha256::digest(crate_data).encode_hex()
+    }
 
-        while tries < max_tries {
-            let mut file = File::open(&file_path)
-                .await
-                .with_context(|| "Unable to open crate file to calc cksum")?;
+    pub async fn exists(&self, name: &str, version: &str) -> Result<bool> {
+        self.store.exists(&Self::crate_key(name, version)).await
+    }
 
-            let mut buf: Vec<u8> = vec![];
+    pub async fn get_file(&self, name: &str, version: &str) -> Option<Bytes> {
+        let key = Self::crate_key(name, version);
+        if let Some(krate) = self.cached(&key).await {
+            return Some(krate);
+        }
 
-            let bytes_read = file
-                .read_to_end(&mut buf)
-                .await
-                .with_context(|| "Unable to read crate file to calc cksum")?;
-
-            let real_length = file.metadata().await?.len();
-            if bytes_read != real_length as usize {
-                let error_msg = format!(
-                    "Try {} - Unable to read crate file {} to calc cksum. Read {} bytes, but file length is {}",
-                    tries + 1,
-                    file_path.display(),
-                    bytes_read,
-                    real_length
-                );
-                warn!(error_msg);
-                tries += 1;
-            } else {
-                let sha256: String = Sha256::digest(&buf).encode_hex();
-                return Ok(sha256);
+        match self.store.get(&key).await {
+            Ok(krate) => krate,
+            Err(e) => {
+                error!("Unable to get crate {} from storage: {}", key, e);
+                None
             }
         }
-
-        let error_msg = format!(
-            "Unable to read crate file {} to calc cksum after {} tries",
-            file_path.display(),
-            max_tries
-        );
-        error!(error_msg);
-        bail!(error_msg)
     }
 
-    async fn create_bin_path(crate_path: &Path) -> Result<()> {
-        if !crate_path.exists() {
-            DirBuilder::new()
-                .recursive(true)
-                .create(&crate_path)
-                .await
-                .with_context(|| format!("Unable to create bin path: {crate_path:?}"))?;
+    /// Size of the crate file in bytes or `None` if the crate does not exist.
+    pub async fn size(&self, name: &str, version: &str) -> Result<Option<u64>> {
+        let key = Self::crate_key(name, version);
+        if let Some(krate) = self.cached(&key).await {
+            return Ok(Some(krate.len() as u64));
         }
-        Ok(())
+        self.store.size(&key).await
     }
 
-    pub async fn get_file(&self, file_path: PathBuf) -> Option<Vec<u8>> {
-        async fn from_cache(cache: &CrateCache, file_path: PathBuf) -> Option<Vec<u8>> {
-            match cache.get(&file_path).await {
-                None => {
-                    let mut file = File::open(&file_path).await.ok()?;
-                    let mut krate = Vec::new();
-                    file.read_to_end(&mut krate).await.ok()?;
-                    cache.insert(file_path, krate.clone()).await;
-                    Some(krate)
+    /// Reads the crate file or the given byte range of it. Small crates are served from
+    /// and added to the cache, larger ones are streamed from the storage backend.
+    pub async fn read(
+        &self,
+        name: &str,
+        version: &str,
+        range: Option<Range<u64>>,
+    ) -> Result<Option<CrateBody>> {
+        let key = Self::crate_key(name, version);
+
+        if let Some(cache) = &self.cache {
+            if let Some(krate) = cache.get(&key).await {
+                return Ok(Some(CrateBody::Bytes(Self::slice(krate, range))));
+            }
+
+            let size = self.store.size(&key).await?;
+            if size.is_some_and(|s| s <= MAX_CACHED_CRATE_SIZE) {
+                if let Some(krate) = self.store.get(&key).await? {
+                    cache.insert(key, krate.clone()).await;
+                    return Ok(Some(CrateBody::Bytes(Self::slice(krate, range))));
                 }
-                Some(krate) => Some(krate.to_owned()),
             }
         }
 
+        Ok(self.store.stream(&key, range).await?.map(CrateBody::Stream))
+    }
+
+    async fn cached(&self, key: &str) -> Option<Bytes> {
         match &self.cache {
-            None => {
-                let mut file = File::open(&file_path).await.ok()?;
-                let mut krate = Vec::new();
-                file.read_to_end(&mut krate).await.ok()?;
-                Some(krate)
+            Some(cache) => cache.get(key).await,
+            None => None,
+        }
+    }
+
+    fn slice(krate: Bytes, range: Option<Range<u64>>) -> Bytes {
+        match range {
+            Some(range) => {
+                let len = krate.len();
+                let start = (range.start as usize).min(len);
+                let end = (range.end as usize).clamp(start, len);
+                krate.slice(start..end)
             }
-            Some(c) => from_cache(c, file_path).await,
+            None => krate,
+        }
+    }
+
+    pub async fn delete(&self, name: &str, version: &str) -> Result<()> {
+        let key = Self::crate_key(name, version);
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&key).await;
+        }
+        let size = self.store.size(&key).await?;
+        self.store.delete(&key).await?;
+        self.release(size.unwrap_or_default());
+        Ok(())
+    }
+
+    /// Keys of all crate files in the storage. Quarantined files are not included.
+    pub async fn crate_keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .store
+            .list()
+            .await?
+            .into_iter()
+            .map(|f| f.key)
+            .filter(|k| k.ends_with(".crate"))
+            .collect())
+    }
+
+    /// Computes the sha256 checksum of a stored crate file without loading it into memory.
+    pub async fn sha256(&self, key: &str) -> Result<Option<String>> {
+        let Some(mut stream) = self.store.stream(key, None).await? else {
+            return Ok(None);
+        };
+
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = stream.next().await {
+            hasher.update(chunk?);
+        }
+        Ok(Some(hasher.finalize().encode_hex()))
+    }
+
+    /// Moves a crate file out of the way, such that it is not served anymore.
+    /// The file is kept under the key with the [`QUARANTINE_SUFFIX`] for inspection.
+    pub async fn quarantine(&self, key: &str) -> Result<()> {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(key).await;
         }
+        if let Some(data) = self.store.get(key).await? {
+            self.store
+                .put(&format!("{key}{QUARANTINE_SUFFIX}"), data)
+                .await?;
+        }
+        // The quarantined copy still uses disk space, so the usage is unchanged.
+        self.store.delete(key).await
     }
 
     pub async fn create_rand_doc_queue_path(&self) -> Result<PathBuf> {
@@ -177,3 +337,172 @@ impl CachedCrateStorage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_crate_store::ObjectCrateStore;
+    use object_store::memory::InMemory;
+
+    fn test_storage() -> CachedCrateStorage {
+        let store = Arc::new(ObjectCrateStore::new(Arc::new(InMemory::new()), "crates"));
+        CachedCrateStorage::with_store(store, &Settings::default())
+    }
+
+    #[tokio::test]
+    async fn add_and_get_bin_package_from_object_store() {
+        let storage = test_storage();
+        let name = OriginalName::from_unchecked_str("test".to_string());
+        let version = Version::from_unchecked_str("0.1.0");
+
+        let cksum = storage
+            .add_bin_package(&name, &version, &[0x1, 0x2, 0x3])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            "039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81",
+            cksum
+        );
+        assert_eq!(
+            Some(Bytes::from_static(&[0x1, 0x2, 0x3])),
+            storage.get_file("test", "0.1.0").await
+        );
+    }
+
+    #[tokio::test]
+    async fn read_range_from_cache() {
+        let storage = test_storage();
+        let name = OriginalName::from_unchecked_str("test".to_string());
+        let version = Version::from_unchecked_str("0.1.0");
+        storage
+            .add_bin_package(&name, &version, &[0x1, 0x2, 0x3, 0x4])
+            .await
+            .unwrap();
+
+        // The first read fills the cache, the second one is served from it
+        for _ in 0..2 {
+            match storage.read("test", "0.1.0", Some(1..3)).await.unwrap() {
+                Some(CrateBody::Bytes(data)) => assert_eq!(Bytes::from_static(&[0x2, 0x3]), data),
+                _ => panic!("Expected cached crate data"),
+            }
+        }
+        assert_eq!(Some(4), storage.size("test", "0.1.0").await.unwrap());
+        assert!(storage.read("test", "0.2.0", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn quarantine_removes_crate_from_keys() {
+        let storage = test_storage();
+        let name = OriginalName::from_unchecked_str("test".to_string());
+        let version = Version::from_unchecked_str("0.1.0");
+        storage
+            .add_bin_package(&name, &version, &[0x1, 0x2, 0x3])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some("039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81".to_string()),
+            storage.sha256("test-0.1.0.crate").await.unwrap()
+        );
+
+        storage.quarantine("test-0.1.0.crate").await.unwrap();
+
+        assert!(storage.crate_keys().await.unwrap().is_empty());
+        assert!(!storage.exists("test", "0.1.0").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn delete_invalidates_cache() {
+        let storage = test_storage();
+        let name = OriginalName::from_unchecked_str("test".to_string());
+        let version = Version::from_unchecked_str("0.1.0");
+        storage
+            .add_bin_package(&name, &version, &[0x1, 0x2, 0x3])
+            .await
+            .unwrap();
+        // Fill the cache
+        storage.read("test", "0.1.0", None).await.unwrap().unwrap();
+
+        storage.delete("test", "0.1.0").await.unwrap();
+
+        assert_eq!(None, storage.get_file("test", "0.1.0").await);
+        assert!(!storage.exists("test", "0.1.0").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn usage_tracks_added_and_deleted_crates() {
+        let store = Arc::new(ObjectCrateStore::new(Arc::new(InMemory::new()), "crates"));
+        store
+            .put("existing-0.1.0.crate", Bytes::from_static(&[0x1, 0x2]))
+            .await
+            .unwrap();
+        let storage = CachedCrateStorage::with_store(store, &Settings::default());
+        assert_eq!(2, storage.calculate_usage().await.unwrap());
+
+        storage
+            .add_bin_package(
+                &OriginalName::from_unchecked_str("test".to_string()),
+                &Version::from_unchecked_str("0.1.0"),
+                &[0x1, 0x2, 0x3],
+            )
+            .await
+            .unwrap();
+        assert_eq!(5, storage.usage());
+
+        storage.delete("existing", "0.1.0").await.unwrap();
+        assert_eq!(3, storage.usage());
+    }
+
+    #[tokio::test]
+    async fn staged_crate_is_served_after_commit() {
+        let storage = test_storage();
+        let name = OriginalName::from_unchecked_str("test".to_string());
+        let version = Version::from_unchecked_str("0.1.0");
+
+        let staged = storage
+            .stage_bin_package(&name, &version, &[0x1, 0x2, 0x3])
+            .await
+            .unwrap();
+
+        assert!(!storage.exists("test", "0.1.0").await.unwrap());
+        assert!(storage.crate_keys().await.unwrap().is_empty());
+
+        storage.commit_staged(&staged).await.unwrap();
+
+        assert_eq!(
+            "039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81",
+            staged.cksum
+        );
+        assert_eq!(
+            vec!["test-0.1.0.crate"],
+            storage.crate_keys().await.unwrap()
+        );
+        assert_eq!(3, storage.usage());
+    }
+
+    #[tokio::test]
+    async fn discarded_crate_is_removed() {
+        let storage = test_storage();
+        let name = OriginalName::from_unchecked_str("test".to_string());
+        let version = Version::from_unchecked_str("0.1.0");
+        let staged = storage
+            .stage_bin_package(&name, &version, &[0x1, 0x2, 0x3])
+            .await
+            .unwrap();
+        storage
+            .add_bin_package(&name, &version, &[0x4])
+            .await
+            .unwrap();
+
+        // A concurrent publish added the crate in the meantime
+        assert!(storage.commit_staged(&staged).await.is_err());
+        storage.discard_staged(staged).await.unwrap();
+
+        assert_eq!(
+            vec!["test-0.1.0.crate"],
+            storage.crate_keys().await.unwrap()
+        );
+        assert_eq!(1, storage.usage());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::fs_crate_store::FsCrateStore;
+use crate::object_crate_store::ObjectCrateStore;
+use anyhow::Result;
+use axum::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use settings::{Settings, StorageBackend};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub type CrateStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFile {
+    pub key: String,
+    pub size: u64,
+}
+
+/// Backend that persists the raw `.crate` files. Each file is addressed by a flat key,
+/// e.g. `mycrate-1.0.0.crate`.
+#[async_trait]
+pub trait CrateStore: Send + Sync {
+    async fn exists(&self, key: &str) -> Result<bool>;
+    async fn get(&self, key: &str) -> Result<Option<Bytes>>;
+    /// Size of the file in bytes or `None` if the file does not exist.
+    async fn size(&self, key: &str) -> Result<Option<u64>>;
+    /// Streams the whole file or only the given byte range of it.
+    async fn stream(&self, key: &str, range: Option<Range<u64>>) -> Result<Option<CrateStream>>;
+    async fn put(&self, key: &str, data: Bytes) -> Result<()>;
+    async fn delete(&self, key: &str) -> Result<()>;
+    /// Moves a file to a new key. Fails if a file with the new key exists already.
+    async fn rename(&self, from: &str, to: &str) -> Result<()>;
+    /// Keys and sizes of all stored files.
+    async fn list(&self) -> Result<Vec<StoredFile>>;
+}
+
+/// Creates the crate store configured in the `[storage]` settings.
+/// The `folder` is used by the filesystem backend, the `prefix` by the object storage backend.
+pub async fn init_crate_store(
+    settings: &Settings,
+    folder: PathBuf,
+    prefix: &str,
+) -> Result<Arc<dyn CrateStore>> {
+    let store: Arc<dyn CrateStore> = match settings.storage.backend {
+        StorageBackend::Filesystem => Arc::new(FsCrateStore::new(folder).await?),
+        StorageBackend::S3 => Arc::new(ObjectCrateStore::s3(&settings.storage.s3, prefix)?),
+    };
+    Ok(store)
+}
@@ -1,14 +1,38 @@
 use crate::cached_crate_storage::CachedCrateStorage;
 use settings::Settings;
 use std::ops::{Deref, DerefMut};
+use tokio::sync::{Mutex, MutexGuard};
 
-pub struct CratesIoCrateStorage(CachedCrateStorage);
+pub struct CratesIoCrateStorage {
+    storage: CachedCrateStorage,
+    eviction: Mutex<()>,
+}
 
 impl CratesIoCrateStorage {
     pub async fn new(settings: &Settings) -> Result<Self, anyhow::Error> {
-        Ok(Self(
-            CachedCrateStorage::new(settings.crates_io_bin_path(), settings).await?,
-        ))
+        Ok(Self {
+            storage: CachedCrateStorage::new(settings.crates_io_bin_path(), "cratesio", settings)
+                .await?,
+            eviction: Mutex::new(()),
+        })
+    }
+
+    /// Storage of the crates cached from a proxied registry other than crates.io.
+    pub async fn for_upstream(settings: &Settings, upstream: &str) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            storage: CachedCrateStorage::new(
+                settings.upstream_bin_path(upstream),
+                &format!("proxy/{upstream}"),
+                settings,
+            )
+            .await?,
+            eviction: Mutex::new(()),
+        })
+    }
+
+    /// Guard for the eviction of cached crates. Returns `None` if an eviction is already running.
+    pub fn try_lock_eviction(&self) -> Option<MutexGuard<'_, ()>> {
+        self.eviction.try_lock().ok()
     }
 }
 
@@ -16,12 +40,12 @@ impl Deref for CratesIoCrateStorage {
     type Target = CachedCrateStorage;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.storage
     }
 }
 
 impl DerefMut for CratesIoCrateStorage {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.storage
     }
 }
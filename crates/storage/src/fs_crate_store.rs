@@ -0,0 +1,271 @@
+use crate::crate_store::{CrateStore, CrateStream, StoredFile};
+use anyhow::{Context, Result};
+use axum::async_trait;
+use bytes::Bytes;
+use futures::StreamExt;
+use std::io::{ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, DirBuilder, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+pub struct FsCrateStore {
+    root: PathBuf,
+}
+
+impl FsCrateStore {
+    pub async fn new(root: PathBuf) -> Result<Self> {
+        Self::create_root(&root).await?;
+        Ok(Self { root })
+    }
+
+    async fn create_root(root: &Path) -> Result<()> {
+        if !root.exists() {
+            DirBuilder::new()
+                .recursive(true)
+                .create(root)
+                .await
+                .with_context(|| format!("Unable to create bin path: {root:?}"))?;
+        }
+        Ok(())
+    }
+
+    fn file_path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[async_trait]
+impl CrateStore for FsCrateStore {
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.file_path(key).exists())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        let file_path = self.file_path(key);
+        match fs::read(&file_path).await {
+            Ok(data) => Ok(Some(Bytes::from(data))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to read crate file: {file_path:?}")),
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<u64>> {
+        let file_path = self.file_path(key);
+        match fs::metadata(&file_path).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                Err(e).with_context(|| format!("Unable to read crate metadata: {file_path:?}"))
+            }
+        }
+    }
+
+    async fn stream(&self, key: &str, range: Option<Range<u64>>) -> Result<Option<CrateStream>> {
+        let file_path = self.file_path(key);
+        let mut file = match File::open(&file_path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Unable to open crate file: {file_path:?}"))
+            }
+        };
+
+        let stream = match range {
+            Some(range) => {
+                file.seek(SeekFrom::Start(range.start))
+                    .await
+                    .with_context(|| format!("Unable to seek in crate file: {file_path:?}"))?;
+                ReaderStream::new(file.take(range.end - range.start)).boxed()
+            }
+            None => ReaderStream::new(file).boxed(),
+        };
+
+        Ok(Some(stream))
+    }
+
+    async fn put(&self, key: &str, data: Bytes) -> Result<()> {
+        // The folder may have been removed while Kellnr is running.
+        Self::create_root(&self.root).await?;
+
+        let file_path = self.file_path(key);
+        let mut file = File::create(&file_path).await.with_context(|| {
+            format!(
+                "Unable to create file on storage: {}",
+                file_path.to_string_lossy()
+            )
+        })?;
+
+        file.write_all(&data).await.with_context(|| {
+            format!(
+                "Unable to write crate to file: {}",
+                file_path.to_string_lossy()
+            )
+        })?;
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let file_path = self.file_path(key);
+        fs::remove_file(&file_path)
+            .await
+            .with_context(|| format!("Unable to delete crate file: {file_path:?}"))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let from_path = self.file_path(from);
+        let to_path = self.file_path(to);
+        // Creating a hard link fails if the target exists, which makes the move
+        // atomic without overwriting an existing file.
+        fs::hard_link(&from_path, &to_path)
+            .await
+            .with_context(|| format!("Unable to move crate file {from_path:?} to {to_path:?}"))?;
+        fs::remove_file(&from_path)
+            .await
+            .with_context(|| format!("Unable to delete crate file: {from_path:?}"))
+    }
+
+    async fn list(&self) -> Result<Vec<StoredFile>> {
+        let mut files = vec![];
+        let mut entries = match fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(files),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Unable to list crate files: {:?}", self.root))
+            }
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                files.push(StoredFile {
+                    key: entry.file_name().to_string_lossy().to_string(),
+                    size: metadata.len(),
+                });
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::util::generate_rand_string;
+
+    struct TestDir(PathBuf);
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            rm_rf::remove(&self.0).expect("Cannot remove test directory")
+        }
+    }
+
+    #[tokio::test]
+    async fn put_get_delete_roundtrip() {
+        let dir = TestDir(PathBuf::from("/tmp").join(generate_rand_string(10)));
+        let store = FsCrateStore::new(dir.0.join("crates")).await.unwrap();
+
+        store
+            .put("test-0.1.0.crate", Bytes::from_static(&[0x1, 0x2, 0x3]))
+            .await
+            .unwrap();
+
+        assert!(dir.0.join("crates").join("test-0.1.0.crate").exists());
+        assert!(store.exists("test-0.1.0.crate").await.unwrap());
+        assert_eq!(
+            Some(Bytes::from_static(&[0x1, 0x2, 0x3])),
+            store.get("test-0.1.0.crate").await.unwrap()
+        );
+
+        store.delete("test-0.1.0.crate").await.unwrap();
+
+        assert!(!store.exists("test-0.1.0.crate").await.unwrap());
+        assert_eq!(None, store.get("test-0.1.0.crate").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn list_returns_all_keys() {
+        let dir = TestDir(PathBuf::from("/tmp").join(generate_rand_string(10)));
+        let store = FsCrateStore::new(dir.0.clone()).await.unwrap();
+        store
+            .put("a-0.1.0.crate", Bytes::from_static(&[0x1]))
+            .await
+            .unwrap();
+        store
+            .put("b-0.1.0.crate", Bytes::from_static(&[0x2]))
+            .await
+            .unwrap();
+
+        let mut files = store.list().await.unwrap();
+        files.sort_by(|a, b| a.key.cmp(&b.key));
+
+        assert_eq!(
+            vec![
+                StoredFile {
+                    key: "a-0.1.0.crate".to_string(),
+                    size: 1
+                },
+                StoredFile {
+                    key: "b-0.1.0.crate".to_string(),
+                    size: 1
+                },
+            ],
+            files
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_range() {
+        let dir = TestDir(PathBuf::from("/tmp").join(generate_rand_string(10)));
+        let store = FsCrateStore::new(dir.0.clone()).await.unwrap();
+        store
+            .put(
+                "test-0.1.0.crate",
+                Bytes::from_static(&[0x1, 0x2, 0x3, 0x4]),
+            )
+            .await
+            .unwrap();
+
+        let chunks: Vec<Bytes> = store
+            .stream("test-0.1.0.crate", Some(1..3))
+            .await
+            .unwrap()
+            .unwrap()
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(Some(4), store.size("test-0.1.0.crate").await.unwrap());
+        assert_eq!(vec![0x2, 0x3], chunks.concat());
+        assert!(store.stream("missing.crate", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rename_does_not_overwrite() {
+        let dir = TestDir(PathBuf::from("/tmp").join(generate_rand_string(10)));
+        let store = FsCrateStore::new(dir.0.clone()).await.unwrap();
+        store
+            .put("a.tmp", Bytes::from_static(&[0x1]))
+            .await
+            .unwrap();
+        store
+            .put("b.tmp", Bytes::from_static(&[0x2]))
+            .await
+            .unwrap();
+
+        store.rename("a.tmp", "test-0.1.0.crate").await.unwrap();
+
+        assert!(!store.exists("a.tmp").await.unwrap());
+        assert!(store.rename("b.tmp", "test-0.1.0.crate").await.is_err());
+        assert_eq!(
+            Some(Bytes::from_static(&[0x1])),
+            store.get("test-0.1.0.crate").await.unwrap()
+        );
+    }
+}
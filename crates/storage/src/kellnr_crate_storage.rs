@@ -1,20 +1,49 @@
 use crate::cached_crate_storage::CachedCrateStorage;
 use settings::Settings;
+use std::collections::HashSet;
 use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
 
-pub struct KellnrCrateStorage(CachedCrateStorage);
+pub struct KellnrCrateStorage {
+    storage: CachedCrateStorage,
+    publishing: Mutex<HashSet<String>>,
+}
+
+/// Marks a crate version as being published. The mark is removed when the guard is dropped.
+pub struct PublishGuard<'a> {
+    publishing: &'a Mutex<HashSet<String>>,
+    key: String,
+}
+
+impl Drop for PublishGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut publishing) = self.publishing.lock() {
+            publishing.remove(&self.key);
+        }
+    }
+}
 
 impl KellnrCrateStorage {
     pub async fn new(settings: &Settings) -> Result<Self, anyhow::Error> {
-        Ok(Self(
-            CachedCrateStorage::new(settings.bin_path(), settings).await?,
-        ))
+        Ok(Self {
+            storage: CachedCrateStorage::new(settings.bin_path(), "crates", settings).await?,
+            publishing: Mutex::new(HashSet::new()),
+        })
     }
 
-    pub async fn delete(&self, crate_name: &str, crate_version: &str) -> Result<(), anyhow::Error> {
-        let path = self.0.crate_path(crate_name, crate_version);
-        tokio::fs::remove_file(path).await?;
-        Ok(())
+    /// Marks the crate version as being published. Returns `None` if the same
+    /// version is published concurrently.
+    pub fn try_lock_publish(&self, name: &str, version: &str) -> Option<PublishGuard<'_>> {
+        let key = CachedCrateStorage::crate_key(name, version);
+        let mut publishing = self.publishing.lock().ok()?;
+        if !publishing.insert(key.clone()) {
+            return None;
+        }
+
+        Some(PublishGuard {
+            publishing: &self.publishing,
+            key,
+        })
     }
 }
 
@@ -22,12 +51,12 @@ impl Deref for KellnrCrateStorage {
     type Target = CachedCrateStorage;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.storage
     }
 }
 
 impl DerefMut for KellnrCrateStorage {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.storage
     }
 }
@@ -1,5 +1,9 @@
 pub mod cached_crate_storage;
+pub mod crate_store;
 pub mod cratesio_crate_storage;
+pub mod fs_crate_store;
 pub mod kellnr_crate_storage;
+pub mod object_crate_store;
+pub mod scrub;
 pub mod storage;
 pub mod storage_provider;
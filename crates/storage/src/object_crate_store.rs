@@ -0,0 +1,259 @@
+use crate::crate_store::{CrateStore, CrateStream, StoredFile};
+use anyhow::{bail, Context, Result};
+use axum::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{GetOptions, GetRange, ObjectStore};
+use settings::storage::S3;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Crate store backed by an object storage, e.g. AWS S3 or an S3 compatible service like MinIO.
+pub struct ObjectCrateStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+}
+
+impl ObjectCrateStore {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str) -> Self {
+        Self {
+            store,
+            prefix: ObjectPath::from(prefix),
+        }
+    }
+
+    pub fn s3(s3: &S3, prefix: &str) -> Result<Self> {
+        let mut builder = AmazonS3Builder::new()
+            .with_region(&s3.region)
+            .with_bucket_name(&s3.bucket)
+            .with_allow_http(s3.allow_http);
+
+        if !s3.endpoint.is_empty() {
+            builder = builder.with_endpoint(&s3.endpoint);
+        }
+        // Without explicit keys, the credentials are taken from the environment
+        // or the instance metadata.
+        if !s3.access_key.is_empty() {
+            builder = builder
+                .with_access_key_id(&s3.access_key)
+                .with_secret_access_key(&s3.secret_key);
+        }
+
+        let store = builder
+            .build()
+            .with_context(|| format!("Unable to create S3 client for bucket {}", s3.bucket))?;
+
+        Ok(Self::new(Arc::new(store), prefix))
+    }
+
+    fn location(&self, key: &str) -> ObjectPath {
+        self.prefix.child(key)
+    }
+}
+
+#[async_trait]
+impl CrateStore for ObjectCrateStore {
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self.store.head(&self.location(key)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("Unable to check crate object: {key}")),
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        match self.store.get(&self.location(key)).await {
+            Ok(result) => {
+                Ok(Some(result.bytes().await.with_context(|| {
+                    format!("Unable to read crate object: {key}")
+                })?))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to get crate object: {key}")),
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<u64>> {
+        match self.store.head(&self.location(key)).await {
+            Ok(meta) => Ok(Some(meta.size as u64)),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to check crate object: {key}")),
+        }
+    }
+
+    async fn stream(&self, key: &str, range: Option<Range<u64>>) -> Result<Option<CrateStream>> {
+        let options = GetOptions {
+            range: range.map(|r| GetRange::from(r.start as usize..r.end as usize)),
+            ..GetOptions::default()
+        };
+
+        match self.store.get_opts(&self.location(key), options).await {
+            Ok(result) => Ok(Some(
+                result.into_stream().map_err(std::io::Error::from).boxed(),
+            )),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to get crate object: {key}")),
+        }
+    }
+
+    async fn put(&self, key: &str, data: Bytes) -> Result<()> {
+        self.store
+            .put(&self.location(key), data)
+            .await
+            .with_context(|| format!("Unable to write crate object: {key}"))?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.store
+            .delete(&self.location(key))
+            .await
+            .with_context(|| format!("Unable to delete crate object: {key}"))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let (from_location, to_location) = (self.location(from), self.location(to));
+        match self
+            .store
+            .rename_if_not_exists(&from_location, &to_location)
+            .await
+        {
+            Ok(()) => Ok(()),
+            // Plain S3 does not support a conditional copy. Fall back to a check,
+            // which is not atomic but sufficient for a single Kellnr instance.
+            Err(object_store::Error::NotSupported { .. }) => {
+                if self.exists(to).await? {
+                    bail!("Crate object exists already: {to}");
+                }
+                self.store
+                    .rename(&from_location, &to_location)
+                    .await
+                    .with_context(|| format!("Unable to move crate object {from} to {to}"))
+            }
+            Err(e) => Err(e).with_context(|| format!("Unable to move crate object {from} to {to}")),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<StoredFile>> {
+        let objects: Vec<_> = self
+            .store
+            .list(Some(&self.prefix))
+            .try_collect()
+            .await
+            .with_context(|| format!("Unable to list crate objects: {}", self.prefix))?;
+
+        Ok(objects
+            .into_iter()
+            .filter_map(|o| {
+                o.location.filename().map(|key| StoredFile {
+                    key: key.to_string(),
+                    size: o.size as u64,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn put_get_delete_roundtrip() {
+        let memory = Arc::new(InMemory::new());
+        let store = ObjectCrateStore::new(memory.clone(), "crates");
+
+        store
+            .put("test-0.1.0.crate", Bytes::from_static(&[0x1, 0x2, 0x3]))
+            .await
+            .unwrap();
+
+        // Objects are stored below the prefix
+        assert!(memory
+            .head(&ObjectPath::from("crates/test-0.1.0.crate"))
+            .await
+            .is_ok());
+        assert!(store.exists("test-0.1.0.crate").await.unwrap());
+        assert_eq!(
+            Some(Bytes::from_static(&[0x1, 0x2, 0x3])),
+            store.get("test-0.1.0.crate").await.unwrap()
+        );
+
+        store.delete("test-0.1.0.crate").await.unwrap();
+
+        assert!(!store.exists("test-0.1.0.crate").await.unwrap());
+        assert_eq!(None, store.get("test-0.1.0.crate").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn prefixes_are_separated() {
+        let memory = Arc::new(InMemory::new());
+        let kellnr = ObjectCrateStore::new(memory.clone(), "crates");
+        let cratesio = ObjectCrateStore::new(memory, "cratesio");
+
+        kellnr
+            .put("test-0.1.0.crate", Bytes::from_static(&[0x1]))
+            .await
+            .unwrap();
+
+        assert!(!cratesio.exists("test-0.1.0.crate").await.unwrap());
+        assert_eq!(
+            vec![StoredFile {
+                key: "test-0.1.0.crate".to_string(),
+                size: 1
+            }],
+            kellnr.list().await.unwrap()
+        );
+        assert!(cratesio.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stream_range() {
+        let store = ObjectCrateStore::new(Arc::new(InMemory::new()), "crates");
+        store
+            .put(
+                "test-0.1.0.crate",
+                Bytes::from_static(&[0x1, 0x2, 0x3, 0x4]),
+            )
+            .await
+            .unwrap();
+
+        let chunks: Vec<Bytes> = store
+            .stream("test-0.1.0.crate", Some(1..3))
+            .await
+            .unwrap()
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(Some(4), store.size("test-0.1.0.crate").await.unwrap());
+        assert_eq!(vec![0x2, 0x3], chunks.concat());
+        assert!(store.stream("missing.crate", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rename_does_not_overwrite() {
+        let store = ObjectCrateStore::new(Arc::new(InMemory::new()), "crates");
+        store
+            .put("a.tmp", Bytes::from_static(&[0x1]))
+            .await
+            .unwrap();
+        store
+            .put("b.tmp", Bytes::from_static(&[0x2]))
+            .await
+            .unwrap();
+
+        store.rename("a.tmp", "test-0.1.0.crate").await.unwrap();
+
+        assert!(!store.exists("a.tmp").await.unwrap());
+        assert!(store.rename("b.tmp", "test-0.1.0.crate").await.is_err());
+        assert_eq!(
+            Some(Bytes::from_static(&[0x1])),
+            store.get("test-0.1.0.crate").await.unwrap()
+        );
+    }
+}
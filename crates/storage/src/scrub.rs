@@ -0,0 +1,192 @@
+use crate::cached_crate_storage::CachedCrateStorage;
+use common::indexed_crate::IndexedCrate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use tracing::{error, warn};
+
+/// Findings of a scrub run over a single crate storage. All entries are storage keys,
+/// e.g. `mycrate-1.0.0.crate`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubResult {
+    pub checked: usize,
+    /// Indexed crate versions without a crate file.
+    pub missing: Vec<String>,
+    /// Crate files without an indexed crate version.
+    pub orphaned: Vec<String>,
+    /// Crate files whose checksum does not match the index.
+    pub corrupted: Vec<String>,
+    /// Corrupted crate files that were moved out of the way.
+    pub quarantined: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubReport {
+    pub started: String,
+    pub finished: String,
+    pub kellnr: ScrubResult,
+    pub cratesio: ScrubResult,
+}
+
+/// Verifies every indexed crate version against the files in the storage.
+/// If `quarantine` is set, corrupted files are moved out of the way.
+pub async fn scrub_storage(
+    storage: &CachedCrateStorage,
+    indexed: &[IndexedCrate],
+    quarantine: bool,
+) -> ScrubResult {
+    let mut result = ScrubResult::default();
+
+    let stored: HashSet<String> = match storage.crate_keys().await {
+        Ok(keys) => keys.into_iter().collect(),
+        Err(e) => {
+            error!("Unable to list crate files: {}", e);
+            result
+                .errors
+                .push(format!("Unable to list crate files: {e}"));
+            return result;
+        }
+    };
+
+    let mut expected = HashSet::with_capacity(indexed.len());
+    for krate in indexed {
+        let key = CachedCrateStorage::crate_key(&krate.name, &krate.version);
+        expected.insert(key.clone());
+        result.checked += 1;
+
+        if !stored.contains(&key) {
+            result.missing.push(key);
+            continue;
+        }
+
+        match storage.sha256(&key).await {
+            Ok(Some(cksum)) if cksum == krate.cksum => (),
+            Ok(Some(_)) => {
+                warn!("Checksum mismatch for crate file: {}", key);
+                if quarantine {
+                    match storage.quarantine(&key).await {
+                        Ok(()) => result.quarantined.push(key.clone()),
+                        Err(e) => result
+                            .errors
+                            .push(format!("Unable to quarantine {key}: {e}")),
+                    }
+                }
+                result.corrupted.push(key);
+            }
+            Ok(None) => result.missing.push(key),
+            Err(e) => result.errors.push(format!("Unable to read {key}: {e}")),
+        }
+    }
+
+    result.orphaned = stored.difference(&expected).cloned().collect();
+    result.orphaned.sort();
+
+    result
+}
+
+/// Keeps track of the running scrub job and the report of the last finished run.
+#[derive(Default)]
+pub struct Scrubber {
+    running: AtomicBool,
+    last_report: RwLock<Option<ScrubReport>>,
+}
+
+impl Scrubber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks a scrub run as started. Returns `false` if a run is already in progress.
+    pub fn try_start(&self) -> bool {
+        self.running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    pub fn finish(&self, report: ScrubReport) {
+        if let Ok(mut last_report) = self.last_report.write() {
+            *last_report = Some(report);
+        }
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn last_report(&self) -> Option<ScrubReport> {
+        self.last_report.read().ok().and_then(|r| r.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_crate_store::ObjectCrateStore;
+    use common::original_name::OriginalName;
+    use common::version::Version;
+    use object_store::memory::InMemory;
+    use settings::Settings;
+    use std::sync::Arc;
+
+    async fn add(storage: &CachedCrateStorage, name: &str, version: &str, data: &[u8]) -> String {
+        storage
+            .add_bin_package(
+                &OriginalName::from_unchecked_str(name.to_string()),
+                &Version::from_unchecked_str(version),
+                data,
+            )
+            .await
+            .unwrap()
+    }
+
+    fn indexed(name: &str, version: &str, cksum: &str) -> IndexedCrate {
+        IndexedCrate {
+            name: name.to_string(),
+            version: version.to_string(),
+            cksum: cksum.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn scrub_finds_missing_orphaned_and_corrupted_crates() {
+        let store = Arc::new(ObjectCrateStore::new(Arc::new(InMemory::new()), "cratesio"));
+        let storage = CachedCrateStorage::with_store(store, &Settings::default());
+        let valid_cksum = add(&storage, "valid", "1.0.0", &[0x1]).await;
+        add(&storage, "corrupted", "1.0.0", &[0x2]).await;
+        add(&storage, "orphaned", "1.0.0", &[0x3]).await;
+
+        let result = scrub_storage(
+            &storage,
+            &[
+                indexed("valid", "1.0.0", &valid_cksum),
+                indexed("corrupted", "1.0.0", "wrong"),
+                indexed("missing", "1.0.0", "cksum"),
+            ],
+            true,
+        )
+        .await;
+
+        assert_eq!(3, result.checked);
+        assert_eq!(vec!["missing-1.0.0.crate"], result.missing);
+        assert_eq!(vec!["orphaned-1.0.0.crate"], result.orphaned);
+        assert_eq!(vec!["corrupted-1.0.0.crate"], result.corrupted);
+        assert_eq!(vec!["corrupted-1.0.0.crate"], result.quarantined);
+        assert!(!storage.exists("corrupted", "1.0.0").await.unwrap());
+    }
+
+    #[test]
+    fn scrubber_allows_only_one_run() {
+        let scrubber = Scrubber::new();
+
+        assert!(scrubber.try_start());
+        assert!(!scrubber.try_start());
+
+        scrubber.finish(ScrubReport::default());
+
+        assert!(!scrubber.is_running());
+        assert_eq!(Some(ScrubReport::default()), scrubber.last_report());
+    }
+}
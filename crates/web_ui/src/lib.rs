@@ -1,15 +1,61 @@
 pub mod error;
+pub mod proxy_cache;
+pub mod proxy_mode;
+pub mod proxy_policy;
+pub mod proxy_queue;
+pub mod scrub;
+pub mod search_index;
+pub mod security_events;
 pub mod session;
 pub mod ui;
 pub mod user;
 
 #[cfg(test)]
 mod test_helper {
+    use appstate::AppStateData;
+    use axum::body::Body;
+    use axum_extra::extract::cookie::Key;
     use cookie::{Cookie, CookieJar};
+    use db::mock::MockDb;
+    use hyper::{header, Request};
+    use settings::constants;
     use std::borrow::Cow;
+    use std::sync::Arc;
 
     pub(crate) const TEST_KEY: &[u8] = &[1; 64];
 
+    /// Database mock with a valid session of an admin or a regular user.
+    pub(crate) fn session_db(is_admin: bool) -> MockDb {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_validate_session()
+            .returning(move |_| Ok(("admin".to_string(), is_admin)));
+        mock_db
+    }
+
+    /// Test state with the database and the key of the session cookie of `session_request`.
+    pub(crate) async fn session_state(mock_db: MockDb) -> AppStateData {
+        AppStateData {
+            db: Arc::new(mock_db),
+            signing_key: Key::from(TEST_KEY),
+            ..appstate::test_state().await
+        }
+    }
+
+    /// Request with a session cookie and a JSON body.
+    pub(crate) fn session_request(method: &str, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(
+                header::COOKIE,
+                encode_cookies([(constants::COOKIE_SESSION_ID, "cookie")]),
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
     // there has to be a better way to set cookies, i really don't like importing cookie crate just to do this
     pub(crate) fn encode_cookies<
         const N: usize,
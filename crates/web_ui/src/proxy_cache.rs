@@ -0,0 +1,707 @@
+use crate::error::RouteError;
+use crate::session::MaybeUser;
+use appstate::{AppState, AppStateData, ProxyPolicies};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use common::cached_crate::CachedCrate;
+use common::normalized_name::NormalizedName;
+use common::original_name::OriginalName;
+use common::version::Version;
+use db::error::DbError;
+use db::DbProvider;
+use index::cratesio_prefetch_api::refresh_crate_index;
+use registry::cratesio_api::cache_crate;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use settings::proxy::{crate_download_url, Proxy, CRATESIO_UPSTREAM};
+use std::sync::Arc;
+use storage::cratesio_crate_storage::CratesIoCrateStorage;
+use tokio::task::JoinSet;
+use tracing::{error, info};
+
+const DEFAULT_PAGE_SIZE: u64 = 10;
+const MAX_PAGE_SIZE: u64 = 100;
+const WARM_CONCURRENCY: usize = 8; // Crates of a lockfile that are fetched concurrently
+const CRATESIO_SOURCES: [&str; 2] = [
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheListParams {
+    page: Option<u64>,
+    page_size: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PinUpdate {
+    pub pinned: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WarmParams {
+    pin: Option<bool>,
+}
+
+/// Crates of a lockfile grouped by the outcome of warming the cache with them.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct WarmReport {
+    pub cached: Vec<LockedCrate>,
+    pub existed: Vec<LockedCrate>,
+    pub failed: Vec<FailedCrate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockedCrate {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FailedCrate {
+    pub name: String,
+    pub version: String,
+    pub error: String,
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockfilePackage>,
+}
+
+#[derive(Deserialize, Clone)]
+struct LockfilePackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+    checksum: Option<String>,
+}
+
+/// Database, storage and index of the proxy cache of an upstream.
+#[derive(Clone)]
+struct ProxyCache {
+    db: Arc<dyn DbProvider>,
+    policies: Arc<ProxyPolicies>,
+    storage: Arc<CratesIoCrateStorage>,
+    index_url: String,
+    download_url: String,
+}
+
+fn proxy_cache(state: &AppStateData, upstream: &str) -> Result<ProxyCache, RouteError> {
+    if upstream == CRATESIO_UPSTREAM {
+        return Ok(ProxyCache {
+            db: state.db.clone(),
+            policies: state.proxy_policies.clone(),
+            storage: state.cratesio_storage.clone(),
+            index_url: state.settings.proxy.index_url.clone(),
+            download_url: state.settings.proxy.download_url.clone(),
+        });
+    }
+
+    let upstream = state
+        .upstreams
+        .get(upstream)
+        .ok_or(RouteError::Status(StatusCode::NOT_FOUND))?;
+    Ok(ProxyCache {
+        db: upstream.db.clone(),
+        policies: state.proxy_policies.clone(),
+        storage: upstream.storage.clone(),
+        index_url: upstream.settings.index_url.clone(),
+        download_url: upstream.settings.download_url.clone(),
+    })
+}
+
+/// Lists the cached crates of an upstream with their cached versions, ordered by name.
+/// Pages are numbered from 0, unlike in the cargo search API, and have at most 100 crates.
+pub async fn list(
+    user: MaybeUser,
+    Path(upstream): Path<String>,
+    Query(params): Query<CacheListParams>,
+    State(state): AppState,
+) -> Result<Json<Vec<CachedCrate>>, RouteError> {
+    user.assert_admin()?;
+
+    let cache = proxy_cache(&state, &upstream)?;
+    let page_size = params
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .min(MAX_PAGE_SIZE);
+    let offset = params.page.unwrap_or(0).saturating_mul(page_size);
+    let mut crates = cache
+        .db
+        .get_cached_cratesio_crates(offset, page_size)
+        .await?;
+    for krate in &mut crates {
+        add_file_sizes(&cache.storage, krate).await;
+    }
+
+    Ok(Json(crates))
+}
+
+/// Returns all versions of a cached crate, including the ones whose file is not cached.
+pub async fn inspect(
+    user: MaybeUser,
+    Path((upstream, name)): Path<(String, OriginalName)>,
+    State(state): AppState,
+) -> Result<Json<CachedCrate>, RouteError> {
+    user.assert_admin()?;
+
+    let cache = proxy_cache(&state, &upstream)?;
+    let mut krate = cache
+        .db
+        .get_cached_cratesio_crate(&name.to_normalized())
+        .await?
+        .ok_or(RouteError::Status(StatusCode::NOT_FOUND))?;
+    add_file_sizes(&cache.storage, &mut krate).await;
+
+    Ok(Json(krate))
+}
+
+/// Removes a crate with its index and all cached files. The crate is fetched from the
+/// upstream again on the next request.
+pub async fn purge(
+    user: MaybeUser,
+    Path((upstream, name)): Path<(String, OriginalName)>,
+    State(state): AppState,
+) -> Result<(), RouteError> {
+    user.assert_admin()?;
+
+    let cache = proxy_cache(&state, &upstream)?;
+    let versions = cache
+        .db
+        .delete_cratesio_crate(&name.to_normalized())
+        .await
+        .map_err(cache_error)?;
+    for version in versions {
+        delete_file(&cache.storage, &name, &version).await?;
+    }
+    info!(
+        "Crate {} purged from the {} proxy cache by {}",
+        name,
+        upstream,
+        user.name()
+    );
+
+    Ok(())
+}
+
+/// Removes the cached file of a crate version. The index entry is kept, such that the
+/// version is downloaded from the upstream again on the next request.
+pub async fn purge_version(
+    user: MaybeUser,
+    Path((upstream, name, version)): Path<(String, OriginalName, Version)>,
+    State(state): AppState,
+) -> Result<(), RouteError> {
+    user.assert_admin()?;
+
+    let cache = proxy_cache(&state, &upstream)?;
+    cache
+        .db
+        .clear_cratesio_last_access(&name.to_normalized(), &version)
+        .await
+        .map_err(cache_error)?;
+    delete_file(&cache.storage, &name, &version).await?;
+    info!(
+        "Crate {} ({}) purged from the {} proxy cache by {}",
+        name,
+        version,
+        upstream,
+        user.name()
+    );
+
+    Ok(())
+}
+
+/// Fetches the index of a crate from the upstream immediately.
+pub async fn refresh(
+    user: MaybeUser,
+    Path((upstream, name)): Path<(String, OriginalName)>,
+    State(state): AppState,
+) -> Result<(), RouteError> {
+    user.assert_admin()?;
+
+    let cache = proxy_cache(&state, &upstream)?;
+    if state.proxy_mode.is_offline() {
+        return Err(RouteError::Status(StatusCode::SERVICE_UNAVAILABLE));
+    }
+    refresh_crate_index(&cache.db, &state.http_client, &cache.index_url, &name)
+        .await
+        .map_err(|e| {
+            error!("Could not refresh index of {}: {}", name, e);
+            RouteError::Status(StatusCode::BAD_GATEWAY)
+        })?;
+    info!(
+        "Index of crate {} refreshed from {} by {}",
+        name,
+        upstream,
+        user.name()
+    );
+
+    Ok(())
+}
+
+/// Pins or unpins a crate. Pinned crates are never evicted from the cache.
+pub async fn pin(
+    user: MaybeUser,
+    Path((upstream, name)): Path<(String, OriginalName)>,
+    State(state): AppState,
+    Json(update): Json<PinUpdate>,
+) -> Result<(), RouteError> {
+    user.assert_admin()?;
+
+    let cache = proxy_cache(&state, &upstream)?;
+    cache
+        .db
+        .set_cratesio_crate_pinned(&name.to_normalized(), update.pinned)
+        .await
+        .map_err(cache_error)?;
+    info!(
+        "Crate {} in the {} proxy cache {} by {}",
+        name,
+        upstream,
+        if update.pinned { "pinned" } else { "unpinned" },
+        user.name()
+    );
+
+    Ok(())
+}
+
+/// Fetches the index entries and crate files of all packages of the upstream in a
+/// `Cargo.lock`, such that they can be built while the proxy is offline. With `pin`,
+/// the crates are pinned against eviction.
+pub async fn warm(
+    user: MaybeUser,
+    Path(upstream): Path<String>,
+    Query(params): Query<WarmParams>,
+    State(state): AppState,
+    lockfile: String,
+) -> Result<Json<WarmReport>, RouteError> {
+    user.assert_admin()?;
+
+    let cache = proxy_cache(&state, &upstream)?;
+    if state.proxy_mode.is_offline() {
+        return Err(RouteError::Status(StatusCode::SERVICE_UNAVAILABLE));
+    }
+    let packages = locked_packages(&lockfile, &upstream, &cache.index_url)?;
+    let pin = params.pin.unwrap_or(false);
+
+    let mut report = WarmReport::default();
+    for batch in packages.chunks(WARM_CONCURRENCY) {
+        let mut warms = JoinSet::new();
+        for package in batch {
+            let cache = cache.clone();
+            let client = state.http_client.clone();
+            let settings = state.settings.clone();
+            let package = package.clone();
+            warms.spawn(async move {
+                let locked = LockedCrate {
+                    name: package.name.clone(),
+                    version: package.version.clone(),
+                };
+                let result = warm_crate(&cache, &client, &settings.proxy, &package, pin).await;
+                (locked, result)
+            });
+        }
+
+        while let Some(warmed) = warms.join_next().await {
+            let (locked, result) = warmed.map_err(|e| {
+                error!("Cache warming task failed: {}", e);
+                RouteError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+            })?;
+            match result {
+                Ok(true) => report.cached.push(locked),
+                Ok(false) => report.existed.push(locked),
+                Err(error) => report.failed.push(FailedCrate {
+                    name: locked.name,
+                    version: locked.version,
+                    error,
+                }),
+            }
+        }
+    }
+    info!(
+        "Proxy cache of {} warmed from a lockfile by {}: {} cached, {} existed, {} failed",
+        upstream,
+        user.name(),
+        report.cached.len(),
+        report.existed.len(),
+        report.failed.len()
+    );
+
+    Ok(Json(report))
+}
+
+/// Caches the index entry and file of a locked package. Returns `false` if the file
+/// was cached already.
+async fn warm_crate(
+    cache: &ProxyCache,
+    client: &Client,
+    proxy_settings: &Proxy,
+    package: &LockfilePackage,
+    pin: bool,
+) -> Result<bool, String> {
+    let name = OriginalName::try_from(&package.name).map_err(|e| e.to_string())?;
+    let version = Version::try_from(&package.version).map_err(|e| e.to_string())?;
+    let normalized_name = name.to_normalized();
+
+    let cksum = match index_cksum(cache, &normalized_name, &version).await? {
+        Some(cksum) => cksum,
+        None => {
+            refresh_crate_index(&cache.db, client, &cache.index_url, &name).await?;
+            index_cksum(cache, &normalized_name, &version)
+                .await?
+                .ok_or("Version not found in the upstream index")?
+        }
+    };
+    if package.checksum.as_ref().is_some_and(|c| *c != cksum) {
+        return Err(format!(
+            "Checksum {} of Cargo.lock does not match the index checksum {}",
+            package.checksum.as_deref().unwrap_or_default(),
+            cksum
+        ));
+    }
+
+    let target = crate_download_url(&cache.download_url, &name, &version);
+    let cached = cache_crate(
+        client,
+        &target,
+        &name,
+        &version,
+        &cache.storage,
+        &cache.db,
+        &cache.policies,
+        proxy_settings,
+    )
+    .await
+    .map_err(|status| format!("Could not cache crate: {}", status))?;
+
+    if pin {
+        cache
+            .db
+            .set_cratesio_crate_pinned(&normalized_name, true)
+            .await
+            .map_err(|e| format!("Could not pin crate: {}", e))?;
+    }
+    Ok(cached)
+}
+
+async fn index_cksum(
+    cache: &ProxyCache,
+    name: &NormalizedName,
+    version: &Version,
+) -> Result<Option<String>, String> {
+    cache
+        .db
+        .get_cratesio_crate_cksum(name, version)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Packages of a `Cargo.lock` that are from the registry of the upstream.
+fn locked_packages(
+    lockfile: &str,
+    upstream: &str,
+    index_url: &str,
+) -> Result<Vec<LockfilePackage>, RouteError> {
+    let lockfile = toml::from_str::<Lockfile>(lockfile)
+        .map_err(|_| RouteError::Status(StatusCode::BAD_REQUEST))?;
+
+    Ok(lockfile
+        .package
+        .into_iter()
+        .filter(|p| {
+            p.source
+                .as_deref()
+                .is_some_and(|s| is_upstream_source(s, upstream, index_url))
+        })
+        .collect())
+}
+
+/// Cargo records crates.io as `registry+https://github.com/rust-lang/crates.io-index`
+/// independent of the protocol, other registries with the URL of their index.
+fn is_upstream_source(source: &str, upstream: &str, index_url: &str) -> bool {
+    if upstream == CRATESIO_UPSTREAM && CRATESIO_SOURCES.contains(&source) {
+        return true;
+    }
+
+    source
+        .strip_prefix("sparse+")
+        .or_else(|| source.strip_prefix("registry+"))
+        .is_some_and(|url| url.trim_end_matches('/') == index_url.trim_end_matches('/'))
+}
+
+async fn add_file_sizes(storage: &CratesIoCrateStorage, krate: &mut CachedCrate) {
+    for version in &mut krate.versions {
+        version.size = storage
+            .size(&krate.name, &version.version)
+            .await
+            .unwrap_or_else(|e| {
+                error!(
+                    "Could not get size of cached crate {} ({}): {}",
+                    krate.name, version.version, e
+                );
+                None
+            });
+    }
+}
+
+async fn delete_file(
+    storage: &CratesIoCrateStorage,
+    name: &OriginalName,
+    version: &Version,
+) -> Result<(), RouteError> {
+    let exists = storage.exists(name, version).await.map_err(|e| {
+        error!("Could not check cached crate {} ({}): {}", name, version, e);
+        RouteError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+    if exists {
+        storage.delete(name, version).await.map_err(|e| {
+            error!(
+                "Could not delete cached crate {} ({}): {}",
+                name, version, e
+            );
+            RouteError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+    }
+    Ok(())
+}
+
+fn cache_error(e: DbError) -> RouteError {
+    match e {
+        DbError::CrateNotFound(_) => RouteError::Status(StatusCode::NOT_FOUND),
+        e => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helper::{session_db, session_request, session_state};
+    use appstate::ProxyMode;
+    use axum::routing::{get, post, put};
+    use axum::Router;
+    use common::cached_crate::CachedVersion;
+    use common::normalized_name::NormalizedName;
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use tower::ServiceExt;
+
+    async fn app(is_admin: bool) -> Router {
+        let mut mock_db = session_db(is_admin);
+        mock_db
+            .expect_get_cached_cratesio_crates()
+            .with(eq(10), eq(5))
+            .returning(|_, _| {
+                Ok(vec![CachedCrate {
+                    name: "serde".to_string(),
+                    pinned: false,
+                    total_downloads: 3,
+                    versions: vec![CachedVersion {
+                        version: "1.0.0".to_string(),
+                        downloads: 3,
+                        last_access: Some("2024-01-02 03:04:05".to_string()),
+                        size: None,
+                    }],
+                }])
+            });
+        mock_db
+            .expect_get_cached_cratesio_crates()
+            .with(eq(u64::MAX), eq(MAX_PAGE_SIZE))
+            .returning(|_, _| Ok(Vec::new()));
+        mock_db
+            .expect_get_cached_cratesio_crate()
+            .returning(|_| Ok(None));
+        mock_db
+            .expect_set_cratesio_crate_pinned()
+            .with(eq(NormalizedName::from_unchecked_str("serde")), eq(true))
+            .returning(|_, _| Ok(()));
+        mock_db
+            .expect_delete_cratesio_crate()
+            .returning(|name| Err(DbError::CrateNotFound(name.to_string())));
+
+        Router::new()
+            .route("/proxy/cache/:upstream", get(list))
+            .route("/proxy/cache/:upstream/warm", post(warm))
+            .route("/proxy/cache/:upstream/:name", get(inspect).delete(purge))
+            .route("/proxy/cache/:upstream/:name/refresh", post(refresh))
+            .route("/proxy/cache/:upstream/:name/pin", put(pin))
+            .with_state(AppStateData {
+                proxy_mode: Arc::new(ProxyMode::new(true)),
+                ..session_state(mock_db).await
+            })
+    }
+
+    #[tokio::test]
+    async fn list_requires_admin() {
+        let r = app(false)
+            .await
+            .oneshot(session_request("GET", "/proxy/cache/crates-io", ""))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, r.status());
+    }
+
+    #[tokio::test]
+    async fn list_returns_page_of_cached_crates() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "GET",
+                "/proxy/cache/crates-io?page=2&page_size=5",
+                "",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let crates = serde_json::from_slice::<Vec<CachedCrate>>(&body).unwrap();
+        assert_eq!("serde", crates[0].name);
+        // The file is not in the test storage.
+        assert_eq!(None, crates[0].versions[0].size);
+    }
+
+    #[tokio::test]
+    async fn list_clamps_page_size_and_offset() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "GET",
+                &format!("/proxy/cache/crates-io?page={}&page_size=1000", u64::MAX),
+                "",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+    }
+
+    #[tokio::test]
+    async fn unknown_upstream_returns_not_found() {
+        let r = app(true)
+            .await
+            .oneshot(session_request("GET", "/proxy/cache/unknown", ""))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, r.status());
+    }
+
+    #[tokio::test]
+    async fn inspect_and_purge_unknown_crate_return_not_found() {
+        let inspect = app(true)
+            .await
+            .oneshot(session_request("GET", "/proxy/cache/crates-io/serde", ""))
+            .await
+            .unwrap();
+        let purge = app(true)
+            .await
+            .oneshot(session_request(
+                "DELETE",
+                "/proxy/cache/crates-io/serde",
+                "",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, inspect.status());
+        assert_eq!(StatusCode::NOT_FOUND, purge.status());
+    }
+
+    #[tokio::test]
+    async fn pin_crate() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "PUT",
+                "/proxy/cache/crates-io/serde/pin",
+                r#"{"pinned": true}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+    }
+
+    #[tokio::test]
+    async fn refresh_while_offline_returns_service_unavailable() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "POST",
+                "/proxy/cache/crates-io/serde/refresh",
+                "",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, r.status());
+    }
+
+    #[tokio::test]
+    async fn warm_while_offline_returns_service_unavailable() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "POST",
+                "/proxy/cache/crates-io/warm",
+                LOCKFILE,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, r.status());
+    }
+
+    const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "my-app"
+version = "0.1.0"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "abc"
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+source = "sparse+https://index.my-registry.example/"
+
+[[package]]
+name = "git-dep"
+version = "0.1.0"
+source = "git+https://github.com/example/git-dep#abc"
+"#;
+
+    #[test]
+    fn locked_packages_of_cratesio() {
+        let packages =
+            locked_packages(LOCKFILE, CRATESIO_UPSTREAM, "https://index.crates.io/").unwrap();
+
+        assert_eq!(1, packages.len());
+        assert_eq!("serde", packages[0].name);
+        assert_eq!(Some("abc".to_string()), packages[0].checksum);
+    }
+
+    #[test]
+    fn locked_packages_of_upstream() {
+        let packages =
+            locked_packages(LOCKFILE, "my-registry", "https://index.my-registry.example").unwrap();
+
+        assert_eq!(1, packages.len());
+        assert_eq!("rand", packages[0].name);
+    }
+
+    #[test]
+    fn invalid_lockfile_is_rejected() {
+        assert!(locked_packages("[[package]]\nname = 1", CRATESIO_UPSTREAM, "").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::error::RouteError;
+use crate::session::MaybeUser;
+use appstate::ProxyModeState;
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProxyModeStatus {
+    pub offline: bool,
+}
+
+pub async fn status(
+    user: MaybeUser,
+    State(proxy_mode): ProxyModeState,
+) -> Result<Json<ProxyModeStatus>, RouteError> {
+    user.assert_admin()?;
+
+    Ok(Json(ProxyModeStatus {
+        offline: proxy_mode.is_offline(),
+    }))
+}
+
+/// Switches the proxy between online and offline mode. The mode is not persisted
+/// and falls back to the `proxy.offline` setting on restart.
+pub async fn set(
+    user: MaybeUser,
+    State(proxy_mode): ProxyModeState,
+    Json(status): Json<ProxyModeStatus>,
+) -> Result<Json<ProxyModeStatus>, RouteError> {
+    user.assert_admin()?;
+
+    proxy_mode.set_offline(status.offline);
+    info!(
+        "Proxy switched to {} mode by {}",
+        if status.offline { "offline" } else { "online" },
+        user.name()
+    );
+
+    Ok(Json(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helper::{session_db, session_request, session_state};
+    use appstate::{AppStateData, ProxyMode};
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use hyper::Request;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn app(is_admin: bool, proxy_mode: Arc<ProxyMode>) -> Router {
+        Router::new()
+            .route("/proxy/mode", get(status).put(set))
+            .with_state(AppStateData {
+                proxy_mode,
+                ..session_state(session_db(is_admin)).await
+            })
+    }
+
+    fn request(method: &str, body: &str) -> Request<Body> {
+        session_request(method, "/proxy/mode", body)
+    }
+
+    #[tokio::test]
+    async fn set_requires_admin() {
+        let proxy_mode = Arc::new(ProxyMode::new(false));
+
+        let r = app(false, proxy_mode.clone())
+            .await
+            .oneshot(request("PUT", r#"{"offline": true}"#))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, r.status());
+        assert!(!proxy_mode.is_offline());
+    }
+
+    #[tokio::test]
+    async fn set_switches_to_offline() {
+        let proxy_mode = Arc::new(ProxyMode::new(false));
+
+        let r = app(true, proxy_mode.clone())
+            .await
+            .oneshot(request("PUT", r#"{"offline": true}"#))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        assert!(proxy_mode.is_offline());
+    }
+}
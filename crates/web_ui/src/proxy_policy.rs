@@ -0,0 +1,244 @@
+use crate::error::RouteError;
+use crate::session::MaybeUser;
+use appstate::{DbState, ProxyPoliciesState};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Utc;
+use common::proxy_policy::{PolicyAction, ProxyRule};
+use db::error::DbError;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewProxyRule {
+    pub pattern: String,
+    #[serde(default = "default_version_req")]
+    pub version_req: String,
+    pub action: PolicyAction,
+}
+
+fn default_version_req() -> String {
+    String::from("*")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProxyRuleUpdate {
+    pub action: PolicyAction,
+}
+
+pub async fn list_rules(
+    user: MaybeUser,
+    State(db): DbState,
+) -> Result<Json<Vec<ProxyRule>>, RouteError> {
+    user.assert_admin()?;
+
+    Ok(Json(db.get_proxy_rules().await?))
+}
+
+pub async fn add_rule(
+    user: MaybeUser,
+    State(db): DbState,
+    State(policies): ProxyPoliciesState,
+    Json(rule): Json<NewProxyRule>,
+) -> Result<Json<ProxyRule>, RouteError> {
+    user.assert_admin()?;
+
+    let pattern = rule.pattern.to_lowercase();
+    let version_req = rule.version_req.trim();
+    ProxyRule::validate(&pattern, version_req)
+        .map_err(|_| RouteError::Status(StatusCode::BAD_REQUEST))?;
+
+    let rule = db
+        .add_proxy_rule(&pattern, version_req, rule.action, &Utc::now())
+        .await
+        .map_err(rule_error)?;
+    policies.invalidate();
+    info!(
+        "Proxy rule {} {} set to {} by {}",
+        rule.pattern,
+        rule.version_req,
+        rule.action,
+        user.name()
+    );
+
+    Ok(Json(rule))
+}
+
+/// Changes the action of a rule, e.g. to approve a pending crate.
+pub async fn update_rule(
+    user: MaybeUser,
+    Path(id): Path<i64>,
+    State(db): DbState,
+    State(policies): ProxyPoliciesState,
+    Json(update): Json<ProxyRuleUpdate>,
+) -> Result<(), RouteError> {
+    user.assert_admin()?;
+
+    db.update_proxy_rule_action(id, update.action)
+        .await
+        .map_err(rule_error)?;
+    policies.invalidate();
+    info!(
+        "Proxy rule {} set to {} by {}",
+        id,
+        update.action,
+        user.name()
+    );
+
+    Ok(())
+}
+
+pub async fn delete_rule(
+    user: MaybeUser,
+    Path(id): Path<i64>,
+    State(db): DbState,
+    State(policies): ProxyPoliciesState,
+) -> Result<(), RouteError> {
+    user.assert_admin()?;
+
+    db.delete_proxy_rule(id).await.map_err(rule_error)?;
+    policies.invalidate();
+    info!("Proxy rule {} deleted by {}", id, user.name());
+
+    Ok(())
+}
+
+fn rule_error(e: DbError) -> RouteError {
+    match e {
+        DbError::ProxyRuleNotFound(_) => RouteError::Status(StatusCode::NOT_FOUND),
+        DbError::ProxyRuleExists(_, _) => RouteError::Status(StatusCode::CONFLICT),
+        e => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helper::{session_db, session_request, session_state};
+    use appstate::{AppStateData, ProxyPolicies};
+    use axum::routing::{get, put};
+    use axum::Router;
+    use db::mock::MockDb;
+    use db::DbProvider;
+    use http_body_util::BodyExt;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn app(is_admin: bool) -> Router {
+        app_with_policies(is_admin, Arc::new(ProxyPolicies::new())).await
+    }
+
+    async fn app_with_policies(is_admin: bool, proxy_policies: Arc<ProxyPolicies>) -> Router {
+        let mut mock_db = session_db(is_admin);
+        mock_db
+            .expect_add_proxy_rule()
+            .returning(|pattern, version_req, action, _| {
+                Ok(ProxyRule {
+                    id: 1,
+                    pattern: pattern.to_string(),
+                    version_req: version_req.to_string(),
+                    action,
+                    created: String::new(),
+                })
+            });
+        mock_db
+            .expect_update_proxy_rule_action()
+            .returning(|id, _| Err(DbError::ProxyRuleNotFound(id)));
+
+        Router::new()
+            .route("/proxy/rules", get(list_rules).post(add_rule))
+            .route("/proxy/rules/:id", put(update_rule).delete(delete_rule))
+            .with_state(AppStateData {
+                proxy_policies,
+                ..session_state(mock_db).await
+            })
+    }
+
+    #[tokio::test]
+    async fn list_rules_requires_admin() {
+        let r = app(false)
+            .await
+            .oneshot(session_request("GET", "/proxy/rules", ""))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, r.status());
+    }
+
+    #[tokio::test]
+    async fn add_rule_returns_rule() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "POST",
+                "/proxy/rules",
+                r#"{"pattern": "Tokio-*", "action": "deny"}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let rule = serde_json::from_slice::<ProxyRule>(&body).unwrap();
+        assert_eq!("tokio-*", rule.pattern);
+        assert_eq!("*", rule.version_req);
+        assert_eq!(PolicyAction::Deny, rule.action);
+    }
+
+    #[tokio::test]
+    async fn add_rule_invalidates_cached_policy() {
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_get_proxy_rules()
+            .times(2)
+            .returning(|| Ok(Vec::new()));
+        let db: Arc<dyn DbProvider> = Arc::new(mock_db);
+        let policies = Arc::new(ProxyPolicies::new());
+        policies.get(&db, false).await.unwrap();
+
+        let r = app_with_policies(true, policies.clone())
+            .await
+            .oneshot(session_request(
+                "POST",
+                "/proxy/rules",
+                r#"{"pattern": "tokio", "action": "deny"}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        // Loads the rules again, as the cached policy was dropped.
+        policies.get(&db, false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn add_rule_with_invalid_version_req() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "POST",
+                "/proxy/rules",
+                r#"{"pattern": "tokio", "version_req": "latest", "action": "allow"}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::BAD_REQUEST, r.status());
+    }
+
+    #[tokio::test]
+    async fn update_unknown_rule_returns_not_found() {
+        let r = app(true)
+            .await
+            .oneshot(session_request(
+                "PUT",
+                "/proxy/rules/42",
+                r#"{"action": "allow"}"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, r.status());
+    }
+}
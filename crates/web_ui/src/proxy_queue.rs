@@ -0,0 +1,80 @@
+use crate::error::RouteError;
+use crate::session::MaybeUser;
+use appstate::{PrefetchQueueState, PrefetchQueueStatus, UpstreamsState};
+use axum::extract::State;
+use axum::Json;
+use settings::proxy::CRATESIO_UPSTREAM;
+use std::collections::BTreeMap;
+
+/// Status of the index update queues of crates.io and the other upstreams by upstream name.
+pub async fn status(
+    user: MaybeUser,
+    State(queue): PrefetchQueueState,
+    State(upstreams): UpstreamsState,
+) -> Result<Json<BTreeMap<String, PrefetchQueueStatus>>, RouteError> {
+    user.assert_admin()?;
+
+    let mut status = BTreeMap::from([(CRATESIO_UPSTREAM.to_string(), queue.status())]);
+    for (name, upstream) in upstreams.iter() {
+        status.insert(name.clone(), upstream.prefetch_queue.status());
+    }
+
+    Ok(Json(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helper::{session_db, session_request, session_state};
+    use appstate::{AppStateData, PrefetchQueue};
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use common::cratesio_prefetch_msg::{CratesioPrefetchMsg, UpdateData};
+    use common::original_name::OriginalName;
+    use http_body_util::BodyExt;
+    use hyper::Request;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn app(is_admin: bool) -> Router {
+        let queue = PrefetchQueue::new(10);
+        queue.push(CratesioPrefetchMsg::Update(UpdateData {
+            name: OriginalName::from_unchecked_str("serde".to_string()),
+            etag: None,
+            last_modified: None,
+        }));
+
+        Router::new()
+            .route("/proxy/queue", get(status))
+            .with_state(AppStateData {
+                cratesio_prefetch_queue: Arc::new(queue),
+                ..session_state(session_db(is_admin)).await
+            })
+    }
+
+    fn request() -> Request<Body> {
+        session_request("GET", "/proxy/queue", "")
+    }
+
+    #[tokio::test]
+    async fn status_requires_admin() {
+        let r = app(false).await.oneshot(request()).await.unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, r.status());
+    }
+
+    #[tokio::test]
+    async fn status_returns_queue_depth() {
+        let r = app(true).await.oneshot(request()).await.unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let status =
+            serde_json::from_slice::<BTreeMap<String, PrefetchQueueStatus>>(&body).unwrap();
+        assert_eq!(1, status[CRATESIO_UPSTREAM].depth);
+        assert_eq!(10, status[CRATESIO_UPSTREAM].capacity);
+        assert_eq!(0, status[CRATESIO_UPSTREAM].in_flight);
+    }
+}
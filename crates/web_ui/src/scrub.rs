@@ -0,0 +1,137 @@
+use crate::error::RouteError;
+use crate::session::MaybeUser;
+use appstate::{AppState, ScrubberState};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use registry::scrub::spawn_scrub;
+use serde::{Deserialize, Serialize};
+use storage::scrub::ScrubReport;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScrubStatus {
+    pub running: bool,
+    pub last_report: Option<ScrubReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScrubParams {
+    /// Quarantine corrupted crates.io proxy files, such that they are downloaded again.
+    #[serde(default = "default_quarantine")]
+    quarantine: bool,
+}
+
+fn default_quarantine() -> bool {
+    true
+}
+
+pub async fn status(
+    user: MaybeUser,
+    State(scrubber): ScrubberState,
+) -> Result<Json<ScrubStatus>, RouteError> {
+    user.assert_admin()?;
+
+    Ok(Json(ScrubStatus {
+        running: scrubber.is_running(),
+        last_report: scrubber.last_report(),
+    }))
+}
+
+pub async fn start(
+    user: MaybeUser,
+    Query(params): Query<ScrubParams>,
+    State(state): AppState,
+) -> Result<StatusCode, RouteError> {
+    user.assert_admin()?;
+
+    let started = spawn_scrub(
+        state.db,
+        state.crate_storage,
+        state.cratesio_storage,
+        state.scrubber,
+        params.quarantine,
+    );
+
+    match started {
+        true => Ok(StatusCode::ACCEPTED),
+        false => Err(RouteError::Status(StatusCode::CONFLICT)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helper::{session_db, session_request, session_state};
+    use appstate::AppStateData;
+    use axum::body::Body;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use hyper::Request;
+    use std::sync::Arc;
+    use storage::scrub::Scrubber;
+    use tower::ServiceExt;
+
+    async fn app(is_admin: bool, scrubber: Arc<Scrubber>) -> Router {
+        Router::new()
+            .route("/scrub", get(status))
+            .route("/scrub", post(start))
+            .with_state(AppStateData {
+                scrubber,
+                ..session_state(session_db(is_admin)).await
+            })
+    }
+
+    fn request(method: &str) -> Request<Body> {
+        session_request(method, "/scrub", "")
+    }
+
+    #[tokio::test]
+    async fn status_requires_admin() {
+        let r = app(false, Arc::new(Scrubber::new()))
+            .await
+            .oneshot(request("GET"))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, r.status());
+    }
+
+    #[tokio::test]
+    async fn status_returns_last_report() {
+        let scrubber = Arc::new(Scrubber::new());
+        scrubber.try_start();
+        scrubber.finish(ScrubReport::default());
+
+        let r = app(true, scrubber)
+            .await
+            .oneshot(request("GET"))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let status = serde_json::from_slice::<ScrubStatus>(&body).unwrap();
+        assert_eq!(
+            ScrubStatus {
+                running: false,
+                last_report: Some(ScrubReport::default()),
+            },
+            status
+        );
+    }
+
+    #[tokio::test]
+    async fn start_while_running_returns_conflict() {
+        let scrubber = Arc::new(Scrubber::new());
+        scrubber.try_start();
+
+        let r = app(true, scrubber)
+            .await
+            .oneshot(request("POST"))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::CONFLICT, r.status());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::error::RouteError;
+use crate::session::MaybeUser;
+use appstate::{DbState, SettingsState};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RebuildReport {
+    /// Number of crates in the rebuilt index.
+    pub indexed: u64,
+}
+
+/// Recreates the full-text search index from the database, e.g. after it was corrupted
+/// or an update of the index failed.
+pub async fn rebuild(
+    user: MaybeUser,
+    State(db): DbState,
+    State(settings): SettingsState,
+) -> Result<Json<RebuildReport>, RouteError> {
+    user.assert_admin()?;
+    if !settings.registry.full_text_search {
+        return Err(RouteError::Status(StatusCode::NOT_FOUND));
+    }
+
+    let indexed = db.rebuild_search_index().await?;
+    info!(
+        "Full-text search index rebuilt with {} crates by {}",
+        indexed,
+        user.name()
+    );
+
+    Ok(Json(RebuildReport { indexed }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helper::{session_db, session_request, session_state};
+    use appstate::AppStateData;
+    use axum::body::Body;
+    use axum::routing::post;
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use hyper::Request;
+    use settings::Settings;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn app(is_admin: bool, full_text_search: bool) -> Router {
+        let mut mock_db = session_db(is_admin);
+        mock_db.expect_rebuild_search_index().returning(|| Ok(3));
+        let mut settings = Settings::default();
+        settings.registry.full_text_search = full_text_search;
+
+        Router::new()
+            .route("/search_index/rebuild", post(rebuild))
+            .with_state(AppStateData {
+                settings: Arc::new(settings),
+                ..session_state(mock_db).await
+            })
+    }
+
+    fn request() -> Request<Body> {
+        session_request("POST", "/search_index/rebuild", "")
+    }
+
+    #[tokio::test]
+    async fn rebuild_requires_admin() {
+        let r = app(false, true).await.oneshot(request()).await.unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, r.status());
+    }
+
+    #[tokio::test]
+    async fn rebuild_requires_full_text_search() {
+        let r = app(true, false).await.oneshot(request()).await.unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, r.status());
+    }
+
+    #[tokio::test]
+    async fn rebuild_returns_number_of_indexed_crates() {
+        let r = app(true, true).await.oneshot(request()).await.unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let report = serde_json::from_slice::<RebuildReport>(&body).unwrap();
+        assert_eq!(RebuildReport { indexed: 3 }, report);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::error::RouteError;
+use crate::session::MaybeUser;
+use appstate::DbState;
+use axum::extract::{Query, State};
+use axum::Json;
+use db::SecurityEvent;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SecurityEventParams {
+    #[serde(default = "default_limit")]
+    limit: u64,
+}
+
+fn default_limit() -> u64 {
+    100
+}
+
+/// Lists the latest security events of all proxied registries, newest first.
+pub async fn list(
+    user: MaybeUser,
+    Query(params): Query<SecurityEventParams>,
+    State(db): DbState,
+) -> Result<Json<Vec<SecurityEvent>>, RouteError> {
+    user.assert_admin()?;
+
+    Ok(Json(db.get_security_events(params.limit).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helper::{session_db, session_request, session_state};
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use db::SecurityEventKind;
+    use http_body_util::BodyExt;
+    use hyper::Request;
+    use mockall::predicate::eq;
+    use tower::ServiceExt;
+
+    fn event() -> SecurityEvent {
+        SecurityEvent {
+            id: 1,
+            kind: SecurityEventKind::ChecksumMismatch,
+            upstream: "crates-io".to_string(),
+            crate_name: "mycrate".to_string(),
+            version: "1.0.0".to_string(),
+            detail: "Expected checksum a, got b".to_string(),
+            created: "2024-01-01 00:00:00".to_string(),
+        }
+    }
+
+    async fn app(is_admin: bool) -> Router {
+        let mut mock_db = session_db(is_admin);
+        mock_db
+            .expect_get_security_events()
+            .with(eq(5))
+            .returning(|_| Ok(vec![event()]));
+
+        Router::new()
+            .route("/security_events", get(list))
+            .with_state(session_state(mock_db).await)
+    }
+
+    fn request() -> Request<Body> {
+        session_request("GET", "/security_events?limit=5", "")
+    }
+
+    #[tokio::test]
+    async fn list_requires_admin() {
+        let r = app(false).await.oneshot(request()).await.unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, r.status());
+    }
+
+    #[tokio::test]
+    async fn list_returns_events() {
+        let r = app(true).await.oneshot(request()).await.unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let events = serde_json::from_slice::<Vec<SecurityEvent>>(&body).unwrap();
+        assert_eq!(vec![event()], events);
+    }
+}
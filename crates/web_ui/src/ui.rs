@@ -1,13 +1,15 @@
 use crate::error::RouteError;
 use crate::session::MaybeUser;
-use appstate::{AppState, DbState, SettingsState};
+use appstate::{AppState, DbState, HttpClientState, ProxyModeState, SettingsState};
 use axum::{
     extract::{Query, State},
     http::StatusCode,
+    response::{IntoResponse, Response},
     Json,
 };
 use common::crate_data::CrateData;
-use common::crate_overview::CrateOverview;
+use common::crate_overview::{CrateOverview, CrateSearchHit};
+use common::cratesio_metadata::CratesIoCrate;
 use common::normalized_name::NormalizedName;
 use common::original_name::OriginalName;
 use common::version::Version;
@@ -88,21 +90,72 @@ pub async fn crates(Query(params): Query<CratesParams>, State(db): DbState) -> J
     })
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Ranked search in the names, descriptions, keywords and categories.
+    #[default]
+    Name,
+    /// Search in the full-text index, which includes the readmes.
+    FullText,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct SearchParams {
-    name: OriginalName,
+    name: String,
+    page: Option<usize>,
+    page_size: Option<usize>,
+    #[serde(default)]
+    mode: SearchMode,
 }
 
-pub async fn search(Query(params): Query<SearchParams>, State(db): DbState) -> Json<Pagination> {
-    let crates = db
-        .search_in_crate_name(&params.name)
-        .await
-        .unwrap_or_default();
-    Json(Pagination {
-        current_num: crates.len(),
-        total_num: crates.len(),
-        crates,
-    })
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SearchHitPagination {
+    crates: Vec<CrateSearchHit>,
+    current_num: usize,
+    total_num: usize,
+}
+
+pub async fn search(
+    Query(params): Query<SearchParams>,
+    State(db): DbState,
+    State(settings): SettingsState,
+) -> Result<Response, RouteError> {
+    // Without a page, all matching crates are returned.
+    let (offset, limit) = match params.page {
+        Some(p) => {
+            let page_size = params.page_size.unwrap_or(10);
+            (p.saturating_mul(page_size) as u64, page_size as u64)
+        }
+        None => (0, u64::MAX),
+    };
+
+    match params.mode {
+        SearchMode::Name => {
+            let page = db
+                .search_crates(&params.name, offset, limit)
+                .await
+                .unwrap_or_default();
+            Ok(Json(Pagination {
+                current_num: offset as usize + page.crates.len(),
+                total_num: page.total as usize,
+                crates: page.crates,
+            })
+            .into_response())
+        }
+        SearchMode::FullText => {
+            if !settings.registry.full_text_search {
+                return Err(RouteError::Status(StatusCode::NOT_FOUND));
+            }
+            let page = db.search_full_text(&params.name, offset, limit).await?;
+            Ok(Json(SearchHitPagination {
+                current_num: offset as usize + page.hits.len(),
+                total_num: page.total as usize,
+                crates: page.hits,
+            })
+            .into_response())
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -129,14 +182,35 @@ pub struct CratesIoDataParams {
     name: OriginalName,
 }
 
-pub async fn cratesio_data(Query(params): Query<CratesIoDataParams>) -> Result<String, StatusCode> {
-    let url = format!("https://crates.io/api/v1/crates/{}", params.name);
+pub async fn cratesio_data(
+    Query(params): Query<CratesIoDataParams>,
+    State(settings): SettingsState,
+    State(proxy_mode): ProxyModeState,
+    State(db): DbState,
+    State(client): HttpClientState,
+) -> Result<String, StatusCode> {
+    // The metadata of proxied crates is fetched in the background, such that
+    // the upstream API is only called for crates that are not cached.
+    match db.get_cratesio_metadata(&params.name.to_normalized()).await {
+        Ok(Some((metadata, _))) => {
+            return serde_json::to_string(&CratesIoCrate { krate: metadata }).map_err(|e| {
+                error!("Failed to serialize crates.io data: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            });
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to get cached crates.io data: {}", e),
+    }
+
+    if proxy_mode.is_offline() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    let url = settings
+        .proxy
+        .api_endpoint(&format!("/api/v1/crates/{}", params.name));
 
-    let client = reqwest::Client::new();
-    let req = client
-        .get(&url)
-        .header("User-Agent", "kellnr")
-        .header("Accept", "application/json");
+    let req = client.get(&url).header("Accept", "application/json");
     let resp = req.send().await;
 
     match resp {
@@ -323,7 +397,10 @@ mod tests {
     use axum::routing::{get, post};
     use axum::Router;
     use axum_extra::extract::cookie::Key;
+    use chrono::Utc;
     use common::crate_data::{CrateRegistryDep, CrateVersionData};
+    use common::crate_overview::{CrateOverviewPage, CrateSearchHitPage};
+    use common::cratesio_metadata::CratesIoMetadata;
     use db::error::DbError;
     use db::mock::MockDb;
     use db::User;
@@ -890,9 +967,9 @@ mod tests {
         let settings = test_settings();
 
         mock_db
-            .expect_search_in_crate_name()
-            .with(eq("doesnotexist"))
-            .returning(move |_name| Ok(vec![]));
+            .expect_search_crates()
+            .with(eq("doesnotexist"), eq(0), eq(u64::MAX))
+            .returning(move |_, _, _| Ok(CrateOverviewPage::default()));
 
         let r = app(
             mock_db,
@@ -933,9 +1010,14 @@ mod tests {
 
         let tc = test_crate_summary.clone();
         mock_db
-            .expect_search_in_crate_name()
-            .with(eq("hello"))
-            .returning(move |_| Ok(vec![tc.clone()]));
+            .expect_search_crates()
+            .with(eq("hello"), eq(0), eq(u64::MAX))
+            .returning(move |_, _, _| {
+                Ok(CrateOverviewPage {
+                    crates: vec![tc.clone()],
+                    total: 1,
+                })
+            });
 
         let r = app(
             mock_db,
@@ -962,6 +1044,76 @@ mod tests {
         assert_eq!(test_crate_summary, result_crates.crates[0]);
     }
 
+    #[tokio::test]
+    async fn search_full_text_returns_hits_with_snippets() {
+        let mut mock_db = MockDb::new();
+        let mut settings = test_settings();
+        settings.registry.full_text_search = true;
+
+        let hit = CrateSearchHit {
+            overview: CrateOverview {
+                original_name: "hello".to_string(),
+                max_version: "1.0.0".to_string(),
+                ..Default::default()
+            },
+            snippet: "Says <b>hello</b>".to_string(),
+        };
+        let h = hit.clone();
+        mock_db
+            .expect_search_full_text()
+            .with(eq("hello"), eq(10), eq(10))
+            .returning(move |_, _, _| {
+                Ok(CrateSearchHitPage {
+                    hits: vec![h.clone()],
+                    total: 11,
+                })
+            });
+
+        let r = app(
+            mock_db,
+            KellnrCrateStorage::new(&settings).await.unwrap(),
+            settings,
+        )
+        .await
+        .oneshot(
+            Request::get("/search?name=hello&mode=fulltext&page=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let result_status = r.status();
+        let result_msg = r.into_body().collect().await.unwrap().to_bytes();
+        let result = serde_json::from_slice::<SearchHitPagination>(&result_msg).unwrap();
+
+        assert_eq!(StatusCode::OK, result_status);
+        assert_eq!(vec![hit], result.crates);
+        assert_eq!(11, result.total_num);
+        assert_eq!(11, result.current_num);
+    }
+
+    #[tokio::test]
+    async fn search_full_text_requires_setting() {
+        let settings = test_settings();
+
+        let r = app(
+            MockDb::new(),
+            KellnrCrateStorage::new(&settings).await.unwrap(),
+            settings,
+        )
+        .await
+        .oneshot(
+            Request::get("/search?name=hello&mode=fulltext")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, r.status());
+    }
+
     #[tokio::test]
     async fn crate_get_crate_information() {
         let mut mock_db = MockDb::new();
@@ -1207,7 +1359,10 @@ mod tests {
 
     #[tokio::test]
     async fn cratesio_data_returns_data() {
-        let mock_db = MockDb::new();
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_get_cratesio_metadata()
+            .returning(|_| Ok(None));
         let settings = test_settings();
         let r = app(
             mock_db,
@@ -1232,7 +1387,10 @@ mod tests {
 
     #[tokio::test]
     async fn cratesio_data_not_found() {
-        let mock_db = MockDb::new();
+        let mut mock_db = MockDb::new();
+        mock_db
+            .expect_get_cratesio_metadata()
+            .returning(|_| Ok(None));
         let settings = test_settings();
         let r = app(
             mock_db,
@@ -1251,6 +1409,42 @@ mod tests {
         assert_eq!(r.status(), StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn cratesio_data_returns_cached_metadata() {
+        let mut mock_db = MockDb::new();
+        mock_db.expect_get_cratesio_metadata().returning(|_| {
+            Ok(Some((
+                CratesIoMetadata {
+                    description: Some("Cached description".to_string()),
+                    ..CratesIoMetadata::default()
+                },
+                Utc::now(),
+            )))
+        });
+        let settings = test_settings();
+        let r = app(
+            mock_db,
+            KellnrCrateStorage::new(&settings).await.unwrap(),
+            settings,
+        )
+        .await
+        .oneshot(
+            Request::get("/cratesio_data?name=quote")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::OK, r.status());
+        let body = r.into_body().collect().await.unwrap().to_bytes();
+        let krate = serde_json::from_slice::<CratesIoCrate>(&body).unwrap();
+        assert_eq!(
+            Some("Cached description".to_string()),
+            krate.krate.description
+        );
+    }
+
     fn test_settings() -> Settings {
         Settings::default()
     }
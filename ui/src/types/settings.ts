@@ -3,6 +3,7 @@ export type Settings = {
     local: Local
     log: Log
     origin: Origin
+    policy: Policy
     postgresql: Postgresql
     proxy: Proxy
     registry: Registry
@@ -30,6 +31,24 @@ export type Origin = {
     protocol: string
 }
 
+export type Policy = {
+    license: string
+    allowed_licenses: string[]
+    repository: string
+    description: string
+    wildcard_dependencies: string
+    overrides: PolicyOverride[]
+}
+
+export type PolicyOverride = {
+    pattern: string
+    license?: string
+    allowed_licenses?: string[]
+    repository?: string
+    description?: string
+    wildcard_dependencies?: string
+}
+
 export type Postgresql = {
     enabled: boolean
     address: string
@@ -41,14 +60,44 @@ export type Postgresql = {
 export type Proxy = {
     enabled: boolean
     num_threads: number
+    max_cache_size: number
+    update_queue_size: number
+    index_ttl_secs: number
+    not_found_ttl_secs: number
+    index_url: string
+    download_url: string
+    api_url: string
+    api_request_interval_ms: number
+    upstreams: Upstream[]
+    approval_required: boolean
+    offline: boolean
+    local_search: boolean
+    unified_index: boolean
+    http_proxy: string
+    no_proxy: string
+    ca_cert: string
+    connect_timeout_secs: number
+    timeout_secs: number
+    user_agent: string
+}
+
+export type Upstream = {
+    name: string
+    index_url: string
+    download_url: string
 }
 
 export type Registry = {
     data_dir: string
     session_age_seconds: number
     cache_size: number
+    cache_size_mb: number
     max_crate_size: number
+    max_unpacked_crate_size: number
     auth_required: boolean
+    name_collision: string
+    reserved_prefixes: string[]
+    full_text_search: boolean
 }
 
 export const emptySettings = {
@@ -70,6 +119,14 @@ export const emptySettings = {
         port: 0,
         protocol: "0"
     },
+    policy: {
+        license: "off",
+        allowed_licenses: [],
+        repository: "off",
+        description: "off",
+        wildcard_dependencies: "off",
+        overrides: [],
+    },
     postgresql: {
         enabled: false,
         address: "",
@@ -79,13 +136,37 @@ export const emptySettings = {
     },
     proxy: {
         enabled: false,
-        num_threads: 0
+        num_threads: 0,
+        max_cache_size: 0,
+        update_queue_size: 0,
+        index_ttl_secs: 0,
+        not_found_ttl_secs: 0,
+        index_url: "",
+        download_url: "",
+        api_url: "",
+        api_request_interval_ms: 0,
+        upstreams: [],
+        approval_required: false,
+        offline: false,
+        local_search: false,
+        unified_index: false,
+        http_proxy: "",
+        no_proxy: "",
+        ca_cert: "",
+        connect_timeout_secs: 0,
+        timeout_secs: 0,
+        user_agent: ""
     },
     registry: {
         data_dir: "",
         session_age_seconds: 0,
         cache_size: 0,
+        cache_size_mb: 0,
         max_crate_size: 0,
+        max_unpacked_crate_size: 0,
         auth_required: false,
+        name_collision: "allow",
+        reserved_prefixes: [],
+        full_text_search: false,
     },
 }